@@ -2,14 +2,32 @@
 // This project is dual-licensed under Apache 2.0 and MIT terms.
 // See LICENSE-APACHE and LICENSE-MIT for details.
 
-use eyre::{Report, WrapErr};
-use influx_db_client::{Client, reqwest::Url};
+use enphase_local::{
+    auth::EnlightenCredentials,
+    digest::{DigestCredentials, DigestUser},
+    tls::TlsVerification,
+};
+use eyre::{Report, WrapErr, bail};
+use influx_common::{
+    influxdb::{ConnectionConfig, Precision},
+    overrides::{apply_env_overrides, deserialize_config, read_secret_files},
+};
+use influx_db_client::reqwest::Url;
 use serde::{Deserialize, Deserializer};
-use std::{fs::read_to_string, time::Duration};
+use std::{
+    env,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use toml::Table;
 
 const DEFAULT_DATABASE: &str = "enphase";
 const DEFAULT_INFLUXDB_URL: &str = "http://localhost:8086";
-const CONFIG_FILENAME: &str = "enphase-influx.toml";
+/// The default path of the config file.
+pub const CONFIG_FILENAME: &str = "enphase-influx.toml";
+/// The prefix of environment variables which override config values.
+const ENV_PREFIX: &str = "ENPHASE_INFLUX";
 
 #[derive(Clone, Debug, Deserialize)]
 #[serde(deny_unknown_fields)]
@@ -19,6 +37,9 @@ pub struct Config {
         rename = "poll_period_seconds"
     )]
     pub poll_period: Duration,
+    /// Whether to tag points with the serial number of the gateway.
+    #[serde(default)]
+    pub tag_gateway_serial: bool,
     #[serde(default)]
     pub influxdb: InfluxDbConfig,
     pub enphase: EnphaseConfig,
@@ -30,17 +51,59 @@ pub fn de_duration_seconds<'de, D: Deserializer<'de>>(d: D) -> Result<Duration,
 }
 
 impl Config {
-    pub fn from_file() -> Result<Config, Report> {
-        Config::read(CONFIG_FILENAME)
+    /// Reads the config from the given file, applying any overrides from environment variables
+    /// and reading secrets from files as described in [`apply_env_overrides`] and
+    /// [`read_secret_files`].
+    pub fn from_file(filename: &Path) -> Result<Config, Report> {
+        Config::read_with_env(filename, env::vars())
     }
 
+    #[cfg(test)]
     fn read(filename: &str) -> Result<Config, Report> {
+        Config::read_with_env(Path::new(filename), [])
+    }
+
+    fn read_with_env(
+        filename: &Path,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Config, Report> {
         let config_file =
-            read_to_string(filename).wrap_err_with(|| format!("Reading {filename}"))?;
-        Ok(toml::from_str(&config_file)?)
+            read_to_string(filename).wrap_err_with(|| format!("Reading {}", filename.display()))?;
+        let mut table = toml::from_str::<Table>(&config_file)
+            .wrap_err_with(|| format!("Parsing {}", filename.display()))?;
+        apply_env_overrides(&mut table, ENV_PREFIX, vars)?;
+        read_secret_files(&mut table)?;
+        let config: Config = deserialize_config(table)
+            .wrap_err_with(|| format!("Parsing {}", filename.display()))?;
+        if config.enphase.token.is_none()
+            && config.enphase.credentials().is_none()
+            && config.enphase.digest_credentials().is_none()
+        {
+            bail!(
+                "Either token, email, password and serial, or digest_user and serial or \
+                 digest_password must be set in the [enphase] section of {}",
+                filename.display()
+            );
+        }
+        if config.enphase.base_url.is_none() && config.enphase.serial.is_none() {
+            bail!(
+                "Either base_url or serial must be set in the [enphase] section of {}",
+                filename.display()
+            );
+        }
+        config
+            .enphase
+            .tls()
+            .wrap_err_with(|| format!("Invalid TLS options in {}", filename.display()))?;
+        Ok(config)
     }
 }
 
+/// Connection details for InfluxDB.
+///
+/// If `bucket` is set then the 2.x API is used, authenticating with `token` if given. This also
+/// works with InfluxDB 3.x. Otherwise the 1.x API is used, with `database` and optionally
+/// `username` and `password`.
 #[derive(Clone, Debug, Deserialize)]
 #[serde(default, deny_unknown_fields)]
 pub struct InfluxDbConfig {
@@ -48,6 +111,10 @@ pub struct InfluxDbConfig {
     pub username: Option<String>,
     pub password: Option<String>,
     pub database: String,
+    pub token: Option<String>,
+    pub org: Option<String>,
+    pub bucket: Option<String>,
+    pub precision: Precision,
 }
 
 impl Default for InfluxDbConfig {
@@ -57,35 +124,364 @@ impl Default for InfluxDbConfig {
             username: None,
             password: None,
             database: DEFAULT_DATABASE.to_owned(),
+            token: None,
+            org: None,
+            bucket: None,
+            precision: Precision::default(),
+        }
+    }
+}
+
+impl InfluxDbConfig {
+    /// Returns the details with which to connect to InfluxDB.
+    pub fn connection(&self) -> ConnectionConfig {
+        ConnectionConfig {
+            url: self.url.clone(),
+            username: self.username.clone(),
+            password: self.password.clone(),
+            database: self.database.clone(),
+            token: self.token.clone(),
+            org: self.org.clone(),
+            bucket: self.bucket.clone(),
+            precision: self.precision,
         }
     }
 }
 
+/// Connection details for the gateway.
+///
+/// Either a `token` or the `email`, `password` and `serial` with which to obtain one from
+/// Enlighten must be given. If `base_url` isn't given then the gateway with the given `serial` is
+/// discovered on the local network.
 #[derive(Clone, Debug, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct EnphaseConfig {
-    pub base_url: Url,
-    pub token: String,
+    pub base_url: Option<Url>,
+    /// A token obtained by hand from the Enphase website.
+    pub token: Option<String>,
+    /// The email address of the Enlighten account with which to obtain a token.
+    pub email: Option<String>,
+    /// The password of the Enlighten account with which to obtain a token.
+    pub password: Option<String>,
+    /// The serial number of the gateway.
+    pub serial: Option<String>,
+    /// The user with which to authenticate to a gateway with firmware older than D7, which uses
+    /// digest authentication rather than tokens.
+    pub digest_user: Option<DigestUser>,
+    /// The password for `digest_user`, if it isn't the default derived from the serial number.
+    pub digest_password: Option<String>,
+    /// The SHA-256 fingerprint of the gateway's certificate, to pin.
+    pub certificate_fingerprint: Option<String>,
+    /// The gateway's certificate in PEM format, to pin.
+    pub certificate: Option<String>,
+    /// CA certificates in PEM format against which to verify the gateway's certificate.
+    pub ca_certificate: Option<String>,
+    /// The file in which to store the fingerprint of the gateway's certificate the first time it
+    /// is seen, to pin it from then on.
+    pub fingerprint_path: Option<PathBuf>,
+    /// Whether to accept any certificate from the gateway.
+    #[serde(default)]
+    pub insecure: bool,
 }
 
-/// Construct a new InfluxDB [`Client`] based on the given configuration options, for the given
-/// database.
-pub fn get_influxdb_client(config: &InfluxDbConfig) -> Result<Client, Report> {
-    let mut influxdb_client = Client::new(config.url.to_owned(), &config.database);
-    if let (Some(username), Some(password)) = (&config.username, &config.password) {
-        influxdb_client = influxdb_client.set_authentication(username, password);
+impl EnphaseConfig {
+    /// Returns the Enlighten credentials with which to obtain a token, if they are all set.
+    pub fn credentials(&self) -> Option<EnlightenCredentials> {
+        Some(EnlightenCredentials {
+            email: self.email.clone()?,
+            password: self.password.clone()?,
+            serial: self.serial.clone()?,
+        })
+    }
+
+    /// Returns the credentials for digest authentication, if `digest_user` is set along with either
+    /// `digest_password` or `serial`.
+    pub fn digest_credentials(&self) -> Option<DigestCredentials> {
+        let user = self.digest_user?;
+        match &self.digest_password {
+            Some(password) => Some(DigestCredentials {
+                username: user.as_str().to_owned(),
+                password: password.clone(),
+            }),
+            None => Some(DigestCredentials::from_serial(
+                user,
+                self.serial.as_deref()?,
+            )),
+        }
+    }
+
+    /// Returns how to verify the gateway's certificate.
+    ///
+    /// If none of the other options are set then the certificate is trusted on first use, and its
+    /// fingerprint is read from `fingerprint_path` if it has already been stored.
+    pub fn tls(&self) -> Result<TlsVerification, Report> {
+        let options = [
+            self.insecure,
+            self.certificate_fingerprint.is_some(),
+            self.certificate.is_some(),
+            self.ca_certificate.is_some(),
+        ];
+        if options.into_iter().filter(|&set| set).count() > 1 {
+            bail!(
+                "Only one of insecure, certificate_fingerprint, certificate and ca_certificate may \
+                 be set"
+            );
+        }
+        if self.insecure {
+            Ok(TlsVerification::Insecure)
+        } else if let Some(fingerprint) = &self.certificate_fingerprint {
+            Ok(TlsVerification::Pinned(fingerprint.parse()?))
+        } else if let Some(certificate) = &self.certificate {
+            Ok(TlsVerification::pinned_certificate_pem(
+                certificate.as_bytes(),
+            )?)
+        } else if let Some(ca_certificate) = &self.ca_certificate {
+            Ok(TlsVerification::ca_certificates_pem(
+                ca_certificate.as_bytes(),
+            )?)
+        } else if let Some(path) = &self.fingerprint_path
+            && path.exists()
+        {
+            let fingerprint = read_to_string(path)
+                .wrap_err_with(|| format!("Reading {}", path.display()))?
+                .parse()
+                .wrap_err_with(|| format!("Parsing {}", path.display()))?;
+            Ok(TlsVerification::Pinned(fingerprint))
+        } else {
+            Ok(TlsVerification::TrustOnFirstUse)
+        }
     }
-    Ok(influxdb_client)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::{
+        env::temp_dir,
+        fs::{remove_file, write},
+        process,
+    };
 
     /// Parsing the example config file should not give any errors.
     #[test]
     fn example_config() {
-        Config::read("enphase-influx.example.toml").unwrap();
+        let config = Config::read("enphase-influx.example.toml").unwrap();
+        assert!(!config.tag_gateway_serial);
+    }
+
+    /// Environment variables should override values from the config file.
+    #[test]
+    fn env_overrides() {
+        let config = Config::read_with_env(
+            Path::new("enphase-influx.example.toml"),
+            [
+                (
+                    "ENPHASE_INFLUX__ENPHASE__TOKEN".to_owned(),
+                    "secret".to_owned(),
+                ),
+                (
+                    "ENPHASE_INFLUX__POLL_PERIOD_SECONDS".to_owned(),
+                    "5".to_owned(),
+                ),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.enphase.token.as_deref(), Some("secret"));
+        assert_eq!(config.poll_period, Duration::from_secs(5));
+    }
+
+    /// Environment variables should be able to set booleans which aren't in the config file.
+    #[test]
+    fn env_overrides_new_keys() {
+        let config = Config::read_with_env(
+            Path::new("enphase-influx.example.toml"),
+            [
+                (
+                    "ENPHASE_INFLUX__TAG_GATEWAY_SERIAL".to_owned(),
+                    "true".to_owned(),
+                ),
+                (
+                    "ENPHASE_INFLUX__ENPHASE__INSECURE".to_owned(),
+                    "true".to_owned(),
+                ),
+            ],
+        )
+        .unwrap();
+        assert!(config.tag_gateway_serial);
+        assert!(config.enphase.insecure);
+    }
+
+    /// Enlighten credentials can be given instead of a token, but one or the other is required.
+    #[test]
+    fn enlighten_credentials() {
+        let config = Config::read_with_env(
+            Path::new("enphase-influx.example.toml"),
+            [
+                (
+                    "ENPHASE_INFLUX__ENPHASE__EMAIL".to_owned(),
+                    "email@address.example".to_owned(),
+                ),
+                (
+                    "ENPHASE_INFLUX__ENPHASE__PASSWORD".to_owned(),
+                    "password".to_owned(),
+                ),
+                (
+                    "ENPHASE_INFLUX__ENPHASE__SERIAL".to_owned(),
+                    "123456789012".to_owned(),
+                ),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            config.enphase.credentials(),
+            Some(EnlightenCredentials {
+                email: "email@address.example".to_owned(),
+                password: "password".to_owned(),
+                serial: "123456789012".to_owned(),
+            })
+        );
+
+        let path = temp_dir().join(format!("enphase-influx-config-{}.toml", process::id()));
+        write(
+            &path,
+            r#"
+            poll_period_seconds = 600
+
+            [enphase]
+            base_url = "https://envoy.local/"
+            email = "email@address.example"
+            "#,
+        )
+        .unwrap();
+        assert!(Config::read_with_env(&path, []).is_err());
+        remove_file(path).unwrap();
+    }
+
+    /// Digest authentication can be used for older firmware, with the password derived from the
+    /// serial number by default.
+    #[test]
+    fn digest_credentials() {
+        let path = temp_dir().join(format!("enphase-influx-digest-{}.toml", process::id()));
+        write(
+            &path,
+            r#"
+            poll_period_seconds = 600
+
+            [enphase]
+            serial = "121234567890"
+            digest_user = "installer"
+            "#,
+        )
+        .unwrap();
+        let config = Config::read_with_env(&path, []).unwrap();
+        assert_eq!(
+            config.enphase.digest_credentials(),
+            Some(DigestCredentials {
+                username: "installer".to_owned(),
+                password: "bBec8b2a".to_owned(),
+            })
+        );
+
+        let config = Config::read_with_env(
+            &path,
+            [(
+                "ENPHASE_INFLUX__ENPHASE__DIGEST_PASSWORD".to_owned(),
+                "secret".to_owned(),
+            )],
+        )
+        .unwrap();
+        assert_eq!(
+            config.enphase.digest_credentials(),
+            Some(DigestCredentials {
+                username: "installer".to_owned(),
+                password: "secret".to_owned(),
+            })
+        );
+
+        write(
+            &path,
+            r#"
+            poll_period_seconds = 600
+
+            [enphase]
+            base_url = "https://envoy.local/"
+            digest_user = "envoy"
+            "#,
+        )
+        .unwrap();
+        assert!(Config::read_with_env(&path, []).is_err());
+        remove_file(path).unwrap();
+    }
+
+    /// The gateway's certificate should be trusted on first use unless other options are given,
+    /// and conflicting options should be rejected.
+    #[test]
+    fn tls_options() {
+        let config = Config::read("enphase-influx.example.toml").unwrap();
+        assert!(matches!(
+            config.enphase.tls().unwrap(),
+            TlsVerification::TrustOnFirstUse
+        ));
+
+        let fingerprint = "AB:".repeat(31) + "AB";
+        let mut enphase = EnphaseConfig {
+            certificate_fingerprint: Some(fingerprint.clone()),
+            ..config.enphase.clone()
+        };
+        assert!(matches!(
+            enphase.tls().unwrap(),
+            TlsVerification::Pinned(pinned) if pinned.to_string() == fingerprint
+        ));
+        enphase.insecure = true;
+        assert!(enphase.tls().is_err());
+        enphase.certificate_fingerprint = None;
+        assert!(matches!(enphase.tls().unwrap(), TlsVerification::Insecure));
+
+        let path = temp_dir().join(format!("enphase-influx-fingerprint-{}", process::id()));
+        write(&path, format!("{fingerprint}\n")).unwrap();
+        let enphase = EnphaseConfig {
+            fingerprint_path: Some(path.clone()),
+            ..config.enphase
+        };
+        assert!(matches!(
+            enphase.tls().unwrap(),
+            TlsVerification::Pinned(pinned) if pinned.to_string() == fingerprint
+        ));
+        remove_file(path).unwrap();
+    }
+
+    /// The gateway can be given by serial number rather than URL, but one or the other is
+    /// required.
+    #[test]
+    fn gateway_serial() {
+        let path = temp_dir().join(format!("enphase-influx-serial-{}.toml", process::id()));
+        write(
+            &path,
+            r#"
+            poll_period_seconds = 600
+
+            [enphase]
+            token = "token"
+            serial = "123456789012"
+            "#,
+        )
+        .unwrap();
+        let config = Config::read_with_env(&path, []).unwrap();
+        assert_eq!(config.enphase.base_url, None);
+        assert_eq!(config.enphase.serial.as_deref(), Some("123456789012"));
+
+        write(
+            &path,
+            r#"
+            poll_period_seconds = 600
+
+            [enphase]
+            token = "token"
+            "#,
+        )
+        .unwrap();
+        assert!(Config::read_with_env(&path, []).is_err());
+        remove_file(path).unwrap();
     }
 
     /// Parsing an empty config file should give an error.
@@ -4,49 +4,231 @@
 
 mod config;
 
-use config::{Config, get_influxdb_client};
+use clap::Parser;
+use config::{CONFIG_FILENAME, Config};
 use enphase_local::{
-    Envoy,
+    Envoy, Error as EnvoyError,
+    auth::EnlightenEndpoints,
+    discovery::{DiscoveryOptions, discover_serial},
     inverters::Inverter,
     production::{Device, DeviceType, MeasurementType, Production},
+    tls::TlsVerification,
+};
+use eyre::{Report, WrapErr, bail, eyre};
+use influx_common::influxdb::InfluxDb;
+use influx_db_client::{Point, reqwest::Url};
+use log::{debug, info, warn};
+use std::{
+    fs::write,
+    path::{Path, PathBuf},
 };
-use eyre::Report;
-use influx_db_client::{Point, Precision};
-use log::{debug, warn};
 use tokio::time::sleep;
 
-const INFLUXDB_PRECISION: Option<Precision> = Some(Precision::Seconds);
+/// A tool to import solar and battery data from the Enphase IQ Gateway local API into InfluxDB.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// The config file to use.
+    #[arg(long, default_value = CONFIG_FILENAME)]
+    config: PathBuf,
+    /// Print the points which would be written to InfluxDB in line protocol, rather than writing
+    /// them.
+    #[arg(long)]
+    dry_run: bool,
+    /// The file to which to write points with `--dry-run`, rather than stdout.
+    #[arg(long, requires = "dry_run")]
+    output: Option<PathBuf>,
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Report> {
     pretty_env_logger::init();
 
-    let config = Config::from_file()?;
-    let influxdb_client = get_influxdb_client(&config.influxdb)?;
-    let envoy = Envoy::new(config.enphase.base_url, &config.enphase.token);
+    let args = Args::parse();
+    let config = Config::from_file(&args.config)?;
+    let mut influxdb = InfluxDb::new(&config.influxdb.connection())?;
+    if args.dry_run {
+        influxdb = influxdb.dry_run(args.output.as_deref())?;
+    }
+    let tls = config.enphase.tls()?;
+    let base_url = match (&config.enphase.base_url, &config.enphase.serial) {
+        (Some(base_url), _) => base_url.clone(),
+        (None, Some(serial)) => discover_gateway(serial).await?,
+        (None, None) => unreachable!("Config validation should require a base URL or serial"),
+    };
+    let mut envoy = if let Some(token) = &config.enphase.token {
+        Envoy::new_with_tls(base_url, token, &tls)?
+    } else if let Some(credentials) = config.enphase.credentials() {
+        Envoy::login_with_options(base_url, credentials, EnlightenEndpoints::default(), &tls)
+            .await?
+    } else if let Some(credentials) = config.enphase.digest_credentials() {
+        Envoy::new_with_digest(base_url, credentials, &tls)?
+    } else {
+        unreachable!("Config validation should require a token or credentials")
+    };
+    let gateway_serial = match envoy.info().await {
+        Ok(info) => {
+            info!(
+                "Connected to gateway {} (part number {}) with firmware {}",
+                info.device.serial_number, info.device.part_number, info.device.software
+            );
+            Some(info.device.serial_number)
+        }
+        Err(e) => {
+            warn!("Couldn't get gateway info: {e}");
+            config.enphase.serial.clone()
+        }
+    };
+    // Adding a tag to existing series would split them, so this is opt-in.
+    let gateway_serial = match (config.tag_gateway_serial, gateway_serial) {
+        (false, _) => None,
+        (true, Some(gateway_serial)) => Some(gateway_serial),
+        (true, None) => bail!(
+            "Couldn't get the gateway's serial number to tag points with, set serial in the \
+             [enphase] section"
+        ),
+    };
 
+    poll(
+        &config,
+        &mut envoy,
+        &influxdb,
+        &tls,
+        gateway_serial.as_deref(),
+        args.dry_run,
+    )
+    .await
+}
+
+/// Polls the gateway every `poll_period` and writes its readings to InfluxDB, until an error
+/// occurs which isn't temporary.
+///
+/// If `once` is set then the gateway is only polled once, so that `--dry-run` exits after printing
+/// a single set of points.
+async fn poll(
+    config: &Config,
+    envoy: &mut Envoy,
+    influxdb: &InfluxDb,
+    tls: &TlsVerification,
+    gateway_serial: Option<&str>,
+    once: bool,
+) -> Result<(), Report> {
     let mut last_inverters = Vec::new();
+    let mut poll_inverters = true;
     loop {
-        let production = envoy.production().await?;
-        let points = production_to_points(&production);
-        influxdb_client
-            .write_points(points, INFLUXDB_PRECISION, None)
-            .await?;
+        match envoy.production().await {
+            Ok(production) => {
+                if matches!(tls, TlsVerification::TrustOnFirstUse)
+                    && let Some(path) = &config.enphase.fingerprint_path
+                {
+                    store_fingerprint(envoy, path)?;
+                }
+                let points = tag_gateway(production_to_points(&production), gateway_serial);
+                influxdb.write_points(points).await?;
+            }
+            Err(e) => {
+                let transport = matches!(e, EnvoyError::Transport { .. });
+                handle_envoy_error(e)?;
+                // The gateway's address may have changed, so look for it again.
+                if transport
+                    && config.enphase.base_url.is_none()
+                    && let Some(serial) = &config.enphase.serial
+                {
+                    match discover_gateway(serial).await {
+                        Ok(base_url) => envoy.set_base_url(base_url),
+                        Err(e) => warn!("{e}"),
+                    }
+                }
+            }
+        }
 
-        let inverters = envoy.inverters().await?;
-        let points = inverters_to_points(&inverters, &last_inverters);
-        if !points.is_empty() {
-            influxdb_client
-                .write_points(points, INFLUXDB_PRECISION, None)
-                .await?;
+        if poll_inverters {
+            match envoy.inverters().await {
+                Ok(inverters) => {
+                    let points = tag_gateway(
+                        inverters_to_points(&inverters, &last_inverters),
+                        gateway_serial,
+                    );
+                    if !points.is_empty() {
+                        influxdb.write_points(points).await?;
+                    }
+                    last_inverters = inverters;
+                }
+                Err(EnvoyError::NotSupported { path }) => {
+                    warn!("Gateway doesn't support {path}, not importing per-inverter data");
+                    poll_inverters = false;
+                }
+                Err(e) => handle_envoy_error(e)?,
+            }
         }
-        last_inverters = inverters;
 
+        if once {
+            return Ok(());
+        }
         sleep(config.poll_period).await;
     }
 }
 
-fn production_to_points(production: &Production) -> Vec<Point> {
+/// Finds the base URL of the gateway with the given serial number on the local network.
+async fn discover_gateway(serial: &str) -> Result<Url, Report> {
+    let gateway = discover_serial(serial, &DiscoveryOptions::default())
+        .await
+        .wrap_err("Discovering gateway")?
+        .ok_or_else(|| eyre!("Couldn't find gateway with serial {serial} on the local network"))?;
+    let base_url = gateway.base_url();
+    info!(
+        "Found gateway {serial} with firmware {} at {base_url}",
+        gateway.firmware_version.as_deref().unwrap_or("unknown"),
+    );
+    Ok(base_url)
+}
+
+/// Stores the fingerprint of the gateway's certificate in the given file, if it hasn't already
+/// been stored.
+fn store_fingerprint(envoy: &Envoy, path: &Path) -> Result<(), Report> {
+    if let Some(fingerprint) = envoy.certificate_fingerprint()
+        && !path.exists()
+    {
+        info!(
+            "Trusting gateway certificate with fingerprint {fingerprint}, storing in {}",
+            path.display()
+        );
+        write(path, format!("{fingerprint}\n"))
+            .wrap_err_with(|| format!("Writing {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Logs errors from the gateway which may be temporary, so that polling can continue, and returns
+/// any others.
+fn handle_envoy_error(error: EnvoyError) -> Result<(), Report> {
+    match &error {
+        EnvoyError::Transport { .. } => {
+            warn!("{error}, will retry");
+            Ok(())
+        }
+        EnvoyError::Status { status, .. } if status.is_server_error() => {
+            warn!("{error}, will retry");
+            Ok(())
+        }
+        EnvoyError::Unauthorized { .. } => Err(Report::new(error)
+            .wrap_err("Gateway rejected token, check that it is valid and hasn't expired")),
+        _ => Err(error.into()),
+    }
+}
+
+/// Tags the given points with the serial number of the gateway, if it is given.
+fn tag_gateway<'a>(points: Vec<Point<'a>>, gateway_serial: Option<&str>) -> Vec<Point<'a>> {
+    let Some(gateway_serial) = gateway_serial else {
+        return points;
+    };
+    points
+        .into_iter()
+        .map(|point| point.add_tag("gateway_serial", gateway_serial.to_owned()))
+        .collect()
+}
+
+fn production_to_points(production: &Production) -> Vec<Point<'_>> {
     production
         .production
         .iter()
@@ -55,7 +237,7 @@ fn production_to_points(production: &Production) -> Vec<Point> {
         .collect()
 }
 
-fn device_production_to_point(device: &Device) -> Option<Point> {
+fn device_production_to_point(device: &Device) -> Option<Point<'_>> {
     match device.type_ {
         DeviceType::Eim => {
             let Some(measurement_type) = device.measurement_type else {
@@ -70,6 +252,8 @@ fn device_production_to_point(device: &Device) -> Option<Point> {
                 MeasurementType::Production => "Producing",
                 MeasurementType::TotalConsumption => "Consuming",
                 MeasurementType::NetConsumption => "Net      ",
+                MeasurementType::Storage => "Storage  ",
+                MeasurementType::Backfeed => "Backfeed ",
             };
             debug!(
                 "{}: {:9} {:7.3} W, {} Wh so far today, {} Wh total",
@@ -113,6 +297,8 @@ fn tag_for_measurement_type(measurement_type: MeasurementType) -> &'static str {
         MeasurementType::Production => "producing",
         MeasurementType::TotalConsumption => "consuming",
         MeasurementType::NetConsumption => "net",
+        MeasurementType::Storage => "storage",
+        MeasurementType::Backfeed => "backfeed",
     }
 }
 
@@ -133,7 +319,7 @@ fn inverters_to_points<'a>(
         .collect()
 }
 
-fn inverter_to_point(inverter: &Inverter) -> Point {
+fn inverter_to_point(inverter: &Inverter) -> Point<'_> {
     debug!(
         "{} Inverter {} producing {} W (max {} W)",
         inverter.last_report_date,
@@ -153,6 +339,101 @@ mod tests {
     use super::*;
     use chrono::{DateTime, TimeZone, Utc};
     use enphase_local::production::{AcBatteryState, Details};
+    use std::{
+        env::temp_dir,
+        fs::{read_to_string, remove_file},
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        process, thread,
+        time::Duration,
+    };
+    use tokio::time::timeout;
+
+    /// Starts an HTTP server on a local port which responds to production and inverter requests
+    /// like a gateway, and returns its base URL.
+    fn stub_gateway() -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut reader = BufReader::new(stream.unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap() == 0 || line.trim_end().is_empty() {
+                        break;
+                    }
+                }
+                let body = if request_line.contains("/production.json") {
+                    r#"{
+                        "production": [{
+                            "type": "inverters",
+                            "activeCount": 10,
+                            "readingTime": 1704067200,
+                            "wNow": 1500
+                        }],
+                        "consumption": [],
+                        "storage": []
+                    }"#
+                } else {
+                    "[]"
+                };
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nContent-Type: application/json\r\n\
+                     Connection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+        });
+        url
+    }
+
+    /// A dry run should poll the gateway once and then return, rather than polling forever.
+    #[tokio::test]
+    async fn dry_run_polls_once() {
+        let base_url = stub_gateway();
+        let config = toml::from_str::<Config>(&format!(
+            r#"
+            poll_period_seconds = 600
+
+            [enphase]
+            base_url = "{base_url}"
+            token = "token"
+            "#
+        ))
+        .unwrap();
+        let path = temp_dir().join(format!("enphase-influx-dry-run-{}.txt", process::id()));
+        let influxdb = InfluxDb::new(&config.influxdb.connection())
+            .unwrap()
+            .dry_run(Some(&path))
+            .unwrap();
+        let mut envoy = Envoy::new(base_url, "token");
+
+        timeout(
+            Duration::from_secs(10),
+            poll(
+                &config,
+                &mut envoy,
+                &influxdb,
+                &TlsVerification::Insecure,
+                None,
+                true,
+            ),
+        )
+        .await
+        .expect("Dry run didn't return after polling once")
+        .unwrap();
+        drop(influxdb);
+
+        let output = read_to_string(&path).unwrap();
+        remove_file(&path).unwrap();
+        assert_eq!(output, "inverters w_now=1500 1704067200\n");
+    }
 
     #[test]
     fn test_production_to_points() {
@@ -251,6 +532,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_tag_gateway() {
+        let points = vec![Point::new("inverters").add_field("w_now", 123.0)];
+        assert_eq!(tag_gateway(points.clone(), None), points);
+        assert_eq!(
+            tag_gateway(points, Some("123456789012")),
+            vec![
+                Point::new("inverters")
+                    .add_field("w_now", 123.0)
+                    .add_tag("gateway_serial", "123456789012")
+            ]
+        );
+    }
+
     #[test]
     fn test_inverters_to_points() {
         let last_report_date = Utc.with_ymd_and_hms(2025, 1, 1, 10, 30, 0).unwrap();
@@ -278,7 +573,7 @@ mod tests {
             max_report_watts: 600,
         };
         assert_eq!(
-            inverters_to_points(&[inverter1.clone()], &[]),
+            inverters_to_points(std::slice::from_ref(&inverter1), &[]),
             vec![
                 Point::new("inverter")
                     .add_timestamp(last_report_date.timestamp())
@@ -24,9 +24,11 @@ async fn main() -> Result<(), Report> {
     let auth_token = &args[2];
 
     let envoy = Envoy::new(Url::parse(base_url)?, auth_token);
+    println!("Info: {:#?}", envoy.info().await?);
     println!("Home: {:#?}", envoy.home().await?);
     println!("Inventory: {:#?}", envoy.inventory(true).await?);
     println!("Production: {:#?}", envoy.production().await?);
+    println!("IVP meters: {:#?}", envoy.meters().await?);
     println!("IVP meter readings: {:#?}", envoy.meter_readings().await?);
     println!("IVP meter reports: {:#?}", envoy.meter_reports().await?);
     println!("Inverters: {:#?}", envoy.inverters().await?);
@@ -71,6 +73,8 @@ fn print_stats(device: &Device) {
                 MeasurementType::Production => "Producing",
                 MeasurementType::TotalConsumption => "Consuming",
                 MeasurementType::NetConsumption => "Net      ",
+                MeasurementType::Storage => "Storage  ",
+                MeasurementType::Backfeed => "Backfeed ",
             };
             let details = device.details.as_ref().unwrap();
             println!(
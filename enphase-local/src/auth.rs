@@ -0,0 +1,273 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Obtaining authentication tokens for the gateway from Enphase's Enlighten cloud service.
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, TimeDelta, Utc};
+use reqwest::{Client, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// The default URL with which to log in to Enlighten.
+pub const DEFAULT_LOGIN_URL: &str = "https://enlighten.enphaseenergy.com/login/login.json";
+/// The default URL from which to request tokens for a gateway.
+pub const DEFAULT_TOKEN_URL: &str = "https://entrez.enphaseenergy.com/tokens";
+
+/// How long before a token expires to renew it.
+const RENEWAL_MARGIN: TimeDelta = TimeDelta::hours(1);
+
+/// The credentials with which to obtain a token for a gateway.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EnlightenCredentials {
+    /// The email address of the Enlighten account.
+    pub email: String,
+    /// The password of the Enlighten account.
+    pub password: String,
+    /// The serial number of the gateway for which to obtain a token.
+    pub serial: String,
+}
+
+/// The URLs of the Enlighten services used to obtain tokens.
+///
+/// These only need to be changed from the defaults for testing.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EnlightenEndpoints {
+    /// The URL with which to log in, returning a session ID.
+    pub login_url: Url,
+    /// The URL from which to request a token for a gateway, given a session ID.
+    pub token_url: Url,
+}
+
+impl Default for EnlightenEndpoints {
+    fn default() -> Self {
+        Self {
+            login_url: DEFAULT_LOGIN_URL.parse().unwrap(),
+            token_url: DEFAULT_TOKEN_URL.parse().unwrap(),
+        }
+    }
+}
+
+/// An error obtaining or validating a token.
+#[derive(Debug, Error)]
+pub enum AuthError {
+    /// There was an error making an HTTP request.
+    #[error("HTTP request error: {0}")]
+    Http(#[from] reqwest::Error),
+    /// Logging in to Enlighten failed.
+    #[error("Enlighten login failed: {0}")]
+    LoginFailed(String),
+    /// Enlighten didn't issue a token.
+    #[error("Token request failed with {status}: {body}")]
+    TokenRequestFailed { status: StatusCode, body: String },
+    /// The gateway rejected the token.
+    #[error("Gateway rejected token with {status}")]
+    TokenRejected { status: StatusCode },
+}
+
+/// A JWT for authenticating to the gateway.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Token {
+    token: String,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl Token {
+    /// Wraps the given JWT, reading its expiry time from its payload if possible.
+    pub fn new(token: &str) -> Self {
+        Self {
+            token: token.to_owned(),
+            expires_at: jwt_expiry(token),
+        }
+    }
+
+    /// Returns the JWT.
+    pub fn as_str(&self) -> &str {
+        &self.token
+    }
+
+    /// Returns the time at which the token expires, if known.
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.expires_at
+    }
+
+    /// Returns whether the token should be renewed at the given time, because it will soon expire.
+    ///
+    /// Tokens with no known expiry time are never renewed.
+    pub fn needs_renewal(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at - now < RENEWAL_MARGIN)
+    }
+}
+
+/// Reads the `exp` claim from the payload of the given JWT, without verifying its signature.
+fn jwt_expiry(token: &str) -> Option<DateTime<Utc>> {
+    #[derive(Deserialize)]
+    struct Claims {
+        exp: i64,
+    }
+
+    let payload = token.split('.').nth(1)?;
+    let payload = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+    let claims = serde_json::from_slice::<Claims>(&payload).ok()?;
+    DateTime::from_timestamp(claims.exp, 0)
+}
+
+#[derive(Debug, Deserialize)]
+struct LoginResponse {
+    session_id: Option<String>,
+    message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct TokenRequest<'a> {
+    session_id: &'a str,
+    serial_num: &'a str,
+    username: &'a str,
+}
+
+/// Logs in to Enlighten with the given credentials and requests an owner token for their gateway.
+pub async fn fetch_token(
+    client: &Client,
+    credentials: &EnlightenCredentials,
+    endpoints: &EnlightenEndpoints,
+) -> Result<Token, AuthError> {
+    let response = client
+        .post(endpoints.login_url.clone())
+        .form(&[
+            ("user[email]", credentials.email.as_str()),
+            ("user[password]", credentials.password.as_str()),
+        ])
+        .send()
+        .await?;
+    let status = response.status();
+    let body = response.text().await?;
+    let login = serde_json::from_str::<LoginResponse>(&body).ok();
+    let session_id = match login {
+        Some(LoginResponse {
+            session_id: Some(session_id),
+            ..
+        }) if status.is_success() => session_id,
+        Some(LoginResponse {
+            message: Some(message),
+            ..
+        }) => return Err(AuthError::LoginFailed(message)),
+        _ => return Err(AuthError::LoginFailed(format!("{status}: {body}"))),
+    };
+
+    let response = client
+        .post(endpoints.token_url.clone())
+        .json(&TokenRequest {
+            session_id: &session_id,
+            serial_num: &credentials.serial,
+            username: &credentials.email,
+        })
+        .send()
+        .await?;
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        return Err(AuthError::TokenRequestFailed { status, body });
+    }
+    Ok(Token::new(body.trim()))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::test_server::{Response, serve};
+    use std::sync::Mutex;
+
+    /// Returns an unsigned JWT with the given expiry time.
+    pub fn jwt(exp: i64) -> String {
+        format!(
+            "{}.{}.signature",
+            URL_SAFE_NO_PAD.encode(r#"{"kid":"1","typ":"JWT","alg":"ES256"}"#),
+            URL_SAFE_NO_PAD.encode(format!(r#"{{"aud":"123","exp":{exp}}}"#))
+        )
+    }
+
+    pub fn credentials() -> EnlightenCredentials {
+        EnlightenCredentials {
+            email: "email@address.example".to_owned(),
+            password: "password".to_owned(),
+            serial: "123456789012".to_owned(),
+        }
+    }
+
+    /// Starts a stub Enlighten service which issues the given token.
+    pub fn enlighten_stub(token: String) -> EnlightenEndpoints {
+        enlighten_stub_sequence(vec![token])
+    }
+
+    /// Starts a stub Enlighten service which issues the given tokens in turn, repeating the last
+    /// one once the others have been issued.
+    pub fn enlighten_stub_sequence(tokens: Vec<String>) -> EnlightenEndpoints {
+        let tokens = Mutex::new(tokens);
+        let base_url =
+            serve(
+                move |request| match (request.method.as_str(), request.path.as_str()) {
+                    ("POST", "/login/login.json") => {
+                        if request.body.contains("user%5Bpassword%5D=password") {
+                            Response::json(r#"{"message":"success","session_id":"session"}"#)
+                        } else {
+                            Response::new(401, r#"{"message":"Login failed"}"#)
+                        }
+                    }
+                    ("POST", "/tokens") if request.body.contains(r#""session_id":"session""#) => {
+                        let mut tokens = tokens.lock().unwrap();
+                        let token = if tokens.len() > 1 {
+                            tokens.remove(0)
+                        } else {
+                            tokens[0].clone()
+                        };
+                        Response::new(200, &token)
+                    }
+                    _ => Response::new(404, "Not found"),
+                },
+            );
+        EnlightenEndpoints {
+            login_url: base_url.join("login/login.json").unwrap(),
+            token_url: base_url.join("tokens").unwrap(),
+        }
+    }
+
+    #[test]
+    fn expiry() {
+        let token = Token::new(&jwt(1735689600));
+        assert_eq!(
+            token.expires_at(),
+            Some("2025-01-01T00:00:00Z".parse().unwrap())
+        );
+        assert!(!token.needs_renewal("2024-12-31T22:00:00Z".parse().unwrap()));
+        assert!(token.needs_renewal("2024-12-31T23:30:00Z".parse().unwrap()));
+
+        let token = Token::new("not a JWT");
+        assert_eq!(token.expires_at(), None);
+        assert!(!token.needs_renewal("2024-12-31T23:30:00Z".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn fetch() {
+        let token = jwt(1735689600);
+        let endpoints = enlighten_stub(token.clone());
+        let fetched = fetch_token(&Client::new(), &credentials(), &endpoints)
+            .await
+            .unwrap();
+        assert_eq!(fetched.as_str(), token);
+    }
+
+    #[tokio::test]
+    async fn login_failed() {
+        let endpoints = enlighten_stub(jwt(1735689600));
+        let credentials = EnlightenCredentials {
+            password: "wrong".to_owned(),
+            ..credentials()
+        };
+        let error = fetch_token(&Client::new(), &credentials, &endpoints)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, AuthError::LoginFailed(message) if message == "Login failed"));
+    }
+}
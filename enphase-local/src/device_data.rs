@@ -0,0 +1,161 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Types returned by the IVP PDM device data API, available on D8 firmware.
+
+use chrono::{DateTime, Utc, serde::ts_seconds};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// The name given to microinverters in device data.
+pub const INVERTER_DEVICE_NAME: &str = "pcu";
+
+/// Parses the device data response, which is an object mapping device IDs to devices, mixed in
+/// with some counts which are ignored.
+pub(crate) fn parse_devices(response: BTreeMap<String, Value>) -> Vec<PdmDevice> {
+    response
+        .into_values()
+        .filter(Value::is_object)
+        .filter_map(|device| serde_json::from_value(device).ok())
+        .collect()
+}
+
+/// A device reported by the power distribution manager.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdmDevice {
+    /// The kind of device, such as `pcu` for a microinverter.
+    pub dev_name: String,
+    /// The serial number of the device.
+    #[serde(rename = "sn")]
+    pub serial_number: String,
+    /// Whether the device is active.
+    #[serde(default)]
+    pub active: bool,
+    #[serde(default)]
+    pub mod_gone: bool,
+    pub channels: Vec<PdmChannel>,
+}
+
+impl PdmDevice {
+    /// Returns whether the device is a microinverter.
+    pub fn is_inverter(&self) -> bool {
+        self.dev_name == INVERTER_DEVICE_NAME
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdmChannel {
+    /// Gateway record ID number of the channel.
+    pub chan_eid: u64,
+    /// The most recent reading from the channel.
+    pub last_reading: PdmReading,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdmReading {
+    /// Gateway record ID number.
+    pub eid: u64,
+    /// The end of the period which the reading covers.
+    #[serde(with = "ts_seconds")]
+    pub end_date: DateTime<Utc>,
+    /// The length of the period which the reading covers, in seconds.
+    pub duration: u32,
+    /// AC voltage in millivolts.
+    #[serde(rename = "acVoltageINmV")]
+    pub ac_voltage_mv: Option<i64>,
+    /// AC frequency in millihertz.
+    #[serde(rename = "acFrequencyINmHz")]
+    pub ac_frequency_mhz: Option<i64>,
+    /// DC voltage in millivolts.
+    #[serde(rename = "dcVoltageINmV")]
+    pub dc_voltage_mv: Option<i64>,
+    /// DC current in milliamps.
+    #[serde(rename = "dcCurrentINmA")]
+    pub dc_current_ma: Option<i64>,
+    /// AC current in milliamps.
+    #[serde(rename = "acCurrentInmA")]
+    pub ac_current_ma: Option<i64>,
+    /// AC power in milliwatts.
+    #[serde(rename = "acPowerINmW")]
+    pub ac_power_mw: Option<i64>,
+    /// Channel temperature in degrees Celsius.
+    pub channel_temp: Option<f64>,
+    /// Total energy produced in joules.
+    pub joules_produced: Option<f64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_example() {
+        let response = serde_json::from_str(
+            r#"{
+    "1": {
+        "devName": "pcu",
+        "sn": "482212345678",
+        "active": true,
+        "modGone": false,
+        "channels": [
+            {
+                "chanEid": 1627390224,
+                "created": 1701084300,
+                "lastReading": {
+                    "eid": 1627390224,
+                    "interval_type": 0,
+                    "endDate": 1701084300,
+                    "duration": 300,
+                    "acVoltageINmV": 241250,
+                    "acFrequencyINmHz": 50010,
+                    "dcVoltageINmV": 36547,
+                    "dcCurrentINmA": 4770,
+                    "channelTemp": 31,
+                    "pwrConvErrSecs": 0,
+                    "pwrConvMaxErrCycles": 0,
+                    "joulesProduced": 540317760,
+                    "acCurrentInmA": 687,
+                    "acPowerINmW": 165710
+                }
+            }
+        ]
+    },
+    "2": {
+        "devName": "nsrb",
+        "sn": "122212345678",
+        "active": true,
+        "modGone": false,
+        "channels": []
+    },
+    "deviceCount": 2,
+    "deviceDataLimit": 50
+}"#,
+        )
+        .unwrap();
+        let devices = parse_devices(response);
+        assert_eq!(devices.len(), 2);
+        assert!(devices[0].is_inverter());
+        assert!(!devices[1].is_inverter());
+        assert_eq!(
+            devices[0].channels[0].last_reading,
+            PdmReading {
+                eid: 1627390224,
+                end_date: "2023-11-27T11:25:00Z".parse().unwrap(),
+                duration: 300,
+                ac_voltage_mv: Some(241250),
+                ac_frequency_mhz: Some(50010),
+                dc_voltage_mv: Some(36547),
+                dc_current_ma: Some(4770),
+                ac_current_ma: Some(687),
+                ac_power_mw: Some(165710),
+                channel_temp: Some(31.0),
+                joules_produced: Some(540317760.0),
+            }
+        );
+    }
+}
@@ -0,0 +1,390 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! HTTP digest authentication, used by gateways with firmware older than D7.
+
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+/// The realm used to derive passwords from the gateway serial number.
+const PASSWORD_REALM: &str = "enphaseenergy.com";
+
+/// A user for digest authentication to the gateway.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DigestUser {
+    /// The homeowner user, `envoy`.
+    Envoy,
+    /// The installer user, `installer`, which has access to more APIs.
+    Installer,
+}
+
+impl DigestUser {
+    /// Returns the username.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Envoy => "envoy",
+            Self::Installer => "installer",
+        }
+    }
+}
+
+/// The username and password for digest authentication to the gateway.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DigestCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl DigestCredentials {
+    /// Returns the credentials for the given user of the gateway with the given serial number, with
+    /// the default password derived from the serial number.
+    pub fn from_serial(user: DigestUser, serial: &str) -> Self {
+        Self {
+            username: user.as_str().to_owned(),
+            password: derive_password(user, serial),
+        }
+    }
+}
+
+/// Returns the default password for the given user of the gateway with the given serial number.
+///
+/// For the `envoy` user this is the last six characters of the serial number. For the `installer`
+/// user it is derived from an MD5 hash of the serial number.
+pub fn derive_password(user: DigestUser, serial: &str) -> String {
+    match user {
+        DigestUser::Envoy => {
+            let start = serial.char_indices().rev().nth(5).map_or(0, |(i, _)| i);
+            serial[start..].to_owned()
+        }
+        DigestUser::Installer => {
+            let hash = md5_hex(&format!(
+                "[e]{}@{PASSWORD_REALM}#{serial} EnPhAsE eNeRgY ",
+                user.as_str()
+            ));
+            let mut zeroes = hash.chars().filter(|&c| c == '0').count() as u8;
+            let mut ones = hash.chars().filter(|&c| c == '1').count() as u8;
+            hash.chars()
+                .rev()
+                .take(8)
+                .map(|c| {
+                    if matches!(zeroes, 3 | 6 | 9) {
+                        zeroes -= 1;
+                    }
+                    zeroes = zeroes.min(20);
+                    if matches!(ones, 9 | 15) {
+                        ones -= 1;
+                    }
+                    ones = ones.min(26);
+                    match c {
+                        '0' => {
+                            let c = char::from(b'f' + zeroes);
+                            zeroes = zeroes.saturating_sub(1);
+                            c
+                        }
+                        '1' => {
+                            let c = char::from(b'@' + ones);
+                            ones = ones.saturating_sub(1);
+                            c
+                        }
+                        c => c,
+                    }
+                })
+                .collect()
+        }
+    }
+}
+
+fn md5_hex(data: &str) -> String {
+    format!("{:x}", md5::compute(data))
+}
+
+/// A challenge from a `WWW-Authenticate: Digest` header.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Challenge {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    algorithm: Option<String>,
+    /// Whether the server supports the `auth` quality of protection.
+    qop_auth: bool,
+}
+
+impl Challenge {
+    /// Parses a `WWW-Authenticate` header value, returning `None` if it isn't a digest challenge.
+    fn parse(header: &str) -> Option<Self> {
+        let (scheme, parameters) = header.trim().split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("Digest") {
+            return None;
+        }
+        let parameters = parse_parameters(parameters);
+        let get = |name: &str| {
+            parameters
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.clone())
+        };
+        Some(Self {
+            realm: get("realm")?,
+            nonce: get("nonce")?,
+            opaque: get("opaque"),
+            algorithm: get("algorithm"),
+            qop_auth: get("qop").is_some_and(|qop| qop.split(',').any(|qop| qop.trim() == "auth")),
+        })
+    }
+
+    /// Returns the `Authorization` header value for the given request.
+    fn authorization(
+        &self,
+        credentials: &DigestCredentials,
+        method: &str,
+        uri: &str,
+        nc: u32,
+        cnonce: &str,
+    ) -> String {
+        let ha1 = md5_hex(&format!(
+            "{}:{}:{}",
+            credentials.username, self.realm, credentials.password
+        ));
+        let ha2 = md5_hex(&format!("{method}:{uri}"));
+        let nc = format!("{nc:08x}");
+        let mut header = format!(
+            r#"Digest username="{}", realm="{}", nonce="{}", uri="{uri}""#,
+            credentials.username, self.realm, self.nonce
+        );
+        if self.qop_auth {
+            let response = md5_hex(&format!("{ha1}:{}:{nc}:{cnonce}:auth:{ha2}", self.nonce));
+            header += &format!(r#", qop=auth, nc={nc}, cnonce="{cnonce}", response="{response}""#);
+        } else {
+            let response = md5_hex(&format!("{ha1}:{}:{ha2}", self.nonce));
+            header += &format!(r#", response="{response}""#);
+        }
+        if let Some(opaque) = &self.opaque {
+            header += &format!(r#", opaque="{opaque}""#);
+        }
+        if let Some(algorithm) = &self.algorithm {
+            header += &format!(", algorithm={algorithm}");
+        }
+        header
+    }
+}
+
+/// Parses comma-separated `key=value` or `key="value"` parameters.
+fn parse_parameters(parameters: &str) -> Vec<(String, String)> {
+    let mut result = Vec::new();
+    let mut rest = parameters.trim();
+    while let Some((key, after)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_owned();
+        let after = after.trim_start();
+        let (value, remaining) = if let Some(quoted) = after.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+        } else {
+            let end = after.find(',').unwrap_or(after.len());
+            (after[..end].trim(), &after[end..])
+        };
+        result.push((key, value.to_owned()));
+        rest = remaining.trim_start().trim_start_matches(',');
+    }
+    result
+}
+
+/// Digest authentication state for a gateway: the credentials, and the most recent challenge so
+/// that it can be answered without an extra round trip.
+#[derive(Debug)]
+pub(crate) struct DigestSession {
+    credentials: DigestCredentials,
+    state: Mutex<DigestState>,
+}
+
+#[derive(Debug, Default)]
+struct DigestState {
+    challenge: Option<Challenge>,
+    /// The number of requests made with the current nonce.
+    nc: u32,
+}
+
+impl DigestSession {
+    pub fn new(credentials: DigestCredentials) -> Self {
+        Self {
+            credentials,
+            state: Default::default(),
+        }
+    }
+
+    /// Returns the `Authorization` header value for the given request, if a challenge has been
+    /// received.
+    pub fn authorization(&self, method: &str, uri: &str) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        let challenge = state.challenge.clone()?;
+        state.nc += 1;
+        Some(challenge.authorization(&self.credentials, method, uri, state.nc, &cnonce()))
+    }
+
+    /// Stores the challenge from the given `WWW-Authenticate` header value, returning whether it
+    /// was a valid digest challenge.
+    pub fn set_challenge(&self, header: &str) -> bool {
+        let Some(challenge) = Challenge::parse(header) else {
+            return false;
+        };
+        *self.state.lock().unwrap() = DigestState {
+            challenge: Some(challenge),
+            nc: 0,
+        };
+        true
+    }
+}
+
+/// Generates a random client nonce.
+fn cnonce() -> String {
+    let mut bytes = [0; 8];
+    SystemRandom::new().fill(&mut bytes).unwrap();
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Envoy, Error,
+        test_server::{Response, serve},
+        tls::TlsVerification,
+    };
+    use reqwest::Url;
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    const NONCE: &str = "b6a2d1c0e5f4";
+
+    /// Starts a stub gateway which requires digest authentication with the given credentials for
+    /// `ivp/meters/readings`, and counts how many challenges it sends.
+    fn gateway_stub(credentials: DigestCredentials, challenges: Arc<AtomicUsize>) -> Url {
+        let challenge = Challenge {
+            realm: "enphaseenergy.com".to_owned(),
+            nonce: NONCE.to_owned(),
+            opaque: None,
+            algorithm: Some("MD5".to_owned()),
+            qop_auth: true,
+        };
+        serve(move |request| {
+            let authorized = request
+                .header("Authorization")
+                .and_then(|header| header.strip_prefix("Digest "))
+                .is_some_and(|header| {
+                    let parameters = parse_parameters(header);
+                    let get = |name: &str| {
+                        parameters
+                            .iter()
+                            .find(|(key, _)| key == name)
+                            .map(|(_, value)| value.as_str())
+                            .unwrap_or_default()
+                    };
+                    let nc = u32::from_str_radix(get("nc"), 16).unwrap_or_default();
+                    get("uri") == request.path
+                        && format!("Digest {header}")
+                            == challenge.authorization(
+                                &credentials,
+                                &request.method,
+                                &request.path,
+                                nc,
+                                get("cnonce"),
+                            )
+                });
+            match request.path.as_str() {
+                "/ivp/meters/readings" if authorized => Response::json("[]"),
+                "/ivp/meters/readings" => {
+                    challenges.fetch_add(1, Ordering::SeqCst);
+                    Response::new(401, "Unauthorized").with_header(
+                        "WWW-Authenticate",
+                        &format!(
+                            r#"Digest realm="enphaseenergy.com", qop="auth", nonce="{NONCE}", algorithm=MD5"#
+                        ),
+                    )
+                }
+                _ => Response::new(404, "Not found"),
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn digest_authentication() {
+        let credentials = DigestCredentials::from_serial(DigestUser::Installer, "122212345678");
+        let challenges = Arc::new(AtomicUsize::new(0));
+        let base_url = gateway_stub(credentials.clone(), challenges.clone());
+
+        let envoy =
+            Envoy::new_with_digest(base_url.clone(), credentials, &TlsVerification::default())
+                .unwrap();
+        assert_eq!(envoy.meter_readings().await.unwrap(), vec![]);
+        assert_eq!(challenges.load(Ordering::SeqCst), 1);
+        // The cached challenge is answered without another round trip.
+        assert_eq!(envoy.meter_readings().await.unwrap(), vec![]);
+        assert_eq!(challenges.load(Ordering::SeqCst), 1);
+        assert_eq!(envoy.token(), None);
+
+        let envoy = Envoy::new_with_digest(
+            base_url,
+            DigestCredentials::from_serial(DigestUser::Envoy, "122212345678"),
+            &TlsVerification::default(),
+        )
+        .unwrap();
+        assert!(matches!(
+            envoy.meter_readings().await.unwrap_err(),
+            Error::Unauthorized { path } if path == "ivp/meters/readings"
+        ));
+    }
+
+    #[test]
+    fn derive_passwords() {
+        assert_eq!(derive_password(DigestUser::Envoy, "121234567890"), "567890");
+        assert_eq!(
+            derive_password(DigestUser::Installer, "121234567890"),
+            "bBec8b2a"
+        );
+        assert_eq!(
+            derive_password(DigestUser::Installer, "122212345678"),
+            "4hff27b2"
+        );
+        assert_eq!(
+            derive_password(DigestUser::Installer, "123456789012"),
+            "7edE24ed"
+        );
+    }
+
+    /// The example from RFC 2617 section 3.5.
+    #[test]
+    fn rfc_2617_example() {
+        let challenge = Challenge::parse(
+            r#"Digest realm="testrealm@host.com", qop="auth,auth-int", nonce="dcd98b7102dd2f0e8b11d0f600bfb0c093", opaque="5ccc069c403ebaf9f0171e9517f40e41""#,
+        )
+        .unwrap();
+        assert_eq!(
+            challenge,
+            Challenge {
+                realm: "testrealm@host.com".to_owned(),
+                nonce: "dcd98b7102dd2f0e8b11d0f600bfb0c093".to_owned(),
+                opaque: Some("5ccc069c403ebaf9f0171e9517f40e41".to_owned()),
+                algorithm: None,
+                qop_auth: true,
+            }
+        );
+        let credentials = DigestCredentials {
+            username: "Mufasa".to_owned(),
+            password: "Circle Of Life".to_owned(),
+        };
+        assert_eq!(
+            challenge.authorization(&credentials, "GET", "/dir/index.html", 1, "0a4f113b"),
+            r#"Digest username="Mufasa", realm="testrealm@host.com", nonce="dcd98b7102dd2f0e8b11d0f600bfb0c093", uri="/dir/index.html", qop=auth, nc=00000001, cnonce="0a4f113b", response="6629fae49393a05397450978507c4ef1", opaque="5ccc069c403ebaf9f0171e9517f40e41""#
+        );
+    }
+
+    #[test]
+    fn not_digest() {
+        assert_eq!(Challenge::parse(r#"Basic realm="envoy""#), None);
+        assert_eq!(Challenge::parse("Digest"), None);
+    }
+}
@@ -0,0 +1,506 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Discovering gateways on the local network with multicast DNS.
+
+use reqwest::Url;
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4},
+    time::Duration,
+};
+use tokio::{net::UdpSocket, time::Instant};
+
+/// The DNS-SD service type advertised by gateways.
+pub const SERVICE_NAME: &str = "_enphase-envoy._tcp.local";
+
+/// The standard mDNS multicast group and port.
+pub const MDNS_ADDRESS: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(224, 0, 0, 251), 5353);
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+/// The top bit of the class in a question asks for a unicast response.
+const UNICAST_RESPONSE: u16 = 0x8000;
+
+/// The maximum number of compression pointers to follow in a single name, to avoid loops.
+const MAX_POINTERS: usize = 16;
+
+/// Options for discovering gateways.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DiscoveryOptions {
+    /// How long to wait for responses.
+    pub timeout: Duration,
+    /// The multicast address and port to which to send the query.
+    pub multicast_address: SocketAddrV4,
+    /// The address of the local interface on which to send the query, or `UNSPECIFIED` for the
+    /// default.
+    pub interface: Ipv4Addr,
+}
+
+impl Default for DiscoveryOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(3),
+            multicast_address: MDNS_ADDRESS,
+            interface: Ipv4Addr::UNSPECIFIED,
+        }
+    }
+}
+
+/// A gateway found on the local network.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DiscoveredGateway {
+    /// The gateway's serial number.
+    pub serial: String,
+    /// The firmware version advertised by the gateway, if any.
+    pub firmware_version: Option<String>,
+    /// The gateway's IP address.
+    pub address: IpAddr,
+}
+
+impl DiscoveredGateway {
+    /// Returns the base URL of the gateway's local API.
+    pub fn base_url(&self) -> Url {
+        let host = match self.address {
+            IpAddr::V4(address) => address.to_string(),
+            IpAddr::V6(address) => format!("[{address}]"),
+        };
+        Url::parse(&format!("https://{host}/")).unwrap()
+    }
+}
+
+/// Returns all gateways which respond within the timeout.
+pub async fn discover(options: &DiscoveryOptions) -> io::Result<Vec<DiscoveredGateway>> {
+    let mut gateways = Vec::new();
+    query(options, |gateway| {
+        if !gateways
+            .iter()
+            .any(|found: &DiscoveredGateway| found.serial == gateway.serial)
+        {
+            gateways.push(gateway);
+        }
+        false
+    })
+    .await?;
+    Ok(gateways)
+}
+
+/// Returns the gateway with the given serial number, or `None` if it doesn't respond within the
+/// timeout.
+pub async fn discover_serial(
+    serial: &str,
+    options: &DiscoveryOptions,
+) -> io::Result<Option<DiscoveredGateway>> {
+    let mut found = None;
+    query(options, |gateway| {
+        if gateway.serial == serial {
+            found = Some(gateway);
+            true
+        } else {
+            false
+        }
+    })
+    .await?;
+    Ok(found)
+}
+
+/// Sends a query for gateways and passes each one found in responses to `found` until it returns
+/// true or the timeout expires.
+async fn query(
+    options: &DiscoveryOptions,
+    mut found: impl FnMut(DiscoveredGateway) -> bool,
+) -> io::Result<()> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_multicast_if_v4(&options.interface)?;
+    socket.set_multicast_loop_v4(true)?;
+    socket.set_multicast_ttl_v4(255)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SockAddr::from(SocketAddrV4::new(options.interface, 0)))?;
+    let socket = UdpSocket::from_std(socket.into())?;
+    socket
+        .send_to(&query_packet(), options.multicast_address)
+        .await?;
+
+    let deadline = Instant::now() + options.timeout;
+    let mut buffer = [0; 9000];
+    loop {
+        let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buffer)).await
+        else {
+            return Ok(());
+        };
+        let (length, source) = received?;
+        let Some(records) = parse_records(&buffer[..length]) else {
+            continue;
+        };
+        for gateway in gateways_from_records(&records, source) {
+            if found(gateway) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Builds a query for PTR records of the gateway service, asking for a unicast response.
+fn query_packet() -> Vec<u8> {
+    let mut packet = Vec::new();
+    // ID, flags, 1 question, no answer, authority or additional records.
+    for field in [0u16, 0, 1, 0, 0, 0] {
+        packet.extend_from_slice(&field.to_be_bytes());
+    }
+    write_name(&mut packet, SERVICE_NAME);
+    packet.extend_from_slice(&TYPE_PTR.to_be_bytes());
+    packet.extend_from_slice(&(CLASS_IN | UNICAST_RESPONSE).to_be_bytes());
+    packet
+}
+
+/// Writes the given dot-separated name in DNS wire format, without compression.
+fn write_name(packet: &mut Vec<u8>, name: &str) {
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+}
+
+/// A resource record from a DNS message.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Record {
+    name: String,
+    data: RecordData,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum RecordData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Ptr(String),
+    Srv { target: String },
+    Txt(HashMap<String, String>),
+    Other,
+}
+
+/// Parses all the answer, authority and additional records from the given DNS message, or
+/// returns `None` if it is malformed.
+fn parse_records(packet: &[u8]) -> Option<Vec<Record>> {
+    let mut reader = Reader {
+        packet,
+        position: 4,
+    };
+    let questions = reader.u16()?;
+    let records =
+        usize::from(reader.u16()?) + usize::from(reader.u16()?) + usize::from(reader.u16()?);
+    for _ in 0..questions {
+        reader.name()?;
+        reader.bytes(4)?;
+    }
+    (0..records).map(|_| reader.record()).collect()
+}
+
+struct Reader<'a> {
+    packet: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, length: usize) -> Option<&'a [u8]> {
+        let bytes = self
+            .packet
+            .get(self.position..self.position.checked_add(length)?)?;
+        self.position += length;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    /// Reads a possibly compressed name, returning it with labels separated by dots.
+    fn name(&mut self) -> Option<String> {
+        let mut labels = Vec::new();
+        let mut position = self.position;
+        let mut pointers = 0;
+        loop {
+            let length = *self.packet.get(position)?;
+            match length {
+                0 => {
+                    position += 1;
+                    break;
+                }
+                length if length & 0xc0 == 0xc0 => {
+                    let offset = usize::from(u16::from_be_bytes([
+                        length & 0x3f,
+                        *self.packet.get(position + 1)?,
+                    ]));
+                    if pointers == 0 {
+                        self.position = position + 2;
+                    }
+                    pointers += 1;
+                    if pointers > MAX_POINTERS {
+                        return None;
+                    }
+                    position = offset;
+                }
+                length => {
+                    let start = position + 1;
+                    let label = self.packet.get(start..start + usize::from(length))?;
+                    labels.push(String::from_utf8_lossy(label).into_owned());
+                    position = start + usize::from(length);
+                }
+            }
+        }
+        if pointers == 0 {
+            self.position = position;
+        }
+        Some(labels.join("."))
+    }
+
+    fn record(&mut self) -> Option<Record> {
+        let name = self.name()?;
+        let type_ = self.u16()?;
+        // Class and TTL.
+        self.bytes(6)?;
+        let length = usize::from(self.u16()?);
+        let end = self.position + length;
+        let data = match type_ {
+            TYPE_A => RecordData::A(<[u8; 4]>::try_from(self.bytes(length)?).ok()?.into()),
+            TYPE_AAAA => RecordData::Aaaa(<[u8; 16]>::try_from(self.bytes(length)?).ok()?.into()),
+            TYPE_PTR => RecordData::Ptr(self.name()?),
+            TYPE_SRV => {
+                // Priority, weight and port.
+                self.bytes(6)?;
+                RecordData::Srv {
+                    target: self.name()?,
+                }
+            }
+            TYPE_TXT => {
+                let mut entries = HashMap::new();
+                while self.position < end {
+                    let length = usize::from(self.u8()?);
+                    let entry = String::from_utf8_lossy(self.bytes(length)?);
+                    let (key, value) = entry.split_once('=').unwrap_or((&entry, ""));
+                    entries.insert(key.to_ascii_lowercase(), value.to_owned());
+                }
+                RecordData::Txt(entries)
+            }
+            _ => RecordData::Other,
+        };
+        if self.position > end {
+            return None;
+        }
+        self.position = end;
+        Some(Record { name, data })
+    }
+}
+
+/// Finds all the gateways advertised in the given records, falling back to the source address of
+/// the response if there is no address record for a gateway.
+fn gateways_from_records(records: &[Record], source: SocketAddr) -> Vec<DiscoveredGateway> {
+    let find = |name: &str, f: &dyn Fn(&RecordData) -> bool| {
+        records
+            .iter()
+            .find(|record| record.name.eq_ignore_ascii_case(name) && f(&record.data))
+            .map(|record| &record.data)
+    };
+    records
+        .iter()
+        .filter_map(|record| match &record.data {
+            RecordData::Ptr(instance) if record.name.eq_ignore_ascii_case(SERVICE_NAME) => {
+                Some(instance)
+            }
+            _ => None,
+        })
+        .filter_map(|instance| {
+            let Some(RecordData::Txt(txt)) =
+                find(instance, &|data| matches!(data, RecordData::Txt(_)))
+            else {
+                return None;
+            };
+            let serial = txt.get("serialnum")?.clone();
+            let address = match find(instance, &|data| matches!(data, RecordData::Srv { .. })) {
+                Some(RecordData::Srv { target }) => {
+                    match find(target, &|data| {
+                        matches!(data, RecordData::A(_) | RecordData::Aaaa(_))
+                    }) {
+                        Some(RecordData::A(address)) => Some(IpAddr::V4(*address)),
+                        Some(RecordData::Aaaa(address)) => Some(IpAddr::V6(*address)),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            Some(DiscoveredGateway {
+                serial,
+                firmware_version: txt.get("protovers").cloned(),
+                address: address.unwrap_or(source.ip()),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{net::UdpSocket as StdUdpSocket, thread};
+
+    struct TestGateway {
+        instance: &'static str,
+        hostname: &'static str,
+        serial: &'static str,
+        firmware_version: &'static str,
+        address: Option<Ipv4Addr>,
+    }
+
+    fn write_record(packet: &mut Vec<u8>, name: &str, type_: u16, data: &[u8]) {
+        write_name(packet, name);
+        packet.extend_from_slice(&type_.to_be_bytes());
+        packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+        packet.extend_from_slice(&120u32.to_be_bytes());
+        packet.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        packet.extend_from_slice(data);
+    }
+
+    /// Builds an mDNS response advertising the given gateway.
+    fn response_packet(gateway: &TestGateway) -> Vec<u8> {
+        let records = if gateway.address.is_some() { 4 } else { 3 };
+        let mut packet = Vec::new();
+        for field in [0u16, 0x8400, 0, 1, 0, records - 1] {
+            packet.extend_from_slice(&field.to_be_bytes());
+        }
+        let mut instance = Vec::new();
+        write_name(&mut instance, gateway.instance);
+        write_record(&mut packet, SERVICE_NAME, TYPE_PTR, &instance);
+
+        let mut txt = Vec::new();
+        for entry in [
+            format!("serialnum={}", gateway.serial),
+            format!("protovers={}", gateway.firmware_version),
+            "txtvers=1".to_owned(),
+        ] {
+            txt.push(entry.len() as u8);
+            txt.extend_from_slice(entry.as_bytes());
+        }
+        write_record(&mut packet, gateway.instance, TYPE_TXT, &txt);
+
+        let mut srv = vec![0, 0, 0, 0, 0, 80];
+        write_name(&mut srv, gateway.hostname);
+        write_record(&mut packet, gateway.instance, TYPE_SRV, &srv);
+
+        if let Some(address) = gateway.address {
+            write_record(&mut packet, gateway.hostname, TYPE_A, &address.octets());
+        }
+        packet
+    }
+
+    /// Starts an mDNS responder on the loopback interface which answers queries for gateways with
+    /// the given gateways, and returns discovery options to query it.
+    fn responder(gateways: Vec<TestGateway>) -> DiscoveryOptions {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).unwrap();
+        socket.set_reuse_address(true).unwrap();
+        socket
+            .bind(&SockAddr::from(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)))
+            .unwrap();
+        socket
+            .join_multicast_v4(MDNS_ADDRESS.ip(), &Ipv4Addr::LOCALHOST)
+            .unwrap();
+        let socket = StdUdpSocket::from(socket);
+        let port = socket.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let mut buffer = [0; 9000];
+            while let Ok((length, source)) = socket.recv_from(&mut buffer) {
+                if buffer[..length] != query_packet() {
+                    continue;
+                }
+                for gateway in &gateways {
+                    socket.send_to(&response_packet(gateway), source).unwrap();
+                }
+            }
+        });
+        DiscoveryOptions {
+            timeout: Duration::from_millis(500),
+            multicast_address: SocketAddrV4::new(*MDNS_ADDRESS.ip(), port),
+            interface: Ipv4Addr::LOCALHOST,
+        }
+    }
+
+    #[test]
+    fn parse_compressed_names() {
+        let mut packet = Vec::new();
+        for field in [0u16, 0x8400, 0, 1, 0, 0] {
+            packet.extend_from_slice(&field.to_be_bytes());
+        }
+        write_name(&mut packet, SERVICE_NAME);
+        packet.extend_from_slice(&TYPE_PTR.to_be_bytes());
+        packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+        packet.extend_from_slice(&120u32.to_be_bytes());
+        packet.extend_from_slice(&8u16.to_be_bytes());
+        // "envoy" followed by a pointer to the service name at offset 12.
+        packet.extend_from_slice(b"\x05envoy\xc0\x0c");
+        assert_eq!(
+            parse_records(&packet),
+            Some(vec![Record {
+                name: SERVICE_NAME.to_owned(),
+                data: RecordData::Ptr(format!("envoy.{SERVICE_NAME}")),
+            }])
+        );
+
+        // A pointer to itself shouldn't loop forever.
+        let length = packet.len();
+        packet[length - 2..].copy_from_slice(&[0xc0, (length - 2) as u8]);
+        assert_eq!(parse_records(&packet), None);
+    }
+
+    #[tokio::test]
+    async fn discover_gateways() {
+        let options = responder(vec![
+            TestGateway {
+                instance: "envoy._enphase-envoy._tcp.local",
+                hostname: "envoy.local",
+                serial: "123456789012",
+                firmware_version: "7.6.175",
+                address: Some(Ipv4Addr::new(192, 168, 1, 42)),
+            },
+            TestGateway {
+                instance: "envoy-2._enphase-envoy._tcp.local",
+                hostname: "envoy-2.local",
+                serial: "210987654321",
+                firmware_version: "5.0.62",
+                address: None,
+            },
+        ]);
+        let gateways = discover(&options).await.unwrap();
+        assert_eq!(
+            gateways,
+            vec![
+                DiscoveredGateway {
+                    serial: "123456789012".to_owned(),
+                    firmware_version: Some("7.6.175".to_owned()),
+                    address: Ipv4Addr::new(192, 168, 1, 42).into(),
+                },
+                DiscoveredGateway {
+                    serial: "210987654321".to_owned(),
+                    firmware_version: Some("5.0.62".to_owned()),
+                    address: Ipv4Addr::LOCALHOST.into(),
+                },
+            ]
+        );
+        assert_eq!(gateways[0].base_url().as_str(), "https://192.168.1.42/");
+
+        let gateway = discover_serial("210987654321", &options).await.unwrap();
+        assert_eq!(gateway, Some(gateways[1].clone()));
+        assert_eq!(
+            discover_serial("000000000000", &options).await.unwrap(),
+            None
+        );
+    }
+}
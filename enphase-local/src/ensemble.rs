@@ -0,0 +1,508 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Types returned by the IVP ensemble APIs, for IQ Batteries (Encharge) and the IQ System
+//! Controller (Enpower).
+
+use chrono::{DateTime, Utc, serde::ts_seconds};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The inventory of ensemble devices, from `ivp/ensemble/inventory`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct EnsembleInventory(pub Vec<EnsembleGroup>);
+
+impl EnsembleInventory {
+    /// Returns all IQ Batteries in the inventory.
+    pub fn batteries(&self) -> impl Iterator<Item = &Encharge> {
+        self.0.iter().flat_map(|group| match group {
+            EnsembleGroup::Encharge { devices } => devices.as_slice(),
+            EnsembleGroup::Enpower { .. } | EnsembleGroup::Other => &[],
+        })
+    }
+
+    /// Returns all IQ System Controllers in the inventory.
+    pub fn enpowers(&self) -> impl Iterator<Item = &Enpower> {
+        self.0.iter().flat_map(|group| match group {
+            EnsembleGroup::Enpower { devices } => devices.as_slice(),
+            EnsembleGroup::Encharge { .. } | EnsembleGroup::Other => &[],
+        })
+    }
+}
+
+/// A group of ensemble devices of a particular type.
+///
+/// Groups of other types are parsed as [`EnsembleGroup::Other`] without their devices.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum EnsembleGroup {
+    /// IQ Batteries.
+    #[serde(rename = "ENCHARGE")]
+    Encharge { devices: Vec<Encharge> },
+    /// IQ System Controllers.
+    #[serde(rename = "ENPOWER")]
+    Enpower { devices: Vec<Enpower> },
+    /// Some other type of device, such as an IQ Meter Collar or IQ Combiner.
+    #[serde(other)]
+    Other,
+}
+
+/// An IQ Battery in the ensemble inventory.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Encharge {
+    /// The part number of the battery.
+    pub part_num: String,
+    /// When the battery was installed.
+    #[serde(with = "ts_seconds")]
+    pub installed: DateTime<Utc>,
+    /// The serial number of the battery.
+    pub serial_num: String,
+    /// The battery's current statuses, such as `envoy.global.ok`.
+    pub device_status: Vec<String>,
+    /// When the battery last reported to the gateway.
+    #[serde(with = "ts_seconds")]
+    pub last_rpt_date: DateTime<Utc>,
+    /// The administrative state of the battery.
+    pub admin_state: u32,
+    /// The name of the administrative state, such as `ENCHG_STATE_READY`.
+    pub admin_state_str: String,
+    /// The battery's firmware product number.
+    pub img_pnum_running: Option<String>,
+    /// Whether the battery is communicating with the gateway.
+    pub communicating: bool,
+    /// Whether the battery is operating.
+    #[serde(default)]
+    pub operating: bool,
+    /// Whether the battery is asleep.
+    #[serde(default)]
+    pub sleep_enabled: bool,
+    /// The state of charge as a percentage.
+    #[serde(rename = "percentFull")]
+    pub percent_full: u8,
+    /// The battery temperature in degrees Celsius.
+    pub temperature: i32,
+    /// The maximum cell temperature in degrees Celsius.
+    #[serde(rename = "maxCellTemp")]
+    pub max_cell_temp: Option<i32>,
+    /// Whether the battery's DC switch is off.
+    #[serde(default)]
+    pub dc_switch_off: bool,
+    /// The capacity of the battery in watt-hours.
+    pub encharge_capacity: u32,
+    /// The phase to which the battery is connected, such as `ph-a`.
+    pub phase: Option<String>,
+}
+
+/// An IQ System Controller in the ensemble inventory.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Enpower {
+    /// The part number of the system controller.
+    pub part_num: String,
+    /// When the system controller was installed.
+    #[serde(with = "ts_seconds")]
+    pub installed: DateTime<Utc>,
+    /// The serial number of the system controller.
+    pub serial_num: String,
+    /// The system controller's current statuses.
+    pub device_status: Vec<String>,
+    /// When the system controller last reported to the gateway.
+    #[serde(with = "ts_seconds")]
+    pub last_rpt_date: DateTime<Utc>,
+    /// The administrative state of the system controller.
+    pub admin_state: u32,
+    /// The name of the administrative state, such as `ENPWR_STATE_OPER_CLOSED`.
+    pub admin_state_str: String,
+    /// The system controller's firmware product number.
+    pub img_pnum_running: Option<String>,
+    /// Whether the system controller is communicating with the gateway.
+    pub communicating: bool,
+    /// The system controller temperature in degrees Fahrenheit.
+    pub temperature: i32,
+    /// The configured state of the grid relay.
+    pub mains_admin_state: RelayState,
+    /// The actual state of the grid relay.
+    pub mains_oper_state: RelayState,
+    /// The grid mode of the system controller, such as `multimode-ongrid`.
+    #[serde(rename = "Enpwr_grid_mode")]
+    pub enpwr_grid_mode: String,
+    /// The grid mode of the batteries, such as `multimode-ongrid`.
+    #[serde(rename = "Enchg_grid_mode")]
+    pub enchg_grid_mode: String,
+    /// A bitmap of the states of the system controller's relays.
+    #[serde(rename = "Enpwr_relay_state_bm")]
+    pub enpwr_relay_state_bm: Option<u32>,
+}
+
+impl Enpower {
+    /// Returns whether the grid relay is closed, so the site is connected to the grid.
+    pub fn grid_connected(&self) -> bool {
+        self.mains_oper_state == RelayState::Closed
+    }
+}
+
+/// The state of a relay.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RelayState {
+    Open,
+    Closed,
+}
+
+/// The status of the ensemble, from `ivp/ensemble/status`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct EnsembleStatus {
+    pub inventory: EnsembleStatusInventory,
+    /// The aggregate state of the storage system.
+    pub secctrl: SecCtrl,
+    /// The state of the grid relay.
+    pub relay: Option<Relay>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct EnsembleStatusInventory {
+    /// The status of each ensemble device, by serial number.
+    pub serial_nums: BTreeMap<String, EnsembleDeviceStatus>,
+}
+
+/// The status of an ensemble device.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct EnsembleDeviceStatus {
+    pub device_type: u32,
+    /// The name of the administrative state, such as `ENCHG_STATE_READY`.
+    pub admin_state_str: String,
+    /// The device's firmware version.
+    pub sw_version: Option<String>,
+    /// The part number of the device.
+    #[serde(rename = "partNumber")]
+    pub part_number: Option<String>,
+    /// The grid mode reported by the device, such as `multimode-ongrid`.
+    pub reported_grid_mode: Option<String>,
+    /// The phase to which the device is connected, such as `ph-a`.
+    pub phase: Option<String>,
+    /// The capacity of a battery in watt-hours.
+    pub encharge_capacity: Option<u32>,
+}
+
+/// The aggregate state of the storage system, from `ivp/ensemble/secctrl` or as part of the
+/// ensemble status.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SecCtrl {
+    /// The aggregate state of charge of all batteries as a percentage.
+    pub agg_soc: u8,
+    /// The total capacity of all batteries in watt-hours.
+    #[serde(rename = "Max_energy")]
+    pub max_energy: u32,
+    /// The aggregate state of charge of IQ Batteries as a percentage.
+    #[serde(rename = "ENC_agg_soc")]
+    pub enc_agg_soc: Option<u8>,
+    /// The aggregate state of health of IQ Batteries as a percentage.
+    #[serde(rename = "ENC_agg_soh")]
+    pub enc_agg_soh: Option<u8>,
+    /// The energy of IQ Batteries reserved for backup, in watt-hours.
+    #[serde(rename = "ENC_agg_backup_energy")]
+    pub enc_agg_backup_energy: Option<u32>,
+    /// The energy available from IQ Batteries, in watt-hours.
+    #[serde(rename = "ENC_agg_avail_energy")]
+    pub enc_agg_avail_energy: Option<u32>,
+    /// The commissioned capacity of IQ Batteries, in watt-hours.
+    #[serde(rename = "Enc_commissioned_capacity")]
+    pub enc_commissioned_capacity: Option<u32>,
+    /// The maximum available capacity of IQ Batteries, in watt-hours.
+    #[serde(rename = "Enc_max_available_capacity")]
+    pub enc_max_available_capacity: Option<u32>,
+    /// The aggregate state of charge of AC Batteries as a percentage.
+    #[serde(rename = "ACB_agg_soc")]
+    pub acb_agg_soc: Option<u8>,
+    /// The energy stored in AC Batteries, in watt-hours.
+    #[serde(rename = "ACB_agg_energy")]
+    pub acb_agg_energy: Option<u32>,
+    /// The state of charge reserved for backup, as a percentage.
+    pub configured_backup_soc: Option<u8>,
+    /// The state of charge reserved for backup after adjustment by the gateway, as a percentage.
+    pub adjusted_backup_soc: Option<u8>,
+}
+
+/// The state of the grid relay, as part of the ensemble status.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Relay {
+    /// The configured state of the grid relay.
+    pub mains_admin_state: RelayState,
+    /// The actual state of the grid relay. Some firmware misspells this field.
+    #[serde(alias = "mains_oper_sate")]
+    pub mains_oper_state: RelayState,
+    /// The grid mode of the system controller, such as `multimode-ongrid`.
+    #[serde(rename = "Enpwr_grid_mode")]
+    pub enpwr_grid_mode: Option<String>,
+    /// The grid mode of the batteries, such as `multimode-ongrid`.
+    #[serde(rename = "Enchg_grid_mode")]
+    pub enchg_grid_mode: Option<String>,
+    /// The grid state seen by the system controller, such as `grid-on`.
+    #[serde(rename = "Enpwr_grid_state")]
+    pub enpwr_grid_state: Option<String>,
+}
+
+/// The power flowing into or out of each IQ Battery, from `ivp/ensemble/power`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct EnsemblePower {
+    /// Some firmware has a stray colon in this field name.
+    #[serde(rename = "devices:", alias = "devices")]
+    pub devices: Vec<BatteryPower>,
+}
+
+/// The power flowing into or out of an IQ Battery.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct BatteryPower {
+    /// The serial number of the battery.
+    pub serial_num: String,
+    /// The real power in milliwatts, positive when discharging and negative when charging.
+    pub real_power_mw: i64,
+    /// The apparent power in millivolt-amps.
+    pub apparent_power_mva: i64,
+    /// The state of charge as a percentage.
+    pub soc: u8,
+}
+
+/// The state of an IQ Battery, combining its inventory entry and current power.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Battery {
+    /// The serial number of the battery.
+    pub serial_number: String,
+    /// The state of charge as a percentage.
+    pub soc: u8,
+    /// The battery temperature in degrees Celsius.
+    pub temperature: i32,
+    /// The real power in watts, positive when discharging and negative when charging, if
+    /// reported.
+    pub watts: Option<f64>,
+    /// The capacity of the battery in watt-hours.
+    pub capacity_wh: u32,
+    /// Whether the battery is communicating with the gateway.
+    pub communicating: bool,
+}
+
+/// Combines the batteries in the inventory with their current power, matching by serial number.
+pub(crate) fn join_batteries(inventory: &EnsembleInventory, power: &EnsemblePower) -> Vec<Battery> {
+    inventory
+        .batteries()
+        .map(|encharge| {
+            let power = power
+                .devices
+                .iter()
+                .find(|device| device.serial_num == encharge.serial_num);
+            Battery {
+                serial_number: encharge.serial_num.clone(),
+                soc: power.map_or(encharge.percent_full, |power| power.soc),
+                temperature: encharge.temperature,
+                watts: power.map(|power| power.real_power_mw as f64 / 1000.0),
+                capacity_wh: encharge.encharge_capacity,
+                communicating: encharge.communicating,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INVENTORY: &str = r#"[
+    {
+        "type": "ENCHARGE",
+        "devices": [
+            {
+                "part_num": "830-01760-r37",
+                "installed": 1695330323,
+                "serial_num": "122327081322",
+                "device_status": ["envoy.global.ok", "prop.done"],
+                "last_rpt_date": 1695769447,
+                "admin_state": 6,
+                "admin_state_str": "ENCHG_STATE_READY",
+                "created_date": 1695330323,
+                "img_load_date": 1695330323,
+                "img_pnum_running": "2.6.5973_rel/22.11",
+                "zigbee_dongle_fw_version": "100F",
+                "bmu_fw_version": "2.1.34",
+                "operating": true,
+                "communicating": true,
+                "sleep_enabled": false,
+                "percentFull": 81,
+                "temperature": 29,
+                "maxCellTemp": 30,
+                "comm_level_sub_ghz": 4,
+                "comm_level_2_4_ghz": 4,
+                "led_status": 17,
+                "dc_switch_off": false,
+                "encharge_rev": 2,
+                "encharge_capacity": 3500,
+                "phase": "ph-a",
+                "der_index": 1
+            }
+        ]
+    },
+    {
+        "type": "ENPOWER",
+        "devices": [
+            {
+                "part_num": "860-00276-r28",
+                "installed": 1695330231,
+                "serial_num": "122303131024",
+                "device_status": ["envoy.global.ok", "prop.done"],
+                "last_rpt_date": 1695769418,
+                "admin_state": 24,
+                "admin_state_str": "ENPWR_STATE_OPER_CLOSED",
+                "created_date": 1695330231,
+                "img_load_date": 1695330231,
+                "img_pnum_running": "1.2.2064_release/20.34",
+                "communicating": true,
+                "temperature": 79,
+                "comm_level_sub_ghz": 5,
+                "comm_level_2_4_ghz": 5,
+                "mains_admin_state": "closed",
+                "mains_oper_state": "closed",
+                "Enpwr_grid_mode": "multimode-ongrid",
+                "Enchg_grid_mode": "multimode-ongrid",
+                "Enpwr_relay_state_bm": 48,
+                "Enpwr_curr_state_id": 16
+            }
+        ]
+    }
+]"#;
+
+    #[test]
+    fn parse_inventory() {
+        let inventory = serde_json::from_str::<EnsembleInventory>(INVENTORY).unwrap();
+        let batteries = inventory.batteries().collect::<Vec<_>>();
+        assert_eq!(batteries.len(), 1);
+        assert_eq!(batteries[0].serial_num, "122327081322");
+        assert_eq!(batteries[0].percent_full, 81);
+        assert_eq!(batteries[0].temperature, 29);
+        assert_eq!(batteries[0].encharge_capacity, 3500);
+        assert_eq!(
+            batteries[0].last_rpt_date,
+            "2023-09-26T23:04:07Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        let enpowers = inventory.enpowers().collect::<Vec<_>>();
+        assert_eq!(enpowers.len(), 1);
+        assert_eq!(enpowers[0].mains_oper_state, RelayState::Closed);
+        assert!(enpowers[0].grid_connected());
+    }
+
+    /// Groups of other types of devices should be ignored.
+    #[test]
+    fn parse_inventory_unknown_group() {
+        let inventory = serde_json::from_str::<EnsembleInventory>(
+            r#"[
+    {
+        "type": "COLLAR",
+        "devices": [
+            {
+                "part_num": "865-00400-r22",
+                "serial_num": "482405001234",
+                "admin_state": 1,
+                "admin_state_str": "ENCMN_MDE_ON_GRID",
+                "communicating": true,
+                "temperature": 41,
+                "grid_state": "on_grid"
+            }
+        ]
+    },
+    {
+        "type": "C6CC",
+        "devices": []
+    }
+]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            inventory,
+            EnsembleInventory(vec![EnsembleGroup::Other, EnsembleGroup::Other])
+        );
+        assert_eq!(inventory.batteries().count(), 0);
+        assert_eq!(inventory.enpowers().count(), 0);
+    }
+
+    #[test]
+    fn parse_status() {
+        let status = serde_json::from_str::<EnsembleStatus>(
+            r#"{
+    "inventory": {
+        "serial_nums": {
+            "122327081322": {
+                "device_type": 13,
+                "comm_interface_str": "USB",
+                "admin_state": 6,
+                "admin_state_str": "ENCHG_STATE_READY",
+                "msg_retry_count": 0,
+                "sw_version": "2.6.5973_rel/22.11",
+                "partNumber": "830-01760-r37",
+                "reported_grid_mode": "multimode-ongrid",
+                "phase": "ph-a",
+                "encharge_capacity": 3500
+            }
+        },
+        "share_info": {},
+        "enpower_count": 1
+    },
+    "counters": {},
+    "secctrl": {
+        "shutdown": false,
+        "agg_soc": 81,
+        "Max_energy": 3500,
+        "ENC_agg_soc": 81,
+        "ENC_agg_soh": 100,
+        "ENC_agg_backup_energy": 0,
+        "ENC_agg_avail_energy": 2835,
+        "Enc_commissioned_capacity": 3500,
+        "Enc_max_available_capacity": 3500,
+        "ACB_agg_soc": 0,
+        "ACB_agg_energy": 0,
+        "VLS_Limit": 0
+    },
+    "relay": {
+        "mains_admin_state": "closed",
+        "mains_oper_sate": "open",
+        "der1_state": 0,
+        "Enchg_grid_mode": "multimode-offgrid",
+        "Enpwr_grid_mode": "multimode-offgrid",
+        "Enpwr_grid_state": "grid-off"
+    }
+}"#,
+        )
+        .unwrap();
+        assert_eq!(status.secctrl.agg_soc, 81);
+        assert_eq!(status.secctrl.enc_agg_avail_energy, Some(2835));
+        assert_eq!(status.secctrl.configured_backup_soc, None);
+        assert_eq!(
+            status.inventory.serial_nums["122327081322"].encharge_capacity,
+            Some(3500)
+        );
+        let relay = status.relay.unwrap();
+        assert_eq!(relay.mains_admin_state, RelayState::Closed);
+        assert_eq!(relay.mains_oper_state, RelayState::Open);
+    }
+
+    #[test]
+    fn join_power() {
+        let inventory = serde_json::from_str::<EnsembleInventory>(INVENTORY).unwrap();
+        let power = serde_json::from_str::<EnsemblePower>(
+            r#"{"devices:": [{"serial_num": "122327081322", "real_power_mw": -1072000, "apparent_power_mva": 1072000, "soc": 82}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            join_batteries(&inventory, &power),
+            vec![Battery {
+                serial_number: "122327081322".to_owned(),
+                soc: 82,
+                temperature: 29,
+                watts: Some(-1072.0),
+                capacity_wh: 3500,
+                communicating: true,
+            }]
+        );
+        assert_eq!(
+            join_batteries(&inventory, &EnsemblePower { devices: vec![] })[0].watts,
+            None
+        );
+    }
+}
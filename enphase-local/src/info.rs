@@ -0,0 +1,221 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Types returned by the info API.
+
+use chrono::{DateTime, Utc, serde::ts_seconds};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+/// Information about the gateway and its software.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Info {
+    /// The current time according to the gateway.
+    #[serde(with = "ts_seconds")]
+    pub time: DateTime<Utc>,
+    /// Information about the gateway itself.
+    pub device: DeviceInfo,
+    /// Whether the gateway requires tokens to authenticate to the local API.
+    #[serde(rename = "web-tokens", default)]
+    pub web_tokens: bool,
+    /// The software packages installed on the gateway.
+    #[serde(rename = "package", default)]
+    pub packages: Vec<Package>,
+    /// Information about the gateway's software build.
+    pub build_info: Option<BuildInfo>,
+}
+
+impl Info {
+    /// Returns the gateway's firmware version, if it can be parsed.
+    pub fn firmware_version(&self) -> Option<FirmwareVersion> {
+        self.device.software.parse().ok()
+    }
+
+    /// Returns the installed package with the given name, if any.
+    pub fn package(&self, name: &str) -> Option<&Package> {
+        self.packages.iter().find(|package| package.name == name)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct DeviceInfo {
+    /// The gateway's serial number.
+    #[serde(rename = "sn")]
+    pub serial_number: String,
+    /// The gateway's part number.
+    #[serde(rename = "pn")]
+    pub part_number: String,
+    /// The gateway's firmware version, such as `D7.6.175`.
+    pub software: String,
+    pub euaid: Option<String>,
+    pub seqnum: Option<u32>,
+    /// The version of the local API.
+    #[serde(rename = "apiver")]
+    pub api_version: Option<u32>,
+    /// Whether the gateway has integrated meters.
+    pub imeter: Option<bool>,
+}
+
+/// A software package installed on the gateway.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Package {
+    /// The name of the package, such as `app` or `rootfs`.
+    #[serde(rename = "@pkg")]
+    pub name: String,
+    /// The part number of the package.
+    #[serde(rename = "pn")]
+    pub part_number: String,
+    /// The version of the package.
+    pub version: String,
+    /// The build number of the package.
+    pub build: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct BuildInfo {
+    /// When the software was built.
+    #[serde(with = "ts_seconds")]
+    pub build_time_gmt: DateTime<Utc>,
+    pub build_id: String,
+}
+
+/// A gateway firmware version, which can be compared to decide which APIs are available.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct FirmwareVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl FirmwareVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl Display for FirmwareVersion {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for FirmwareVersion {
+    type Err = ();
+
+    /// Parses a version such as `D7.6.175` or `R5.0.62`, ignoring any leading letter, missing
+    /// components or trailing suffix.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut components = s
+            .trim_start_matches(|c: char| c.is_ascii_alphabetic())
+            .split('.')
+            .map(|component| {
+                let digits = component
+                    .find(|c: char| !c.is_ascii_digit())
+                    .map_or(component, |end| &component[..end]);
+                digits.parse::<u32>()
+            });
+        let major = components.next().ok_or(())?.map_err(|_| ())?;
+        let mut next = || components.next().and_then(Result::ok).unwrap_or(0);
+        Ok(Self::new(major, next(), next()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_example() {
+        let info = quick_xml::de::from_str::<Info>(
+            r#"<?xml version='1.0' encoding='UTF-8'?>
+<envoy_info>
+  <time>1701084345</time>
+  <device>
+    <sn>122212345678</sn>
+    <pn>800-00654-r08</pn>
+    <software>D7.6.175</software>
+    <euaid>4c8675</euaid>
+    <seqnum>0</seqnum>
+    <apiver>1</apiver>
+    <imeter>true</imeter>
+  </device>
+  <web-tokens>true</web-tokens>
+  <package pkg="rootfs">
+    <pn>500-00001-r01</pn>
+    <version>02.00.00</version>
+    <build>950</build>
+  </package>
+  <package pkg="app">
+    <pn>500-00002-r01</pn>
+    <version>07.06.175</version>
+    <build>f79c8d</build>
+  </package>
+  <build_info>
+    <build_time_gmt>1700591302</build_time_gmt>
+    <build_id>release-7.6.x-175-Nov-21-23-18:28:22</build_id>
+  </build_info>
+</envoy_info>"#,
+        )
+        .unwrap();
+        assert_eq!(
+            info,
+            Info {
+                time: "2023-11-27T11:25:45Z".parse().unwrap(),
+                device: DeviceInfo {
+                    serial_number: "122212345678".to_owned(),
+                    part_number: "800-00654-r08".to_owned(),
+                    software: "D7.6.175".to_owned(),
+                    euaid: Some("4c8675".to_owned()),
+                    seqnum: Some(0),
+                    api_version: Some(1),
+                    imeter: Some(true),
+                },
+                web_tokens: true,
+                packages: vec![
+                    Package {
+                        name: "rootfs".to_owned(),
+                        part_number: "500-00001-r01".to_owned(),
+                        version: "02.00.00".to_owned(),
+                        build: "950".to_owned(),
+                    },
+                    Package {
+                        name: "app".to_owned(),
+                        part_number: "500-00002-r01".to_owned(),
+                        version: "07.06.175".to_owned(),
+                        build: "f79c8d".to_owned(),
+                    },
+                ],
+                build_info: Some(BuildInfo {
+                    build_time_gmt: "2023-11-21T18:28:22Z".parse().unwrap(),
+                    build_id: "release-7.6.x-175-Nov-21-23-18:28:22".to_owned(),
+                }),
+            }
+        );
+        assert_eq!(
+            info.firmware_version(),
+            Some(FirmwareVersion::new(7, 6, 175))
+        );
+        assert_eq!(info.package("app").unwrap().version, "07.06.175");
+    }
+
+    #[test]
+    fn parse_firmware_version() {
+        assert_eq!("D7.6.175".parse(), Ok(FirmwareVersion::new(7, 6, 175)));
+        assert_eq!("R5.0.62".parse(), Ok(FirmwareVersion::new(5, 0, 62)));
+        assert_eq!(
+            "8.2.4286_f7e3".parse(),
+            Ok(FirmwareVersion::new(8, 2, 4286))
+        );
+        assert_eq!("D8".parse(), Ok(FirmwareVersion::new(8, 0, 0)));
+        assert_eq!("unknown".parse::<FirmwareVersion>(), Err(()));
+        assert!(FirmwareVersion::new(7, 6, 175) > FirmwareVersion::new(5, 0, 62));
+    }
+}
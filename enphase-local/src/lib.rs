@@ -19,114 +19,758 @@
 //! # Ok(()) }
 //! ```
 
+pub mod auth;
+pub mod device_data;
+pub mod digest;
+pub mod discovery;
+pub mod ensemble;
 pub mod home;
+pub mod info;
 pub mod inventory;
 pub mod inverters;
+pub mod livedata;
 pub mod meters;
 pub mod production;
+pub mod snapshot;
+#[cfg(test)]
+mod test_server;
 mod timestamp_string;
+pub mod tls;
 
+use auth::{AuthError, EnlightenCredentials, EnlightenEndpoints, Token, fetch_token};
+use chrono::Utc;
+use device_data::{PdmDevice, parse_devices};
+use digest::{DigestCredentials, DigestSession};
+use ensemble::{
+    Battery, EnsembleInventory, EnsemblePower, EnsembleStatus, SecCtrl, join_batteries,
+};
 use home::Home;
+use info::Info;
 use inventory::Inventory;
 use inverters::Inverter;
-use meters::{Reading, Report};
+use livedata::{LiveDataPoller, LiveStatus, StreamRequest, StreamResponse, StreamState};
+use meters::{ConfiguredReading, Meter, Reading, Report, join_readings};
 use production::Production;
-use reqwest::{Client, Error, Url};
+use reqwest::{
+    Client, Method, Response, StatusCode, Url,
+    header::{AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE},
+};
+use serde::{Serialize, de::DeserializeOwned};
+use snapshot::{Capabilities, Snapshot};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use tls::{Fingerprint, PinningVerifier, TlsError, TlsVerification};
+use url::Position;
+
+/// An error communicating with the gateway.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The gateway rejected the request because the token is missing, invalid or has expired.
+    #[error("Unauthorized request to {path}, token may be invalid or expired")]
+    Unauthorized { path: String },
+    /// The endpoint doesn't exist, probably because the gateway's firmware doesn't support it.
+    #[error("Endpoint {path} not supported by gateway")]
+    NotSupported { path: String },
+    /// The gateway returned some other unsuccessful status.
+    #[error("Request to {path} failed with {status}: {body}")]
+    Status {
+        path: String,
+        status: StatusCode,
+        body: String,
+    },
+    /// There was an error connecting to the gateway or reading its response.
+    #[error("Error requesting {path}: {source}")]
+    Transport {
+        path: String,
+        #[source]
+        source: reqwest::Error,
+    },
+    /// The response from the gateway couldn't be parsed.
+    #[error("Error parsing response from {path}: {source}")]
+    Json {
+        path: String,
+        /// The raw response body.
+        body: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    /// The XML response from the gateway couldn't be parsed.
+    #[error("Error parsing XML response from {path}: {source}")]
+    Xml {
+        path: String,
+        /// The raw response body.
+        body: String,
+        #[source]
+        source: quick_xml::DeError,
+    },
+    /// The gateway presented a different certificate to the one pinned.
+    #[error(
+        "Gateway presented certificate with fingerprint {actual} but {expected} was expected; \
+         if the gateway's certificate has legitimately changed then pin the new one"
+    )]
+    CertificateMismatch {
+        expected: Fingerprint,
+        actual: Fingerprint,
+    },
+    /// There was an error setting up TLS verification.
+    #[error("TLS error: {0}")]
+    Tls(#[from] TlsError),
+    /// The path couldn't be joined to the base URL.
+    #[error("Invalid URL for {path}: {source}")]
+    Url {
+        path: String,
+        #[source]
+        source: url::ParseError,
+    },
+    /// There was an error obtaining or validating a token.
+    #[error("Authentication error: {0}")]
+    Auth(#[from] AuthError),
+}
 
 /// Client for the Enphase Envoy local API.
 #[derive(Clone, Debug)]
 pub struct Envoy {
     base_url: Url,
-    auth_token: String,
+    auth: Auth,
     client: Client,
+    verifier: Option<Arc<PinningVerifier>>,
+    /// The capabilities of the gateway, once they have been probed.
+    capabilities: Arc<Mutex<Option<Capabilities>>>,
+}
+
+/// How to authenticate to the gateway.
+#[derive(Clone, Debug)]
+enum Auth {
+    /// A fixed token supplied by the user.
+    Token(String),
+    /// A token obtained from Enlighten, which is renewed when it is about to expire.
+    Enlighten(Arc<EnlightenSession>),
+    /// HTTP digest authentication, for firmware older than D7.
+    Digest(Arc<DigestSession>),
+}
+
+#[derive(Debug)]
+struct EnlightenSession {
+    /// The client for Enlighten, which uses the usual public CAs rather than the gateway's TLS
+    /// verification.
+    client: Client,
+    credentials: EnlightenCredentials,
+    endpoints: EnlightenEndpoints,
+    token: Mutex<Token>,
 }
 
 impl Envoy {
     /// Constructs a new Enphase Envoy local API client with the given base URL and auth token.
+    ///
+    /// The gateway's certificate is trusted on first use. Use [`new_with_tls`](Self::new_with_tls)
+    /// to verify it some other way.
     pub fn new(base_url: Url, auth_token: &str) -> Self {
-        Self {
+        Self::new_with_tls(base_url, auth_token, &TlsVerification::TrustOnFirstUse)
+            .expect("Failed to build HTTP client")
+    }
+
+    /// Constructs a new Enphase Envoy local API client with the given base URL and auth token,
+    /// verifying the gateway's certificate as given.
+    pub fn new_with_tls(
+        base_url: Url,
+        auth_token: &str,
+        tls: &TlsVerification,
+    ) -> Result<Self, Error> {
+        let (client, verifier) = new_client(tls)?;
+        Ok(Self {
+            base_url,
+            auth: Auth::Token(auth_token.to_owned()),
+            client,
+            verifier,
+            capabilities: Default::default(),
+        })
+    }
+
+    /// Constructs a new Enphase Envoy local API client for a gateway with firmware older than D7,
+    /// which uses HTTP digest authentication rather than tokens.
+    ///
+    /// Use [`DigestCredentials::from_serial`] for the default password of the `envoy` or
+    /// `installer` user.
+    pub fn new_with_digest(
+        base_url: Url,
+        credentials: DigestCredentials,
+        tls: &TlsVerification,
+    ) -> Result<Self, Error> {
+        let (client, verifier) = new_client(tls)?;
+        Ok(Self {
             base_url,
-            auth_token: auth_token.to_owned(),
-            client: Client::builder()
-                .danger_accept_invalid_certs(true)
-                .build()
-                .unwrap(),
+            auth: Auth::Digest(Arc::new(DigestSession::new(credentials))),
+            client,
+            verifier,
+            capabilities: Default::default(),
+        })
+    }
+
+    /// Constructs a new Enphase Envoy local API client which obtains an owner token from
+    /// Enlighten with the given credentials, using the default Enlighten endpoints and trusting
+    /// the gateway's certificate on first use.
+    ///
+    /// The token is validated with the gateway, and renewed automatically shortly before it
+    /// expires.
+    pub async fn login(base_url: Url, credentials: EnlightenCredentials) -> Result<Self, Error> {
+        Self::login_with_options(
+            base_url,
+            credentials,
+            EnlightenEndpoints::default(),
+            &TlsVerification::TrustOnFirstUse,
+        )
+        .await
+    }
+
+    /// Like [`login`](Self::login), but with the given Enlighten endpoints rather than the
+    /// defaults, and verifying the gateway's certificate as given.
+    pub async fn login_with_options(
+        base_url: Url,
+        credentials: EnlightenCredentials,
+        endpoints: EnlightenEndpoints,
+        tls: &TlsVerification,
+    ) -> Result<Self, Error> {
+        let enlighten_client = Client::new();
+        let token = fetch_token(&enlighten_client, &credentials, &endpoints).await?;
+        let (client, verifier) = new_client(tls)?;
+        let envoy = Self {
+            base_url,
+            auth: Auth::Enlighten(Arc::new(EnlightenSession {
+                client: enlighten_client,
+                credentials,
+                endpoints,
+                token: Mutex::new(token.clone()),
+            })),
+            client,
+            verifier,
+            capabilities: Default::default(),
+        };
+        envoy.check_token(token.as_str()).await?;
+        Ok(envoy)
+    }
+
+    /// Returns the base URL of the gateway.
+    pub fn base_url(&self) -> &Url {
+        &self.base_url
+    }
+
+    /// Changes the base URL of the gateway, such as after discovering that its address has
+    /// changed. The token, session and any pinned certificate are kept.
+    pub fn set_base_url(&mut self, base_url: Url) {
+        self.base_url = base_url;
+    }
+
+    /// Returns the fingerprint of the gateway's certificate, if it is pinned.
+    ///
+    /// With [`TlsVerification::TrustOnFirstUse`] this is `None` until the first connection to the
+    /// gateway. Store it and pass it to [`TlsVerification::Pinned`] in future to keep trusting the
+    /// same certificate.
+    pub fn certificate_fingerprint(&self) -> Option<Fingerprint> {
+        self.verifier
+            .as_ref()
+            .and_then(|verifier| verifier.pinned())
+    }
+
+    /// Returns the token currently in use, if it was obtained from Enlighten.
+    pub fn token(&self) -> Option<Token> {
+        match &self.auth {
+            Auth::Token(_) | Auth::Digest(_) => None,
+            Auth::Enlighten(session) => Some(session.token.lock().unwrap().clone()),
         }
     }
 
-    /// Returns a summary of the gateway status.
-    pub async fn home(&self) -> Result<Home, Error> {
-        self.client
-            .get(self.base_url.join("home.json").unwrap())
+    /// Checks that the gateway accepts the given token.
+    ///
+    /// This also starts a session with the gateway, whose cookie is used for subsequent requests.
+    async fn check_token(&self, token: &str) -> Result<(), Error> {
+        const PATH: &str = "auth/check_jwt";
+        let response = self
+            .client
+            .get(self.url(PATH)?)
+            .bearer_auth(token)
             .send()
-            .await?
-            .error_for_status()?
-            .json()
             .await
+            .map_err(|source| self.transport_error(PATH, source))?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(())
+        } else {
+            Err(AuthError::TokenRejected { status }.into())
+        }
+    }
+
+    /// Returns the `Authorization` header value with which to authenticate a request for the given
+    /// URL, first renewing the token if necessary.
+    ///
+    /// With digest authentication this is `None` until the gateway has sent a challenge.
+    async fn authorization(&self, method: &Method, url: &Url) -> Result<Option<String>, Error> {
+        match &self.auth {
+            Auth::Token(token) => Ok(Some(format!("Bearer {token}"))),
+            Auth::Enlighten(session) => {
+                let token = session.token.lock().unwrap().clone();
+                if !token.needs_renewal(Utc::now()) {
+                    return Ok(Some(format!("Bearer {}", token.as_str())));
+                }
+                let token =
+                    fetch_token(&session.client, &session.credentials, &session.endpoints).await?;
+                self.check_token(token.as_str()).await?;
+                *session.token.lock().unwrap() = token.clone();
+                Ok(Some(format!("Bearer {}", token.as_str())))
+            }
+            Auth::Digest(session) => {
+                Ok(session.authorization(method.as_str(), &url[Position::BeforePath..]))
+            }
+        }
+    }
+
+    /// Sends a request to the given URL with an optional JSON body, optionally with
+    /// authentication.
+    async fn send(
+        &self,
+        method: &Method,
+        url: &Url,
+        path: &str,
+        body: Option<&str>,
+        authenticated: bool,
+    ) -> Result<Response, Error> {
+        let mut request = self.client.request(method.clone(), url.clone());
+        if let Some(body) = body {
+            request = request
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.to_owned());
+        }
+        if authenticated && let Some(authorization) = self.authorization(method, url).await? {
+            request = request.header(AUTHORIZATION, authorization);
+        }
+        request
+            .send()
+            .await
+            .map_err(|source| self.transport_error(path, source))
+    }
+
+    /// Converts an error making a request to the given path, checking whether it was caused by the
+    /// gateway's certificate not matching the pinned one.
+    fn transport_error(&self, path: &str, source: reqwest::Error) -> Error {
+        if let Some(verifier) = &self.verifier
+            && let Some(actual) = verifier.take_rejected()
+            && let Some(expected) = verifier.pinned()
+        {
+            return Error::CertificateMismatch { expected, actual };
+        }
+        Error::Transport {
+            path: path.to_owned(),
+            source,
+        }
+    }
+
+    /// Returns the URL for the given path, relative to the base URL.
+    fn url(&self, path: &str) -> Result<Url, Error> {
+        self.base_url.join(path).map_err(|source| Error::Url {
+            path: path.to_owned(),
+            source,
+        })
+    }
+
+    /// Makes a request to the given path with an optional JSON body, optionally with
+    /// authentication, and returns the response body if it was successful.
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&str>,
+        authenticated: bool,
+    ) -> Result<String, Error> {
+        let url = self.url(path)?;
+        let mut response = self.send(&method, &url, path, body, authenticated).await?;
+        // With digest authentication, answer a new challenge and try again.
+        if authenticated
+            && response.status() == StatusCode::UNAUTHORIZED
+            && let Auth::Digest(session) = &self.auth
+            && let Some(challenge) = response
+                .headers()
+                .get(WWW_AUTHENTICATE)
+                .and_then(|challenge| challenge.to_str().ok())
+            && session.set_challenge(challenge)
+        {
+            response = self.send(&method, &url, path, body, authenticated).await?;
+        }
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|source| self.transport_error(path, source))?;
+        let path = path.to_owned();
+        match status {
+            _ if status.is_success() => Ok(body),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(Error::Unauthorized { path }),
+            StatusCode::NOT_FOUND => Err(Error::NotSupported { path }),
+            _ => Err(Error::Status { path, status, body }),
+        }
+    }
+
+    /// Makes a GET request to the given path, optionally with authentication, and returns the
+    /// response body if it was successful.
+    async fn get(&self, path: &str, authenticated: bool) -> Result<String, Error> {
+        self.request(Method::GET, path, None, authenticated).await
+    }
+
+    /// Makes a GET request to the given path, optionally with authentication, and parses the
+    /// response as JSON.
+    async fn get_json<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        authenticated: bool,
+    ) -> Result<T, Error> {
+        let body = self.get(path, authenticated).await?;
+        parse_json(path, body)
+    }
+
+    /// Makes an authenticated POST request to the given path with the given JSON body, and parses
+    /// the response as JSON.
+    async fn post_json<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &impl Serialize,
+    ) -> Result<T, Error> {
+        let body = serde_json::to_string(body).expect("Request body should serialize");
+        let response = self.request(Method::POST, path, Some(&body), true).await?;
+        parse_json(path, response)
+    }
+
+    /// Makes an unauthenticated GET request to the given path and parses the response as XML.
+    async fn get_xml<T: DeserializeOwned>(&self, path: &str) -> Result<T, Error> {
+        let body = self.get(path, false).await?;
+        quick_xml::de::from_str(&body).map_err(|source| Error::Xml {
+            path: path.to_owned(),
+            body,
+            source,
+        })
+    }
+
+    /// Returns information about the gateway such as its serial number and firmware version.
+    ///
+    /// This doesn't need a token.
+    pub async fn info(&self) -> Result<Info, Error> {
+        self.get_xml("info.xml").await
+    }
+
+    /// Returns the gateway's firmware version and which APIs it supports.
+    ///
+    /// The gateway is only probed the first time this is called, after which the result is
+    /// cached. An error is returned if the gateway rejects the token for `api/v1/production`, but
+    /// other endpoints which reject it are treated as unsupported, as some firmware restricts them
+    /// to installers.
+    pub async fn capabilities(&self) -> Result<Capabilities, Error> {
+        if let Some(capabilities) = self.capabilities.lock().unwrap().clone() {
+            return Ok(capabilities);
+        }
+        let firmware_version = match self.info().await {
+            Ok(info) => info.firmware_version(),
+            Err(Error::NotSupported { .. } | Error::Xml { .. }) => None,
+            Err(e) => return Err(e),
+        };
+        match self.get("api/v1/production", true).await {
+            Ok(_) | Err(Error::NotSupported { .. }) => {}
+            Err(e) => return Err(e),
+        }
+        let capabilities = Capabilities {
+            firmware_version,
+            meter_reports: self.supports("ivp/meters/reports").await?,
+            device_data: self.supports("ivp/pdm/device_data").await?,
+            inverters: self.supports("api/v1/production/inverters").await?,
+        };
+        *self.capabilities.lock().unwrap() = Some(capabilities.clone());
+        Ok(capabilities)
+    }
+
+    /// Returns whether the gateway supports the given endpoint, and allows access to it with the
+    /// token.
+    async fn supports(&self, path: &str) -> Result<bool, Error> {
+        match self.get(path, true).await {
+            Ok(_) => Ok(true),
+            Err(Error::NotSupported { .. } | Error::Unauthorized { .. }) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns production, consumption and per-inverter data from the best sources supported by
+    /// the gateway.
+    ///
+    /// Meter reports are preferred for production and consumption, falling back to
+    /// `production.json`. Per-inverter data comes from device data if available, otherwise from
+    /// the v1 inverters API.
+    pub async fn snapshot(&self) -> Result<Snapshot, Error> {
+        let capabilities = self.capabilities().await?;
+        let mut snapshot = Snapshot {
+            firmware_version: capabilities.firmware_version,
+            ..Default::default()
+        };
+        if capabilities.meter_reports {
+            snapshot.apply_meter_reports(&self.meter_reports().await?);
+        }
+        if snapshot.missing_totals() {
+            match self.production().await {
+                Ok(production) => snapshot.apply_production(&production),
+                // Some D8 firmware no longer serves production.json.
+                Err(Error::NotSupported { .. }) if snapshot.production.is_some() => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if capabilities.device_data {
+            snapshot.apply_device_data(&self.device_data().await?);
+        } else if capabilities.inverters {
+            snapshot.apply_inverters(&self.inverters().await?);
+        }
+        Ok(snapshot)
+    }
+
+    /// Gets data about devices from the power distribution manager, available on D8 firmware.
+    pub async fn device_data(&self) -> Result<Vec<PdmDevice>, Error> {
+        let response = self
+            .get_json::<BTreeMap<String, serde_json::Value>>("ivp/pdm/device_data", true)
+            .await?;
+        Ok(parse_devices(response))
+    }
+
+    /// Returns a summary of the gateway status.
+    pub async fn home(&self) -> Result<Home, Error> {
+        self.get_json("home.json", false).await
     }
 
     /// Returns an inventory of devices in the system.
     pub async fn inventory(&self, include_deleted: bool) -> Result<Inventory, Error> {
-        let mut url = self.base_url.join("inventory.json").unwrap();
-        url.set_query(Some(&format!(
-            "deleted={}",
+        let path = format!(
+            "inventory.json?deleted={}",
             if include_deleted { 1 } else { 0 }
-        )));
-        self.client
-            .get(url)
-            .bearer_auth(&self.auth_token)
-            .send()
-            .await?
-            .error_for_status()?
-            .json()
-            .await
+        );
+        self.get_json(&path, true).await
     }
 
     /// Returns statistics about current and past production and consumption.
     pub async fn production(&self) -> Result<Production, Error> {
-        self.client
-            .get(self.base_url.join("production.json?details=1").unwrap())
-            .bearer_auth(&self.auth_token)
-            .send()
-            .await?
-            .error_for_status()?
-            .json()
-            .await
+        self.get_json("production.json?details=1", true).await
     }
 
     /// Gets readings from all meters.
     pub async fn meter_readings(&self) -> Result<Vec<Reading>, Error> {
-        self.client
-            .get(self.base_url.join("ivp/meters/readings").unwrap())
-            .bearer_auth(&self.auth_token)
-            .send()
-            .await?
-            .error_for_status()?
-            .json()
-            .await
+        self.get_json("ivp/meters/readings", true).await
+    }
+
+    /// Gets the configuration of all meters, including what each one measures.
+    pub async fn meters(&self) -> Result<Vec<Meter>, Error> {
+        self.get_json("ivp/meters", true).await
+    }
+
+    /// Gets readings from all meters along with each meter's configuration.
+    pub async fn configured_meter_readings(&self) -> Result<Vec<ConfiguredReading>, Error> {
+        let meters = self.meters().await?;
+        let readings = self.meter_readings().await?;
+        Ok(join_readings(&meters, readings))
     }
 
     /// Gets reports from all meters.
     pub async fn meter_reports(&self) -> Result<Vec<Report>, Error> {
-        self.client
-            .get(self.base_url.join("ivp/meters/reports").unwrap())
-            .bearer_auth(&self.auth_token)
-            .send()
-            .await?
-            .error_for_status()?
-            .json()
-            .await
+        self.get_json("ivp/meters/reports", true).await
     }
 
     /// Gets individual inverter production data from the v1 API.
     pub async fn inverters(&self) -> Result<Vec<Inverter>, Error> {
-        self.client
-            .get(self.base_url.join("api/v1/production/inverters").unwrap())
-            .bearer_auth(&self.auth_token)
-            .send()
-            .await?
-            .error_for_status()?
-            .json()
-            .await
+        self.get_json("api/v1/production/inverters", true).await
+    }
+
+    /// Gets the inventory of IQ Batteries and IQ System Controllers.
+    pub async fn ensemble_inventory(&self) -> Result<EnsembleInventory, Error> {
+        self.get_json("ivp/ensemble/inventory", true).await
+    }
+
+    /// Gets the status of the ensemble, including the aggregate battery state and grid relay.
+    pub async fn ensemble_status(&self) -> Result<EnsembleStatus, Error> {
+        self.get_json("ivp/ensemble/status", true).await
+    }
+
+    /// Gets the aggregate state of charge and capacity of the storage system.
+    pub async fn ensemble_secctrl(&self) -> Result<SecCtrl, Error> {
+        self.get_json("ivp/ensemble/secctrl", true).await
+    }
+
+    /// Gets the power flowing into or out of each IQ Battery.
+    pub async fn ensemble_power(&self) -> Result<EnsemblePower, Error> {
+        self.get_json("ivp/ensemble/power", true).await
+    }
+
+    /// Gets the state of charge, temperature, power and capacity of each IQ Battery, combining the
+    /// ensemble inventory and power.
+    pub async fn batteries(&self) -> Result<Vec<Battery>, Error> {
+        let inventory = self.ensemble_inventory().await?;
+        let power = self.ensemble_power().await?;
+        Ok(join_batteries(&inventory, &power))
+    }
+
+    /// Gets the current live data, including real power per phase for PV, storage, grid, load and
+    /// generator.
+    ///
+    /// The readings are only kept up to date while the live data stream is enabled; see
+    /// [`Envoy::set_live_stream`] and [`Envoy::live_data_poller`].
+    pub async fn live_status(&self) -> Result<LiveStatus, Error> {
+        self.get_json("ivp/livedata/status", true).await
+    }
+
+    /// Enables or disables the live data stream, returning its new state.
+    ///
+    /// The gateway disables the stream again by itself after a while.
+    pub async fn set_live_stream(&self, enable: bool) -> Result<StreamState, Error> {
+        let response: StreamResponse = self
+            .post_json(
+                "ivp/livedata/stream",
+                &StreamRequest {
+                    enable: enable.into(),
+                },
+            )
+            .await?;
+        Ok(response.sc_stream)
+    }
+
+    /// Returns a poller which gets live data every `period`, keeping the live data stream
+    /// enabled.
+    pub fn live_data_poller(&self, period: Duration) -> LiveDataPoller<'_> {
+        LiveDataPoller::new(self, period)
+    }
+}
+
+/// Parses the response body from the given path as JSON.
+fn parse_json<T: DeserializeOwned>(path: &str, body: String) -> Result<T, Error> {
+    serde_json::from_str(&body).map_err(|source| Error::Json {
+        path: path.to_owned(),
+        body,
+        source,
+    })
+}
+
+fn new_client(tls: &TlsVerification) -> Result<(Client, Option<Arc<PinningVerifier>>), Error> {
+    Ok(tls.client(Client::builder().cookie_store(true))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use auth::tests::{credentials, enlighten_stub, enlighten_stub_sequence, jwt};
+    use chrono::TimeDelta;
+    use test_server::{Response, serve};
+
+    /// Starts a stub gateway which accepts the given tokens.
+    fn gateway_stub(valid_tokens: Vec<String>) -> Url {
+        serve(move |request| {
+            let authorized = request
+                .header("Authorization")
+                .is_some_and(|authorization| {
+                    valid_tokens
+                        .iter()
+                        .any(|token| authorization == format!("Bearer {token}"))
+                });
+            match request.path.as_str() {
+                _ if !authorized => Response::new(401, "Unauthorized"),
+                "/auth/check_jwt" => Response::new(200, "Valid token.")
+                    .with_header("Set-Cookie", "sessionId=abc; Path=/"),
+                "/ivp/meters/readings" => Response::json("[]"),
+                "/ivp/meters/reports" => Response::json(r#"{"unexpected": true}"#),
+                "/api/v1/production/inverters" => Response::new(500, "Internal error"),
+                _ => Response::new(404, "Not found"),
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn login() {
+        let token = jwt((Utc::now() + TimeDelta::days(365)).timestamp());
+        let endpoints = enlighten_stub(token.clone());
+        let envoy = Envoy::login_with_options(
+            gateway_stub(vec![token.clone()]),
+            credentials(),
+            endpoints,
+            &TlsVerification::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(envoy.token().unwrap().as_str(), token);
+        assert_eq!(envoy.meter_readings().await.unwrap(), vec![]);
+    }
+
+    #[tokio::test]
+    async fn errors() {
+        let token = jwt((Utc::now() + TimeDelta::days(365)).timestamp());
+        let base_url = gateway_stub(vec![token.clone()]);
+
+        let envoy = Envoy::new(base_url.clone(), "wrong");
+        assert!(matches!(
+            envoy.meter_readings().await.unwrap_err(),
+            Error::Unauthorized { path } if path == "ivp/meters/readings"
+        ));
+
+        let envoy = Envoy::new(base_url, &token);
+        assert!(matches!(
+            envoy.production().await.unwrap_err(),
+            Error::NotSupported { path } if path == "production.json?details=1"
+        ));
+        assert!(matches!(
+            envoy.inverters().await.unwrap_err(),
+            Error::Status { path, status, body }
+                if path == "api/v1/production/inverters" && status == 500 && body == "Internal error"
+        ));
+        assert!(matches!(
+            envoy.meter_reports().await.unwrap_err(),
+            Error::Json { path, body, .. }
+                if path == "ivp/meters/reports" && body == r#"{"unexpected": true}"#
+        ));
+
+        // Nothing is listening on port 1 of the loopback address.
+        let envoy = Envoy::new("http://127.0.0.1:1/".parse().unwrap(), &token);
+        assert!(matches!(
+            envoy.meter_readings().await.unwrap_err(),
+            Error::Transport { .. }
+        ));
+
+        let envoy = Envoy::new("data:text/plain,envoy".parse().unwrap(), &token);
+        assert!(matches!(
+            envoy.meter_readings().await.unwrap_err(),
+            Error::Url { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn token_rejected() {
+        let token = jwt((Utc::now() + TimeDelta::days(365)).timestamp());
+        let endpoints = enlighten_stub(token);
+        let error = Envoy::login_with_options(
+            gateway_stub(vec![]),
+            credentials(),
+            endpoints,
+            &TlsVerification::default(),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            Error::Auth(AuthError::TokenRejected { status }) if status == 401
+        ));
+    }
+
+    #[tokio::test]
+    async fn renew_expiring_token() {
+        let expiring = jwt((Utc::now() + TimeDelta::minutes(5)).timestamp());
+        let renewed = jwt((Utc::now() + TimeDelta::days(365)).timestamp());
+        let envoy = Envoy::login_with_options(
+            gateway_stub(vec![expiring.clone(), renewed.clone()]),
+            credentials(),
+            enlighten_stub_sequence(vec![expiring.clone(), renewed.clone()]),
+            &TlsVerification::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(envoy.token().unwrap().as_str(), expiring);
+
+        assert_eq!(envoy.meter_readings().await.unwrap(), vec![]);
+        assert_eq!(envoy.token().unwrap().as_str(), renewed);
     }
 }
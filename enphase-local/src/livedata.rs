@@ -0,0 +1,267 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Types returned by the IVP live data API, which gives power flows updated about once a second
+//! while streaming is enabled.
+
+use crate::{Envoy, Error};
+use chrono::{DateTime, Utc, serde::ts_seconds};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::time::{Interval, MissedTickBehavior, interval};
+
+/// The current live data, from `ivp/livedata/status`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct LiveStatus {
+    pub connection: LiveConnection,
+    pub meters: LiveMeters,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct LiveConnection {
+    pub mqtt_state: Option<String>,
+    pub prov_state: Option<String>,
+    pub auth_state: Option<String>,
+    /// Whether live data is being streamed, so that the meter readings are up to date.
+    pub sc_stream: StreamState,
+}
+
+/// Whether the live data stream is enabled.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamState {
+    Enabled,
+    Disabled,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct LiveMeters {
+    /// When the readings were last updated.
+    #[serde(with = "ts_seconds")]
+    pub last_update: DateTime<Utc>,
+    /// The aggregate state of charge of all batteries as a percentage.
+    pub soc: Option<u8>,
+    pub main_relay_state: Option<u32>,
+    pub gen_relay_state: Option<u32>,
+    /// The number of phases.
+    pub phase_count: u8,
+    /// Whether the site has a split-phase supply.
+    #[serde(default)]
+    pub is_split_phase: u8,
+    /// The aggregate state of charge of IQ Batteries as a percentage.
+    pub enc_agg_soc: Option<u8>,
+    /// The energy stored in IQ Batteries, in watt-hours.
+    pub enc_agg_energy: Option<u32>,
+    /// The aggregate state of charge of AC Batteries as a percentage.
+    pub acb_agg_soc: Option<u8>,
+    /// The energy stored in AC Batteries, in watt-hours.
+    pub acb_agg_energy: Option<u32>,
+    /// Power produced by the PV system.
+    pub pv: PowerFlow,
+    /// Power discharged from batteries, negative when charging.
+    pub storage: PowerFlow,
+    /// Power imported from the grid, negative when exporting.
+    pub grid: PowerFlow,
+    /// Power consumed by the load.
+    pub load: PowerFlow,
+    /// Power produced by a generator.
+    #[serde(default)]
+    pub generator: PowerFlow,
+}
+
+/// Real and apparent power, in total and per phase.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct PowerFlow {
+    /// Total real power in milliwatts.
+    pub agg_p_mw: i64,
+    /// Total apparent power in millivolt-amps.
+    pub agg_s_mva: i64,
+    /// Real power on phase A in milliwatts.
+    pub agg_p_ph_a_mw: i64,
+    /// Real power on phase B in milliwatts.
+    pub agg_p_ph_b_mw: i64,
+    /// Real power on phase C in milliwatts.
+    pub agg_p_ph_c_mw: i64,
+    /// Apparent power on phase A in millivolt-amps.
+    pub agg_s_ph_a_mva: i64,
+    /// Apparent power on phase B in millivolt-amps.
+    pub agg_s_ph_b_mva: i64,
+    /// Apparent power on phase C in millivolt-amps.
+    pub agg_s_ph_c_mva: i64,
+}
+
+impl PowerFlow {
+    /// Returns the total real power in watts.
+    pub fn watts(&self) -> f64 {
+        self.agg_p_mw as f64 / 1000.0
+    }
+
+    /// Returns the real power on each phase in watts.
+    pub fn phase_watts(&self) -> [f64; 3] {
+        [self.agg_p_ph_a_mw, self.agg_p_ph_b_mw, self.agg_p_ph_c_mw].map(|mw| mw as f64 / 1000.0)
+    }
+}
+
+/// The request body to enable or disable the live data stream.
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct StreamRequest {
+    pub enable: u8,
+}
+
+/// The response to enabling or disabling the live data stream.
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct StreamResponse {
+    pub sc_stream: StreamState,
+}
+
+/// Polls live data at a fixed period, enabling the stream whenever the gateway reports that it has
+/// stopped.
+///
+/// The gateway stops streaming a while after it was enabled, after which the readings are no longer
+/// updated. Create one with [`Envoy::live_data_poller`].
+#[derive(Debug)]
+pub struct LiveDataPoller<'a> {
+    envoy: &'a Envoy,
+    interval: Interval,
+}
+
+impl<'a> LiveDataPoller<'a> {
+    pub(crate) fn new(envoy: &'a Envoy, period: Duration) -> Self {
+        let mut interval = interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Self { envoy, interval }
+    }
+
+    /// Waits until the next poll is due, then returns the current live data.
+    ///
+    /// If the stream isn't enabled then it is enabled and the live data read again, though it may
+    /// not be up to date until the next poll.
+    pub async fn next(&mut self) -> Result<LiveStatus, Error> {
+        self.interval.tick().await;
+        let status = self.envoy.live_status().await?;
+        if status.connection.sc_stream == StreamState::Enabled {
+            return Ok(status);
+        }
+        self.envoy.set_live_stream(true).await?;
+        self.envoy.live_status().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{Response, serve};
+    use std::sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    };
+
+    /// Returns an example live data status with the given stream state.
+    fn status(stream: &str) -> String {
+        format!(
+            r#"{{
+    "connection": {{
+        "mqtt_state": "connected",
+        "prov_state": "configured",
+        "auth_state": "ok",
+        "sc_stream": "{stream}",
+        "sc_debug": "disabled"
+    }},
+    "meters": {{
+        "last_update": 1701084345,
+        "soc": 81,
+        "main_relay_state": 1,
+        "gen_relay_state": 5,
+        "backup_bat_mode": 1,
+        "backup_soc": 30,
+        "is_split_phase": 0,
+        "phase_count": 1,
+        "enc_agg_soc": 81,
+        "enc_agg_energy": 2835,
+        "acb_agg_soc": 0,
+        "acb_agg_energy": 0,
+        "pv": {{
+            "agg_p_mw": 2153000, "agg_s_mva": 2187000,
+            "agg_p_ph_a_mw": 2153000, "agg_p_ph_b_mw": 0, "agg_p_ph_c_mw": 0,
+            "agg_s_ph_a_mva": 2187000, "agg_s_ph_b_mva": 0, "agg_s_ph_c_mva": 0
+        }},
+        "storage": {{
+            "agg_p_mw": -1072000, "agg_s_mva": 1072000,
+            "agg_p_ph_a_mw": -1072000, "agg_p_ph_b_mw": 0, "agg_p_ph_c_mw": 0,
+            "agg_s_ph_a_mva": 1072000, "agg_s_ph_b_mva": 0, "agg_s_ph_c_mva": 0
+        }},
+        "grid": {{
+            "agg_p_mw": -250500, "agg_s_mva": 310000,
+            "agg_p_ph_a_mw": -250500, "agg_p_ph_b_mw": 0, "agg_p_ph_c_mw": 0,
+            "agg_s_ph_a_mva": 310000, "agg_s_ph_b_mva": 0, "agg_s_ph_c_mva": 0
+        }},
+        "load": {{
+            "agg_p_mw": 830500, "agg_s_mva": 905000,
+            "agg_p_ph_a_mw": 830500, "agg_p_ph_b_mw": 0, "agg_p_ph_c_mw": 0,
+            "agg_s_ph_a_mva": 905000, "agg_s_ph_b_mva": 0, "agg_s_ph_c_mva": 0
+        }}
+    }},
+    "tasks": {{"task_id": -1537581426, "timestamp": 1701084300}},
+    "counters": {{}},
+    "dry_contacts": {{}}
+}}"#
+        )
+    }
+
+    #[test]
+    fn parse_example() {
+        let status = serde_json::from_str::<LiveStatus>(&status("enabled")).unwrap();
+        assert_eq!(status.connection.sc_stream, StreamState::Enabled);
+        assert_eq!(
+            status.meters.last_update,
+            "2023-11-27T11:25:45Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        assert_eq!(status.meters.pv.watts(), 2153.0);
+        assert_eq!(status.meters.storage.watts(), -1072.0);
+        assert_eq!(status.meters.grid.phase_watts(), [-250.5, 0.0, 0.0]);
+        assert_eq!(status.meters.load.watts(), 830.5);
+        assert_eq!(status.meters.generator, PowerFlow::default());
+    }
+
+    #[tokio::test]
+    async fn poller_enables_stream() {
+        let streaming = Arc::new(AtomicBool::new(false));
+        let enables = Arc::new(AtomicUsize::new(0));
+        let base_url = {
+            let streaming = streaming.clone();
+            let enables = enables.clone();
+            serve(
+                move |request| match (request.method.as_str(), request.path.as_str()) {
+                    ("GET", "/ivp/livedata/status") => {
+                        if streaming.load(Ordering::SeqCst) {
+                            Response::json(&status("enabled"))
+                        } else {
+                            Response::json(&status("disabled"))
+                        }
+                    }
+                    ("POST", "/ivp/livedata/stream") if request.body == r#"{"enable":1}"# => {
+                        enables.fetch_add(1, Ordering::SeqCst);
+                        streaming.store(true, Ordering::SeqCst);
+                        Response::json(r#"{"sc_stream": "enabled"}"#)
+                    }
+                    _ => Response::new(404, "Not found"),
+                },
+            )
+        };
+        let envoy = Envoy::new(base_url, "token");
+        let mut poller = envoy.live_data_poller(Duration::from_millis(10));
+
+        let status = poller.next().await.unwrap();
+        assert_eq!(status.connection.sc_stream, StreamState::Enabled);
+        assert_eq!(enables.load(Ordering::SeqCst), 1);
+        poller.next().await.unwrap();
+        assert_eq!(enables.load(Ordering::SeqCst), 1);
+
+        // The gateway stops streaming after a while.
+        streaming.store(false, Ordering::SeqCst);
+        let status = poller.next().await.unwrap();
+        assert_eq!(status.connection.sc_stream, StreamState::Enabled);
+        assert_eq!(enables.load(Ordering::SeqCst), 2);
+    }
+}
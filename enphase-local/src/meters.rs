@@ -96,3 +96,177 @@ pub struct MeterReading {
     /// Frequency in Hertz.
     pub freq_hz: f64,
 }
+
+/// The configuration of a meter.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Meter {
+    /// Gateway record ID number, matching [`Channel::eid`] in the meter's readings.
+    pub eid: u64,
+    /// Whether the meter is enabled.
+    pub state: MeterState,
+    /// What the meter measures.
+    pub measurement_type: MeasurementType,
+    /// The phase configuration of the meter.
+    pub phase_mode: PhaseMode,
+    /// The number of phases measured.
+    pub phase_count: u8,
+    /// Whether the meter is measuring normally.
+    pub metering_status: MeteringStatus,
+    /// Any problems reported by the meter.
+    #[serde(default)]
+    pub status_flags: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MeterState {
+    Enabled,
+    Disabled,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PhaseMode {
+    Single,
+    Split,
+    Three,
+    /// Some other phase mode not yet known.
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MeteringStatus {
+    Normal,
+    NotMetering,
+    CheckWiring,
+    /// Some other status not yet known.
+    #[serde(other)]
+    Other,
+}
+
+/// A reading along with the configuration of the meter which took it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ConfiguredReading {
+    pub meter: Meter,
+    pub reading: Reading,
+}
+
+/// Joins readings to the configuration of the meters which took them, by eid.
+///
+/// Readings from meters which aren't in `meters` are left out.
+pub fn join_readings(meters: &[Meter], readings: Vec<Reading>) -> Vec<ConfiguredReading> {
+    readings
+        .into_iter()
+        .filter_map(|reading| {
+            let meter = meters
+                .iter()
+                .find(|meter| meter.eid == reading.summary.eid)?;
+            Some(ConfiguredReading {
+                meter: meter.clone(),
+                reading,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn join_meters() {
+        let meters = serde_json::from_str::<Vec<Meter>>(
+            r#"[
+    {
+        "eid": 704643328,
+        "state": "enabled",
+        "measurementType": "production",
+        "phaseMode": "single",
+        "phaseCount": 1,
+        "meteringStatus": "normal",
+        "statusFlags": []
+    },
+    {
+        "eid": 704643584,
+        "state": "enabled",
+        "measurementType": "net-consumption",
+        "phaseMode": "single",
+        "phaseCount": 1,
+        "meteringStatus": "check-wiring",
+        "statusFlags": ["negative-production"]
+    }
+]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            meters[1],
+            Meter {
+                eid: 704643584,
+                state: MeterState::Enabled,
+                measurement_type: MeasurementType::NetConsumption,
+                phase_mode: PhaseMode::Single,
+                phase_count: 1,
+                metering_status: MeteringStatus::CheckWiring,
+                status_flags: vec!["negative-production".to_owned()],
+            }
+        );
+
+        let reading = |eid| Reading {
+            summary: Channel {
+                eid,
+                ..Default::default()
+            },
+            channels: vec![],
+        };
+        let joined = join_readings(
+            &meters,
+            vec![reading(704643584), reading(704643328), reading(1)],
+        );
+        assert_eq!(joined.len(), 2);
+        assert_eq!(
+            joined[0].meter.measurement_type,
+            MeasurementType::NetConsumption
+        );
+        assert_eq!(joined[0].reading, reading(704643584));
+        assert_eq!(
+            joined[1].meter.measurement_type,
+            MeasurementType::Production
+        );
+    }
+
+    /// Storage and backfeed meters, and phase modes and statuses which aren't known yet, should
+    /// still be parsed.
+    #[test]
+    fn parse_other_meters() {
+        let meters = serde_json::from_str::<Vec<Meter>>(
+            r#"[
+    {
+        "eid": 704643840,
+        "state": "enabled",
+        "measurementType": "storage",
+        "phaseMode": "single",
+        "phaseCount": 1,
+        "meteringStatus": "normal",
+        "statusFlags": []
+    },
+    {
+        "eid": 704644096,
+        "state": "disabled",
+        "measurementType": "backfeed",
+        "phaseMode": "four",
+        "phaseCount": 1,
+        "meteringStatus": "calibrating",
+        "statusFlags": []
+    }
+]"#,
+        )
+        .unwrap();
+        assert_eq!(meters[0].measurement_type, MeasurementType::Storage);
+        assert_eq!(meters[1].measurement_type, MeasurementType::Backfeed);
+        assert_eq!(meters[1].phase_mode, PhaseMode::Other);
+        assert_eq!(meters[1].metering_status, MeteringStatus::Other);
+    }
+}
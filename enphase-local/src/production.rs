@@ -93,6 +93,10 @@ pub enum MeasurementType {
     NetConsumption,
     /// Total load.
     TotalConsumption,
+    /// Power flowing into or out of batteries.
+    Storage,
+    /// Power fed back from a backup source such as a generator.
+    Backfeed,
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
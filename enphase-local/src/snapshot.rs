@@ -0,0 +1,419 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A normalised view of production, consumption and per-inverter data, from whichever APIs the
+//! gateway's firmware supports.
+
+use crate::{
+    device_data::PdmDevice,
+    info::FirmwareVersion,
+    inverters::Inverter,
+    meters::Report,
+    production::{Device, DeviceType, MeasurementType, Production},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// The first firmware version which requires a token to access the local API.
+pub const TOKEN_FIRMWARE_VERSION: FirmwareVersion = FirmwareVersion::new(7, 0, 0);
+
+/// The firmware version and APIs supported by a gateway.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Capabilities {
+    /// The gateway's firmware version, if known.
+    pub firmware_version: Option<FirmwareVersion>,
+    /// Whether `ivp/meters/reports` is supported.
+    pub meter_reports: bool,
+    /// Whether `ivp/pdm/device_data` is supported.
+    pub device_data: bool,
+    /// Whether `api/v1/production/inverters` is supported.
+    pub inverters: bool,
+}
+
+impl Capabilities {
+    /// Returns whether the gateway's firmware requires a token to access the local API.
+    ///
+    /// If the firmware version is unknown then a token is assumed to be required.
+    pub fn requires_token(&self) -> bool {
+        self.firmware_version
+            .is_none_or(|version| version >= TOKEN_FIRMWARE_VERSION)
+    }
+}
+
+/// The API from which a reading was taken.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    /// `ivp/meters/reports`.
+    MeterReports,
+    /// The integrated meter in `production.json`.
+    ProductionMeter,
+    /// The inverters total in `production.json`, for systems without a production meter.
+    ProductionInverters,
+    /// `ivp/pdm/device_data`.
+    DeviceData,
+    /// `api/v1/production/inverters`.
+    InvertersApi,
+}
+
+/// A total power reading.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PowerReading {
+    /// When the reading was taken.
+    pub timestamp: DateTime<Utc>,
+    /// The current power in watts.
+    pub watts: f64,
+    /// The total energy in watt-hours over the lifetime of the meter, if known.
+    pub lifetime_wh: Option<f64>,
+    /// The API from which the reading was taken.
+    pub source: Source,
+}
+
+/// A reading from a single microinverter.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct InverterReading {
+    /// The serial number of the microinverter.
+    pub serial_number: String,
+    /// When the microinverter last reported.
+    pub timestamp: DateTime<Utc>,
+    /// The power in watts last reported.
+    pub watts: f64,
+    /// The maximum power in watts which the microinverter has reported, if known.
+    pub max_watts: Option<f64>,
+    /// The total energy in watt-hours which the microinverter has produced, if known.
+    pub lifetime_wh: Option<f64>,
+    /// The API from which the reading was taken.
+    pub source: Source,
+}
+
+/// Production, consumption and per-inverter data from the best sources available.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Snapshot {
+    /// The gateway's firmware version, if known.
+    pub firmware_version: Option<FirmwareVersion>,
+    /// Total solar production.
+    pub production: Option<PowerReading>,
+    /// Total consumption by the load, if there is a consumption meter.
+    pub total_consumption: Option<PowerReading>,
+    /// Consumption from the grid, minus export to it, if there is a consumption meter.
+    pub net_consumption: Option<PowerReading>,
+    /// Readings from individual microinverters.
+    pub inverters: Vec<InverterReading>,
+}
+
+impl Snapshot {
+    /// Returns whether any of the production or consumption readings are still missing.
+    pub(crate) fn missing_totals(&self) -> bool {
+        self.production.is_none()
+            || self.total_consumption.is_none()
+            || self.net_consumption.is_none()
+    }
+
+    /// Fills in any missing production or consumption readings from the given meter reports.
+    pub(crate) fn apply_meter_reports(&mut self, reports: &[Report]) {
+        for report in reports {
+            let cumulative = &report.cumulative;
+            let (slot, lifetime_wh) = match report.report_type {
+                MeasurementType::Production => (&mut self.production, cumulative.wh_dlvd_cum),
+                MeasurementType::TotalConsumption => {
+                    (&mut self.total_consumption, cumulative.wh_dlvd_cum)
+                }
+                MeasurementType::NetConsumption => (
+                    &mut self.net_consumption,
+                    cumulative.wh_dlvd_cum - cumulative.wh_rcvd_cum,
+                ),
+                MeasurementType::Storage | MeasurementType::Backfeed => continue,
+            };
+            slot.get_or_insert(PowerReading {
+                timestamp: report.created_at,
+                watts: cumulative.curr_w,
+                lifetime_wh: Some(lifetime_wh),
+                source: Source::MeterReports,
+            });
+        }
+    }
+
+    /// Fills in any missing production or consumption readings from `production.json`.
+    ///
+    /// Integrated meter readings are only used if the meter is active, as unmetered systems still
+    /// include them but with meaningless values. Production falls back to the inverters total.
+    pub(crate) fn apply_production(&mut self, production: &Production) {
+        for device in production.production.iter().chain(&production.consumption) {
+            if let Some(measurement_type) = device.measurement_type
+                && device.type_ == DeviceType::Eim
+                && device.active_count > 0
+            {
+                let slot = match measurement_type {
+                    MeasurementType::Production => &mut self.production,
+                    MeasurementType::TotalConsumption => &mut self.total_consumption,
+                    MeasurementType::NetConsumption => &mut self.net_consumption,
+                    MeasurementType::Storage | MeasurementType::Backfeed => continue,
+                };
+                slot.get_or_insert_with(|| power_reading(device, Source::ProductionMeter));
+            }
+        }
+        if self.production.is_none()
+            && let Some(device) = production
+                .production
+                .iter()
+                .find(|device| device.type_ == DeviceType::Inverters)
+        {
+            self.production = Some(power_reading(device, Source::ProductionInverters));
+        }
+    }
+
+    /// Sets the inverter readings from `ivp/pdm/device_data`.
+    pub(crate) fn apply_device_data(&mut self, devices: &[PdmDevice]) {
+        self.inverters = devices
+            .iter()
+            .filter(|device| device.is_inverter())
+            .filter_map(|device| {
+                let reading = &device.channels.first()?.last_reading;
+                Some(InverterReading {
+                    serial_number: device.serial_number.clone(),
+                    timestamp: reading.end_date,
+                    watts: reading.ac_power_mw? as f64 / 1000.0,
+                    max_watts: None,
+                    lifetime_wh: reading.joules_produced.map(|joules| joules / 3600.0),
+                    source: Source::DeviceData,
+                })
+            })
+            .collect();
+    }
+
+    /// Sets the inverter readings from `api/v1/production/inverters`.
+    pub(crate) fn apply_inverters(&mut self, inverters: &[Inverter]) {
+        self.inverters = inverters
+            .iter()
+            .map(|inverter| InverterReading {
+                serial_number: inverter.serial_number.clone(),
+                timestamp: inverter.last_report_date,
+                watts: inverter.last_report_watts.into(),
+                max_watts: Some(inverter.max_report_watts.into()),
+                lifetime_wh: None,
+                source: Source::InvertersApi,
+            })
+            .collect();
+    }
+}
+
+fn power_reading(device: &Device, source: Source) -> PowerReading {
+    PowerReading {
+        timestamp: device.reading_time,
+        watts: device.w_now,
+        lifetime_wh: device.details.as_ref().map(|details| details.wh_lifetime),
+        source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Envoy, Error,
+        test_server::{Response, serve},
+    };
+    use reqwest::Url;
+
+    const INFO_D5: &str = "<envoy_info><time>1701084345</time><device><sn>122212345678</sn>\
+        <pn>800-00555-r03</pn><software>R5.0.62</software></device></envoy_info>";
+    const INFO_D8: &str = "<envoy_info><time>1701084345</time><device><sn>122212345678</sn>\
+        <pn>800-00654-r08</pn><software>D8.2.4286</software></device>\
+        <web-tokens>true</web-tokens></envoy_info>";
+
+    /// Production from an unmetered system, with inactive integrated meters.
+    const PRODUCTION_UNMETERED: &str = r#"{
+        "production": [
+            {"type": "inverters", "activeCount": 10, "readingTime": 1701084300, "wNow": 225,
+             "whLifetime": 22444},
+            {"type": "eim", "activeCount": 0, "measurementType": "production",
+             "readingTime": 1701084345, "wNow": 0}
+        ],
+        "consumption": [
+            {"type": "eim", "activeCount": 0, "measurementType": "total-consumption",
+             "readingTime": 1701084345, "wNow": 0}
+        ],
+        "storage": []
+    }"#;
+
+    const METER_REPORTS: &str = r#"[
+        {"createdAt": 1701084345, "reportType": "production",
+         "cumulative": {"currW": 1500, "actPower": 1500, "apprntPwr": 1600, "reactPwr": 10,
+                        "whDlvdCum": 100000, "whRcvdCum": 0, "varhLagCum": 0, "varhLeadCum": 0,
+                        "vahCum": 0, "rmsVoltage": 240, "rmsCurrent": 6, "pwrFactor": 1,
+                        "freqHz": 50},
+         "lines": []},
+        {"createdAt": 1701084345, "reportType": "net-consumption",
+         "cumulative": {"currW": -1000, "actPower": -1000, "apprntPwr": 1000, "reactPwr": 0,
+                        "whDlvdCum": 5000, "whRcvdCum": 3000, "varhLagCum": 0, "varhLeadCum": 0,
+                        "vahCum": 0, "rmsVoltage": 240, "rmsCurrent": 4, "pwrFactor": 1,
+                        "freqHz": 50},
+         "lines": []},
+        {"createdAt": 1701084345, "reportType": "total-consumption",
+         "cumulative": {"currW": 500, "actPower": 500, "apprntPwr": 500, "reactPwr": 0,
+                        "whDlvdCum": 60000, "whRcvdCum": 0, "varhLagCum": 0, "varhLeadCum": 0,
+                        "vahCum": 0, "rmsVoltage": 240, "rmsCurrent": 2, "pwrFactor": 1,
+                        "freqHz": 50},
+         "lines": []}
+    ]"#;
+
+    const INVERTERS: &str = r#"[{"serialNumber": "482212345678", "lastReportDate": 1701084300,
+        "devType": 1, "lastReportWatts": 22, "maxReportWatts": 300}]"#;
+
+    const DEVICE_DATA: &str = r#"{
+        "1": {"devName": "pcu", "sn": "482212345678", "active": true, "modGone": false,
+              "channels": [{"chanEid": 1, "lastReading": {"eid": 1, "endDate": 1701084300,
+              "duration": 300, "acPowerINmW": 165710, "joulesProduced": 3600000}}]},
+        "deviceCount": 1
+    }"#;
+
+    /// Starts a stub gateway which serves the given responses, and 404 for anything else.
+    fn firmware_stub(responses: Vec<(&'static str, &'static str)>) -> Url {
+        serve(move |request| {
+            match responses
+                .iter()
+                .find(|(path, _)| request.path.split('?').next() == Some(path))
+            {
+                Some((_, body)) => Response::json(body),
+                None => Response::new(404, "Not found"),
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn snapshot_d5_unmetered() {
+        let envoy = Envoy::new(
+            firmware_stub(vec![
+                ("/info.xml", INFO_D5),
+                ("/production.json", PRODUCTION_UNMETERED),
+                ("/api/v1/production/inverters", INVERTERS),
+            ]),
+            "",
+        );
+        let capabilities = envoy.capabilities().await.unwrap();
+        assert_eq!(
+            capabilities,
+            Capabilities {
+                firmware_version: Some(FirmwareVersion::new(5, 0, 62)),
+                meter_reports: false,
+                device_data: false,
+                inverters: true,
+            }
+        );
+        assert!(!capabilities.requires_token());
+
+        let snapshot = envoy.snapshot().await.unwrap();
+        assert_eq!(
+            snapshot,
+            Snapshot {
+                firmware_version: Some(FirmwareVersion::new(5, 0, 62)),
+                production: Some(PowerReading {
+                    timestamp: "2023-11-27T11:25:00Z".parse().unwrap(),
+                    watts: 225.0,
+                    lifetime_wh: None,
+                    source: Source::ProductionInverters,
+                }),
+                total_consumption: None,
+                net_consumption: None,
+                inverters: vec![InverterReading {
+                    serial_number: "482212345678".to_owned(),
+                    timestamp: "2023-11-27T11:25:00Z".parse().unwrap(),
+                    watts: 22.0,
+                    max_watts: Some(300.0),
+                    lifetime_wh: None,
+                    source: Source::InvertersApi,
+                }],
+            }
+        );
+    }
+
+    /// Endpoints restricted to installers should be treated as unsupported, as long as the token
+    /// is accepted.
+    #[tokio::test]
+    async fn capabilities_restricted_endpoints() {
+        let envoy = Envoy::new(
+            serve(|request| match request.path.as_str() {
+                "/info.xml" => Response::json(INFO_D8),
+                "/api/v1/production" => Response::json("{}"),
+                "/api/v1/production/inverters" => Response::json(INVERTERS),
+                "/ivp/meters/reports" => Response::new(401, "Unauthorized"),
+                "/ivp/pdm/device_data" => Response::new(403, "Forbidden"),
+                _ => Response::new(404, "Not found"),
+            }),
+            "token",
+        );
+        assert_eq!(
+            envoy.capabilities().await.unwrap(),
+            Capabilities {
+                firmware_version: Some(FirmwareVersion::new(8, 2, 4286)),
+                meter_reports: false,
+                device_data: false,
+                inverters: true,
+            }
+        );
+    }
+
+    /// If the token itself is rejected then probing should fail.
+    #[tokio::test]
+    async fn capabilities_token_rejected() {
+        let envoy = Envoy::new(
+            serve(|request| match request.path.as_str() {
+                "/info.xml" => Response::json(INFO_D8),
+                _ => Response::new(401, "Unauthorized"),
+            }),
+            "token",
+        );
+        assert!(matches!(
+            envoy.capabilities().await,
+            Err(Error::Unauthorized { path }) if path == "api/v1/production"
+        ));
+    }
+
+    #[tokio::test]
+    async fn snapshot_d8_metered() {
+        let envoy = Envoy::new(
+            firmware_stub(vec![
+                ("/info.xml", INFO_D8),
+                ("/ivp/meters/reports", METER_REPORTS),
+                ("/ivp/pdm/device_data", DEVICE_DATA),
+                ("/api/v1/production/inverters", INVERTERS),
+            ]),
+            "token",
+        );
+        let snapshot = envoy.snapshot().await.unwrap();
+        let timestamp = "2023-11-27T11:25:45Z".parse().unwrap();
+        assert_eq!(
+            snapshot,
+            Snapshot {
+                firmware_version: Some(FirmwareVersion::new(8, 2, 4286)),
+                production: Some(PowerReading {
+                    timestamp,
+                    watts: 1500.0,
+                    lifetime_wh: Some(100000.0),
+                    source: Source::MeterReports,
+                }),
+                total_consumption: Some(PowerReading {
+                    timestamp,
+                    watts: 500.0,
+                    lifetime_wh: Some(60000.0),
+                    source: Source::MeterReports,
+                }),
+                net_consumption: Some(PowerReading {
+                    timestamp,
+                    watts: -1000.0,
+                    lifetime_wh: Some(2000.0),
+                    source: Source::MeterReports,
+                }),
+                inverters: vec![InverterReading {
+                    serial_number: "482212345678".to_owned(),
+                    timestamp: "2023-11-27T11:25:00Z".parse().unwrap(),
+                    watts: 165.71,
+                    max_watts: None,
+                    lifetime_wh: Some(1000.0),
+                    source: Source::DeviceData,
+                }],
+            }
+        );
+    }
+}
@@ -0,0 +1,162 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A minimal HTTP server for tests, standing in for the gateway or Enphase's cloud services.
+
+use reqwest::Url;
+use rustls::{
+    ServerConfig, ServerConnection, StreamOwned,
+    crypto::ring::default_provider,
+    pki_types::{CertificateDer, PrivateKeyDer},
+};
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    sync::Arc,
+    thread,
+};
+
+/// A request received by the stub server.
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub method: String,
+    /// The path including any query string.
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Request {
+    /// Returns the value of the header with the given name, if present.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A response for the stub server to send.
+#[derive(Clone, Debug)]
+pub struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Response {
+    /// A response with the given status and plain text body.
+    pub fn new(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            headers: vec![("Content-Type".to_owned(), "text/plain".to_owned())],
+            body: body.to_owned(),
+        }
+    }
+
+    /// A successful response with the given JSON body.
+    pub fn json(body: &str) -> Self {
+        Self::new(200, body).with_header("Content-Type", "application/json")
+    }
+
+    /// Adds the given header, replacing any existing header with the same name.
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers
+            .retain(|(header, _)| !header.eq_ignore_ascii_case(name));
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+}
+
+/// Starts a server on a loopback port which responds to every request with the result of
+/// `handler`, and returns its base URL.
+pub fn serve(handler: impl Fn(&Request) -> Response + Send + 'static) -> Url {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            respond(&mut stream, &handler);
+        }
+    });
+    url
+}
+
+/// Like [`serve`], but over TLS with the given certificate and private key.
+pub fn serve_tls(
+    certificate: CertificateDer<'static>,
+    key: PrivateKeyDer<'static>,
+    handler: impl Fn(&Request) -> Response + Send + 'static,
+) -> Url {
+    let config = Arc::new(
+        ServerConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![certificate], key)
+            .unwrap(),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = Url::parse(&format!("https://{}/", listener.local_addr().unwrap())).unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let connection = ServerConnection::new(config.clone()).unwrap();
+            respond(&mut StreamOwned::new(connection, stream), &handler);
+        }
+    });
+    url
+}
+
+/// Reads a single request from the stream and writes the handler's response to it.
+fn respond(stream: &mut (impl Read + Write), handler: &impl Fn(&Request) -> Response) {
+    let Some(request) = read_request(stream) else {
+        return;
+    };
+    let response = handler(&request);
+    let mut output = format!("HTTP/1.1 {} Stub\r\n", response.status);
+    for (name, value) in &response.headers {
+        output += &format!("{name}: {value}\r\n");
+    }
+    output += &format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.body.len(),
+        response.body
+    );
+    let _ = stream.write_all(output.as_bytes());
+    let _ = stream.flush();
+}
+
+fn read_request(stream: &mut impl Read) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).ok()?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next()?.to_owned();
+    let path = parts.next()?.to_owned();
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':')?;
+        headers.push((name.trim().to_owned(), value.trim().to_owned()));
+    }
+    let content_length = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("Content-Length"))
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).ok()?;
+
+    Some(Request {
+        method,
+        path,
+        headers,
+        body: String::from_utf8(body).ok()?,
+    })
+}
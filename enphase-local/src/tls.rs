@@ -0,0 +1,385 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Verifying the gateway's TLS certificate.
+//!
+//! Gateways use a self-signed certificate, so it can't be verified against the usual public CAs.
+//! Instead the certificate can be pinned by its fingerprint, either given in advance or trusted
+//! the first time it is seen, or verified against a CA or certificate supplied by the user.
+
+use reqwest::{Certificate, Client, ClientBuilder};
+use ring::digest::{SHA256, digest};
+use rustls::{
+    ClientConfig, DigitallySignedStruct, SignatureScheme,
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{
+        CryptoProvider, ring::default_provider, verify_tls12_signature, verify_tls13_signature,
+    },
+    pki_types::{CertificateDer, ServerName, UnixTime, pem::PemObject},
+};
+use std::{
+    fmt::{self, Debug, Display, Formatter},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+use thiserror::Error;
+
+/// An error configuring TLS verification.
+#[derive(Debug, Error)]
+pub enum TlsError {
+    /// A PEM file couldn't be parsed.
+    #[error("Error parsing PEM certificate: {0}")]
+    Pem(#[from] rustls::pki_types::pem::Error),
+    /// A fingerprint wasn't 32 hex-encoded bytes.
+    #[error("Invalid SHA-256 fingerprint {0:?}")]
+    InvalidFingerprint(String),
+    /// There was an error setting up the TLS client.
+    #[error("TLS configuration error: {0}")]
+    Rustls(#[from] rustls::Error),
+    /// There was an error building the HTTP client.
+    #[error("HTTP client error: {0}")]
+    Client(#[from] reqwest::Error),
+}
+
+/// The SHA-256 fingerprint of a DER-encoded certificate.
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+pub struct Fingerprint([u8; 32]);
+
+impl Fingerprint {
+    /// Calculates the fingerprint of the given DER-encoded certificate.
+    pub fn of_certificate(der: &[u8]) -> Self {
+        Self(digest(&SHA256, der).as_ref().try_into().unwrap())
+    }
+
+    /// Returns the raw bytes of the fingerprint.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl Display for Fingerprint {
+    /// Formats the fingerprint as colon-separated upper case hex, like `openssl x509 -fingerprint`.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(":")?;
+            }
+            write!(f, "{byte:02X}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for Fingerprint {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Fingerprint({self})")
+    }
+}
+
+impl FromStr for Fingerprint {
+    type Err = TlsError;
+
+    /// Parses a hex-encoded fingerprint, with or without colons between bytes.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || TlsError::InvalidFingerprint(s.to_owned());
+        let hex = s.trim().replace(':', "");
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut bytes = [0; 32];
+        for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            let pair = str::from_utf8(pair).map_err(|_| invalid())?;
+            *byte = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+        }
+        Ok(Self(bytes))
+    }
+}
+
+/// How to verify the gateway's TLS certificate.
+#[derive(Clone, Debug, Default)]
+pub enum TlsVerification {
+    /// Trust whatever certificate the gateway presents on the first connection, and only accept
+    /// that certificate from then on.
+    ///
+    /// The fingerprint can be retrieved with
+    /// [`Envoy::certificate_fingerprint`](crate::Envoy::certificate_fingerprint) and stored, to
+    /// pin it with [`Pinned`](Self::Pinned) in future.
+    #[default]
+    TrustOnFirstUse,
+    /// Only accept a certificate with the given fingerprint.
+    Pinned(Fingerprint),
+    /// Verify the certificate and hostname against the given CA certificates.
+    CaCertificates(Vec<CertificateDer<'static>>),
+    /// Accept any certificate.
+    ///
+    /// This allows anyone able to intercept traffic to the gateway to steal the token, so should
+    /// only be used if you are sure your network is trusted.
+    Insecure,
+}
+
+impl TlsVerification {
+    /// Pins the first certificate in the given PEM data.
+    pub fn pinned_certificate_pem(pem: &[u8]) -> Result<Self, TlsError> {
+        let certificate = CertificateDer::from_pem_slice(pem)?;
+        Ok(Self::Pinned(Fingerprint::of_certificate(&certificate)))
+    }
+
+    /// Verifies against all the CA certificates in the given PEM data.
+    pub fn ca_certificates_pem(pem: &[u8]) -> Result<Self, TlsError> {
+        let certificates = CertificateDer::pem_slice_iter(pem).collect::<Result<Vec<_>, _>>()?;
+        if certificates.is_empty() {
+            return Err(rustls::pki_types::pem::Error::NoItemsFound.into());
+        }
+        Ok(Self::CaCertificates(certificates))
+    }
+
+    /// Builds an HTTP client for the gateway which verifies its certificate in this way.
+    ///
+    /// Also returns the pinning verifier if one is used, to find out which certificate was trusted
+    /// or rejected.
+    pub(crate) fn client(
+        &self,
+        builder: ClientBuilder,
+    ) -> Result<(Client, Option<Arc<PinningVerifier>>), TlsError> {
+        let (builder, verifier) = match self {
+            Self::TrustOnFirstUse => {
+                let verifier = Arc::new(PinningVerifier::new(None));
+                (
+                    builder.use_preconfigured_tls(verifier.clone().client_config()?),
+                    Some(verifier),
+                )
+            }
+            Self::Pinned(fingerprint) => {
+                let verifier = Arc::new(PinningVerifier::new(Some(*fingerprint)));
+                (
+                    builder.use_preconfigured_tls(verifier.clone().client_config()?),
+                    Some(verifier),
+                )
+            }
+            Self::CaCertificates(certificates) => {
+                let mut builder = builder.tls_built_in_root_certs(false);
+                for certificate in certificates {
+                    builder = builder.add_root_certificate(Certificate::from_der(certificate)?);
+                }
+                (builder, None)
+            }
+            Self::Insecure => (builder.danger_accept_invalid_certs(true), None),
+        };
+        Ok((builder.build()?, verifier))
+    }
+}
+
+/// A certificate verifier which only accepts a single certificate, identified by its fingerprint.
+///
+/// If no fingerprint is given then the first certificate seen is pinned.
+#[derive(Debug)]
+pub(crate) struct PinningVerifier {
+    provider: Arc<CryptoProvider>,
+    state: Mutex<PinningState>,
+}
+
+#[derive(Debug, Default)]
+struct PinningState {
+    pinned: Option<Fingerprint>,
+    /// The fingerprint of the most recent certificate which didn't match the pinned one.
+    rejected: Option<Fingerprint>,
+}
+
+impl PinningVerifier {
+    fn new(pinned: Option<Fingerprint>) -> Self {
+        Self {
+            provider: Arc::new(default_provider()),
+            state: Mutex::new(PinningState {
+                pinned,
+                rejected: None,
+            }),
+        }
+    }
+
+    fn client_config(self: Arc<Self>) -> Result<ClientConfig, rustls::Error> {
+        Ok(ClientConfig::builder_with_provider(self.provider.clone())
+            .with_safe_default_protocol_versions()?
+            .dangerous()
+            .with_custom_certificate_verifier(self)
+            .with_no_client_auth())
+    }
+
+    /// Returns the fingerprint of the pinned certificate, if any.
+    pub fn pinned(&self) -> Option<Fingerprint> {
+        self.state.lock().unwrap().pinned
+    }
+
+    /// Returns and clears the fingerprint of the last certificate rejected because it didn't
+    /// match, if any.
+    pub fn take_rejected(&self) -> Option<Fingerprint> {
+        self.state.lock().unwrap().rejected.take()
+    }
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let fingerprint = Fingerprint::of_certificate(end_entity);
+        let mut state = self.state.lock().unwrap();
+        match state.pinned {
+            None => {
+                state.pinned = Some(fingerprint);
+                Ok(ServerCertVerified::assertion())
+            }
+            Some(pinned) if pinned == fingerprint => Ok(ServerCertVerified::assertion()),
+            Some(_) => {
+                state.rejected = Some(fingerprint);
+                Err(rustls::Error::InvalidCertificate(
+                    rustls::CertificateError::ApplicationVerificationFailure,
+                ))
+            }
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Envoy, Error,
+        test_server::{Response, serve_tls},
+    };
+    use rcgen::{CertifiedKey, generate_simple_self_signed};
+    use reqwest::Url;
+    use rustls::pki_types::PrivatePkcs8KeyDer;
+
+    /// Starts a stub gateway over TLS with a newly generated self-signed certificate, and returns
+    /// its URL and certificate.
+    fn tls_gateway_stub() -> (Url, rcgen::Certificate) {
+        let CertifiedKey { cert, signing_key } =
+            generate_simple_self_signed(vec!["127.0.0.1".to_owned()]).unwrap();
+        let url = serve_tls(
+            cert.der().clone(),
+            PrivatePkcs8KeyDer::from(signing_key.serialize_der()).into(),
+            |_| Response::json("[]"),
+        );
+        (url, cert)
+    }
+
+    #[tokio::test]
+    async fn trust_on_first_use() {
+        let (url, certificate) = tls_gateway_stub();
+        let envoy = Envoy::new(url.clone(), "token");
+        assert_eq!(envoy.certificate_fingerprint(), None);
+        assert_eq!(envoy.meter_readings().await.unwrap(), vec![]);
+        let fingerprint = envoy.certificate_fingerprint().unwrap();
+        assert_eq!(fingerprint, Fingerprint::of_certificate(certificate.der()));
+
+        // A gateway with a different certificate should then be rejected.
+        let (other_url, other_certificate) = tls_gateway_stub();
+        let mut envoy = envoy;
+        envoy.set_base_url(other_url);
+        let error = envoy.meter_readings().await.unwrap_err();
+        assert!(matches!(
+            error,
+            Error::CertificateMismatch { expected, actual }
+                if expected == fingerprint
+                    && actual == Fingerprint::of_certificate(other_certificate.der())
+        ));
+    }
+
+    #[tokio::test]
+    async fn pinned() {
+        let (url, certificate) = tls_gateway_stub();
+        let tls = TlsVerification::pinned_certificate_pem(certificate.pem().as_bytes()).unwrap();
+        let envoy = Envoy::new_with_tls(url.clone(), "token", &tls).unwrap();
+        assert_eq!(envoy.meter_readings().await.unwrap(), vec![]);
+
+        let expected = Fingerprint::of_certificate(b"some other certificate");
+        let tls = TlsVerification::Pinned(expected);
+        let envoy = Envoy::new_with_tls(url, "token", &tls).unwrap();
+        let error = envoy.meter_readings().await.unwrap_err();
+        assert!(matches!(error, Error::CertificateMismatch { .. }));
+        assert!(error.to_string().contains(&expected.to_string()));
+    }
+
+    #[tokio::test]
+    async fn ca_certificates() {
+        let (url, certificate) = tls_gateway_stub();
+        let tls = TlsVerification::ca_certificates_pem(certificate.pem().as_bytes()).unwrap();
+        let envoy = Envoy::new_with_tls(url.clone(), "token", &tls).unwrap();
+        assert_eq!(envoy.meter_readings().await.unwrap(), vec![]);
+
+        let (_, other_certificate) = tls_gateway_stub();
+        let tls = TlsVerification::ca_certificates_pem(other_certificate.pem().as_bytes()).unwrap();
+        let envoy = Envoy::new_with_tls(url, "token", &tls).unwrap();
+        assert!(matches!(
+            envoy.meter_readings().await.unwrap_err(),
+            Error::Transport { .. }
+        ));
+
+        assert!(TlsVerification::ca_certificates_pem(b"").is_err());
+    }
+
+    #[tokio::test]
+    async fn insecure() {
+        let (url, _) = tls_gateway_stub();
+        let envoy = Envoy::new_with_tls(url, "token", &TlsVerification::Insecure).unwrap();
+        assert_eq!(envoy.meter_readings().await.unwrap(), vec![]);
+        assert_eq!(envoy.certificate_fingerprint(), None);
+    }
+
+    #[test]
+    fn fingerprint_round_trip() {
+        let fingerprint = Fingerprint::of_certificate(b"certificate");
+        let formatted = fingerprint.to_string();
+        assert_eq!(formatted.len(), 32 * 3 - 1);
+        assert_eq!(formatted.parse::<Fingerprint>().unwrap(), fingerprint);
+        assert_eq!(
+            formatted
+                .replace(':', "")
+                .to_lowercase()
+                .parse::<Fingerprint>()
+                .unwrap(),
+            fingerprint
+        );
+        assert!("AB:CD".parse::<Fingerprint>().is_err());
+        assert!("Z".repeat(64).parse::<Fingerprint>().is_err());
+    }
+}
@@ -0,0 +1,625 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Writing to and querying InfluxDB, with either the 1.x API or the 2.x API.
+
+use eyre::{Report, WrapErr, bail, eyre};
+use influx_db_client::{
+    Client, Node, Point, Value,
+    reqwest::{self, StatusCode, Url, header::AUTHORIZATION},
+};
+use serde::Deserialize;
+use std::{
+    fs::File,
+    io::{BufWriter, Write, stdout},
+    path::Path,
+    sync::Mutex,
+};
+
+/// Connection details for InfluxDB.
+///
+/// If `bucket` is set then the 2.x API is used, authenticating with `token` if given. This also
+/// works with InfluxDB 3.x. Otherwise the 1.x API is used, with `database` and optionally
+/// `username` and `password`.
+#[derive(Clone, Debug)]
+pub struct ConnectionConfig {
+    pub url: Url,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub database: String,
+    pub token: Option<String>,
+    pub org: Option<String>,
+    pub bucket: Option<String>,
+    pub precision: Precision,
+}
+
+/// The precision with which to write timestamps to InfluxDB.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+pub enum Precision {
+    #[default]
+    #[serde(rename = "s")]
+    Seconds,
+    #[serde(rename = "ms")]
+    Milliseconds,
+    #[serde(rename = "us")]
+    Microseconds,
+    #[serde(rename = "ns")]
+    Nanoseconds,
+}
+
+/// A connection to InfluxDB.
+pub enum InfluxDb {
+    /// InfluxDB 1.x.
+    V1 {
+        client: Client,
+        precision: Precision,
+    },
+    /// InfluxDB 2.x, or 3.x using its 2.x compatible API.
+    V2 {
+        client: reqwest::Client,
+        url: Url,
+        token: Option<String>,
+        org: Option<String>,
+        bucket: String,
+        precision: Precision,
+    },
+    /// Prints points in line protocol rather than writing them, for `--dry-run`.
+    DryRun {
+        /// The real InfluxDB, which is still used for queries.
+        influxdb: Box<InfluxDb>,
+        output: Mutex<Box<dyn Write + Send>>,
+        precision: Precision,
+    },
+}
+
+impl InfluxDb {
+    /// Constructs a new InfluxDB connection based on the given configuration options.
+    pub fn new(config: &ConnectionConfig) -> Result<Self, Report> {
+        if let Some(bucket) = &config.bucket {
+            Ok(Self::V2 {
+                client: reqwest::Client::new(),
+                url: config.url.clone(),
+                token: config.token.clone(),
+                org: config.org.clone(),
+                bucket: bucket.clone(),
+                precision: config.precision,
+            })
+        } else {
+            let mut client = Client::new(config.url.clone(), &config.database);
+            if let (Some(username), Some(password)) = (&config.username, &config.password) {
+                client = client.set_authentication(username, password);
+            }
+            Ok(Self::V1 {
+                client,
+                precision: config.precision,
+            })
+        }
+    }
+
+    /// Converts this connection into one which writes points in line protocol to the given file, or
+    /// to stdout if no file is given, rather than to InfluxDB.
+    pub fn dry_run(self, output: Option<&Path>) -> Result<Self, Report> {
+        let precision = match &self {
+            Self::V1 { precision, .. }
+            | Self::V2 { precision, .. }
+            | Self::DryRun { precision, .. } => *precision,
+        };
+        let output: Box<dyn Write + Send> = match output {
+            Some(path) => Box::new(BufWriter::new(
+                File::create(path).wrap_err_with(|| format!("Creating {}", path.display()))?,
+            )),
+            None => Box::new(stdout()),
+        };
+        Ok(Self::DryRun {
+            influxdb: Box::new(self),
+            output: Mutex::new(output),
+            precision,
+        })
+    }
+
+    /// Writes the given points, whose timestamps must be in seconds.
+    pub async fn write_points(&self, points: Vec<Point<'_>>) -> Result<(), Report> {
+        match self {
+            Self::V1 { client, precision } => {
+                let points = points.into_iter().map(|point| precision.scale_point(point));
+                client
+                    .write_points(points, Some(precision.to_influx_db_client()), None)
+                    .await?;
+            }
+            Self::V2 {
+                client,
+                url,
+                token,
+                org,
+                bucket,
+                precision,
+            } => {
+                let mut url = url.join("api/v2/write")?;
+                {
+                    let mut query = url.query_pairs_mut();
+                    if let Some(org) = org {
+                        query.append_pair("org", org);
+                    }
+                    query
+                        .append_pair("bucket", bucket)
+                        .append_pair("precision", precision.as_str());
+                }
+                let body =
+                    line_protocol(points.into_iter().map(|point| precision.scale_point(point)));
+                let mut request = client
+                    .post(url)
+                    .header("Content-Type", "text/plain; charset=utf-8")
+                    .body(body);
+                if let Some(token) = token {
+                    request = request.header(AUTHORIZATION, format!("Token {token}"));
+                }
+                let response = request.send().await?;
+                let status = response.status();
+                if status != StatusCode::NO_CONTENT && !status.is_success() {
+                    bail!(
+                        "Error writing to InfluxDB: {status}: {}",
+                        response.text().await?
+                    );
+                }
+            }
+            Self::DryRun {
+                output, precision, ..
+            } => {
+                let lines =
+                    line_protocol(points.into_iter().map(|point| precision.scale_point(point)));
+                let mut output = output.lock().unwrap();
+                output.write_all(lines.as_bytes())?;
+                output.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the given InfluxQL query, returning timestamps in seconds.
+    ///
+    /// For InfluxDB 2.x and 3.x this uses the 1.x compatible query API, treating the bucket name as
+    /// the database name. InfluxDB 3.x supports this directly, but InfluxDB 2.x only does if the
+    /// bucket has a DBRP mapping, so a clear error is returned if it doesn't.
+    pub async fn query(&self, query: &str) -> Result<Vec<Node>, Report> {
+        match self {
+            Self::V1 { client, .. } => Ok(client
+                .query(query, Some(influx_db_client::Precision::Seconds))
+                .await?
+                .unwrap_or_default()),
+            Self::V2 {
+                client,
+                url,
+                token,
+                bucket,
+                ..
+            } => {
+                let mut url = url.join("query")?;
+                url.query_pairs_mut()
+                    .append_pair("db", bucket)
+                    .append_pair("q", query)
+                    .append_pair("epoch", "s");
+                let mut request = client.get(url);
+                if let Some(token) = token {
+                    request = request.header(AUTHORIZATION, format!("Token {token}"));
+                }
+                let response = request.send().await?;
+                let status = response.status();
+                if !status.is_success() {
+                    let body = response.text().await?;
+                    if status == StatusCode::NOT_FOUND || is_database_not_found(&body) {
+                        return Err(missing_dbrp_mapping(bucket, &body));
+                    }
+                    bail!("Error querying InfluxDB: {status}: {body}");
+                }
+                let response: QueryResponse = response.json().await?;
+                let statement_errors = response
+                    .results
+                    .iter()
+                    .filter_map(|result| result.error.as_ref());
+                if let Some(error) = response.error.iter().chain(statement_errors).next() {
+                    if is_database_not_found(error) {
+                        return Err(missing_dbrp_mapping(bucket, error));
+                    }
+                    bail!("Error querying InfluxDB: {error}");
+                }
+                Ok(response
+                    .results
+                    .into_iter()
+                    .map(|result| result.node)
+                    .collect())
+            }
+            Self::DryRun { influxdb, .. } => Box::pin(influxdb.query(query)).await,
+        }
+    }
+}
+
+/// The response to a query with the 1.x compatible query API.
+#[derive(Deserialize)]
+struct QueryResponse {
+    #[serde(default)]
+    results: Vec<StatementResult>,
+    error: Option<String>,
+}
+
+/// The result of a single statement of a query, which may have failed even if the query as a whole
+/// succeeded.
+#[derive(Deserialize)]
+struct StatementResult {
+    error: Option<String>,
+    #[serde(flatten)]
+    node: Node,
+}
+
+/// Returns whether the given error from the 1.x compatible query API means that the database
+/// wasn't found.
+fn is_database_not_found(error: &str) -> bool {
+    let error = error.to_lowercase();
+    error.contains("database not found") || error.contains("dbrp")
+}
+
+/// Returns an error explaining that the given bucket can't be queried with InfluxQL because
+/// InfluxDB has no DBRP mapping for it.
+fn missing_dbrp_mapping(bucket: &str, error: &str) -> Report {
+    eyre!(
+        "InfluxDB couldn't find database {bucket:?} ({error}). With InfluxDB 2.x, the bucket needs a \
+         DBRP mapping to be queried, which can be created with `influx v1 dbrp create --db \
+         {bucket} --rp autogen --default --bucket-id <bucket ID>`."
+    )
+}
+
+impl Precision {
+    /// Returns the name of the precision used by the InfluxDB API.
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Seconds => "s",
+            Self::Milliseconds => "ms",
+            Self::Microseconds => "us",
+            Self::Nanoseconds => "ns",
+        }
+    }
+
+    fn to_influx_db_client(self) -> influx_db_client::Precision {
+        match self {
+            Self::Seconds => influx_db_client::Precision::Seconds,
+            Self::Milliseconds => influx_db_client::Precision::Milliseconds,
+            Self::Microseconds => influx_db_client::Precision::Microseconds,
+            Self::Nanoseconds => influx_db_client::Precision::Nanoseconds,
+        }
+    }
+
+    /// Converts the timestamp of the given point from seconds to this precision.
+    fn scale_point(self, mut point: Point<'_>) -> Point<'_> {
+        let factor = match self {
+            Self::Seconds => 1,
+            Self::Milliseconds => 1_000,
+            Self::Microseconds => 1_000_000,
+            Self::Nanoseconds => 1_000_000_000,
+        };
+        point.timestamp = point.timestamp.map(|timestamp| timestamp * factor);
+        point
+    }
+}
+
+/// Serialises the given points in InfluxDB line protocol.
+///
+/// Tags and fields are sorted by key, so the output is deterministic.
+pub fn line_protocol<'a>(points: impl IntoIterator<Item = Point<'a>>) -> String {
+    let mut lines = String::new();
+    for point in points {
+        lines.push_str(&escape(&point.measurement, &[',', ' ']));
+        let mut tags = point.tags.iter().collect::<Vec<_>>();
+        tags.sort_by_key(|(key, _)| *key);
+        for (key, value) in tags {
+            let value = match value {
+                Value::String(s) => s.to_string(),
+                Value::Integer(i) => i.to_string(),
+                Value::Float(f) => f.to_string(),
+                Value::Boolean(b) => b.to_string(),
+            };
+            lines.push(',');
+            lines.push_str(&escape(key, &[',', '=', ' ']));
+            lines.push('=');
+            lines.push_str(&escape(&value, &[',', '=', ' ']));
+        }
+        let mut fields = point.fields.iter().collect::<Vec<_>>();
+        fields.sort_by_key(|(key, _)| *key);
+        for (i, (key, value)) in fields.into_iter().enumerate() {
+            lines.push(if i == 0 { ' ' } else { ',' });
+            lines.push_str(&escape(key, &[',', '=', ' ']));
+            lines.push('=');
+            match value {
+                Value::String(s) => {
+                    lines.push('"');
+                    lines.push_str(&escape(s, &['"']));
+                    lines.push('"');
+                }
+                Value::Integer(i) => lines.push_str(&format!("{i}i")),
+                Value::Float(f) => lines.push_str(&f.to_string()),
+                Value::Boolean(b) => lines.push_str(&b.to_string()),
+            }
+        }
+        if let Some(timestamp) = point.timestamp {
+            lines.push(' ');
+            lines.push_str(&timestamp.to_string());
+        }
+        lines.push('\n');
+    }
+    lines
+}
+
+/// Escapes backslashes and the given special characters with a backslash.
+fn escape(s: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c == '\\' || special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        thread::{self, JoinHandle},
+    };
+
+    /// An HTTP request received by [`stub_server`].
+    #[derive(Debug)]
+    struct Request {
+        request_line: String,
+        headers: Vec<(String, String)>,
+        body: String,
+    }
+
+    impl Request {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(header, _)| header.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        }
+    }
+
+    /// Returns connection details for the 1.x API at the given URL.
+    fn config(url: Url) -> ConnectionConfig {
+        ConnectionConfig {
+            url,
+            username: None,
+            password: None,
+            database: "energy".to_owned(),
+            token: None,
+            org: None,
+            bucket: None,
+            precision: Precision::Seconds,
+        }
+    }
+
+    /// Starts an HTTP server on a local port which accepts a single request and replies to it with
+    /// the given status line and body.
+    fn stub_server(
+        status: &'static str,
+        response_body: &'static str,
+    ) -> (Url, JoinHandle<Request>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut headers = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                let (name, value) = line.split_once(':').unwrap();
+                headers.push((name.to_owned(), value.trim().to_owned()));
+            }
+            let content_length = headers
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+                .map_or(0, |(_, value)| value.parse().unwrap());
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 {status}\r\nContent-Length: {}\r\nContent-Type: application/json\r\n\
+                 Connection: close\r\n\r\n{response_body}",
+                response_body.len()
+            )
+            .unwrap();
+            Request {
+                request_line: request_line.trim_end().to_owned(),
+                headers,
+                body: String::from_utf8(body).unwrap(),
+            }
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn serialise_points() {
+        let points = vec![
+            Point::new("octopower")
+                .add_timestamp(1704067200)
+                .add_tag("serial", "A 1")
+                .add_tag("mpxn", "1234")
+                .add_field("consumption", 0.5),
+            Point::new("my measurement,1")
+                .add_tag("a=b", "c,d")
+                .add_field("count", 3)
+                .add_field("name", "say \"hi\""),
+        ];
+        assert_eq!(
+            line_protocol(points),
+            "octopower,mpxn=1234,serial=A\\ 1 consumption=0.5 1704067200\n\
+             my\\ measurement\\,1,a\\=b=c\\,d count=3i,name=\"say \\\"hi\\\"\"\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn write_v2() {
+        let (url, server) = stub_server("204 No Content", "");
+        let influxdb = InfluxDb::new(&ConnectionConfig {
+            token: Some("secret".to_owned()),
+            org: Some("home".to_owned()),
+            bucket: Some("energy".to_owned()),
+            precision: Precision::Milliseconds,
+            ..config(url)
+        })
+        .unwrap();
+        influxdb
+            .write_points(vec![
+                Point::new("octopower")
+                    .add_timestamp(1704067200)
+                    .add_tag("mpxn", "1234")
+                    .add_field("consumption", 0.5),
+            ])
+            .await
+            .unwrap();
+
+        let request = server.join().unwrap();
+        assert_eq!(
+            request.request_line,
+            "POST /api/v2/write?org=home&bucket=energy&precision=ms HTTP/1.1"
+        );
+        assert_eq!(request.header("Authorization"), Some("Token secret"));
+        assert_eq!(
+            request.header("Content-Type"),
+            Some("text/plain; charset=utf-8")
+        );
+        assert_eq!(
+            request.body,
+            "octopower,mpxn=1234 consumption=0.5 1704067200000\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn write_v2_error() {
+        let (url, server) = stub_server("401 Unauthorized", r#"{"code":"unauthorized"}"#);
+        let influxdb = InfluxDb::new(&ConnectionConfig {
+            bucket: Some("energy".to_owned()),
+            ..config(url)
+        })
+        .unwrap();
+        let error = influxdb
+            .write_points(vec![Point::new("octopower").add_field("consumption", 0.5)])
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("401"));
+
+        let request = server.join().unwrap();
+        assert_eq!(
+            request.request_line,
+            "POST /api/v2/write?bucket=energy&precision=s HTTP/1.1"
+        );
+        assert_eq!(request.header("Authorization"), None);
+    }
+
+    #[tokio::test]
+    async fn query_v2() {
+        let (url, server) = stub_server(
+            "200 OK",
+            r#"{"results":[{"statement_id":0,"series":[{"name":"octopower","tags":{"mpxn":"1234","serial":"A"},"columns":["time","last"],"values":[[1704067200,0.5]]}]}]}"#,
+        );
+        let influxdb = InfluxDb::new(&ConnectionConfig {
+            token: Some("secret".to_owned()),
+            bucket: Some("energy".to_owned()),
+            ..config(url)
+        })
+        .unwrap();
+        let nodes = influxdb.query("SELECT 1").await.unwrap();
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].series.as_ref().unwrap().len(), 1);
+
+        let request = server.join().unwrap();
+        assert_eq!(
+            request.request_line,
+            "GET /query?db=energy&q=SELECT+1&epoch=s HTTP/1.1"
+        );
+        assert_eq!(request.header("Authorization"), Some("Token secret"));
+    }
+
+    #[tokio::test]
+    async fn query_v2_missing_dbrp_mapping() {
+        let (url, server) = stub_server(
+            "404 Not Found",
+            r#"{"code":"not found","message":"database not found: energy"}"#,
+        );
+        let influxdb = InfluxDb::new(&ConnectionConfig {
+            bucket: Some("energy".to_owned()),
+            ..config(url)
+        })
+        .unwrap();
+        let error = influxdb.query("SELECT 1").await.unwrap_err();
+        assert!(error.to_string().contains("DBRP mapping"));
+        server.join().unwrap();
+
+        // Errors may also be given for the individual statement.
+        let (url, server) = stub_server(
+            "200 OK",
+            r#"{"results":[{"statement_id":0,"error":"database not found: energy"}]}"#,
+        );
+        let influxdb = InfluxDb::new(&ConnectionConfig {
+            bucket: Some("energy".to_owned()),
+            ..config(url)
+        })
+        .unwrap();
+        let error = influxdb.query("SELECT 1").await.unwrap_err();
+        assert!(error.to_string().contains("DBRP mapping"));
+        server.join().unwrap();
+
+        let (url, server) = stub_server(
+            "200 OK",
+            r#"{"results":[{"statement_id":0,"error":"error parsing query"}]}"#,
+        );
+        let influxdb = InfluxDb::new(&ConnectionConfig {
+            bucket: Some("energy".to_owned()),
+            ..config(url)
+        })
+        .unwrap();
+        let error = influxdb.query("SELECT").await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Error querying InfluxDB: error parsing query"
+        );
+        server.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn dry_run() {
+        let path =
+            std::env::temp_dir().join(format!("influx-common-dry-run-{}.txt", std::process::id()));
+        let influxdb = InfluxDb::new(&ConnectionConfig {
+            precision: Precision::Milliseconds,
+            ..config("http://localhost:8086/".parse().unwrap())
+        })
+        .unwrap()
+        .dry_run(Some(&path))
+        .unwrap();
+        influxdb
+            .write_points(vec![
+                Point::new("octopower")
+                    .add_timestamp(1704067200)
+                    .add_field("consumption", 0.5),
+            ])
+            .await
+            .unwrap();
+        drop(influxdb);
+
+        let output = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(output, "octopower consumption=0.5 1704067200000\n");
+    }
+}
@@ -0,0 +1,8 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Code shared between the octo-influx and enphase-influx importers.
+
+pub mod influxdb;
+pub mod overrides;
@@ -0,0 +1,468 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Overriding config file values from environment variables, and reading secrets from files.
+
+use eyre::{Report, WrapErr, bail, eyre};
+use serde::{
+    Deserializer,
+    de::{
+        DeserializeOwned, Error as _, IntoDeserializer, Unexpected, Visitor,
+        value::{MapDeserializer, SeqDeserializer},
+    },
+    forward_to_deserialize_any,
+};
+use std::fs::read_to_string;
+use toml::{Table, Value, de::Error};
+
+/// The separator between the components of the path to a config value in an environment variable
+/// name.
+const SEPARATOR: &str = "__";
+
+/// The suffix of a key whose value is the path of a file from which to read the value of the key
+/// without the suffix.
+const FILE_SUFFIX: &str = "_file";
+
+/// Overrides values in the given config table with any of the given environment variables whose
+/// names start with `prefix` followed by `__`.
+///
+/// The rest of the name is the path to the value, with components separated by `__`, so for example
+/// `OCTO_INFLUX__OCTOPUS__PASSWORD` sets `password` in the `[octopus]` table. Names are converted to
+/// lowercase. A component which is a number indexes into an array, such as a list of `[[octopus]]`
+/// tables.
+///
+/// If the value being overridden is a string then the variable's value is used as-is, otherwise it
+/// is parsed as the same type. New values are always strings, so that a password or serial number
+/// which looks like a number isn't turned into one. Use [`deserialize_config`] to parse them as
+/// booleans or numbers where the config needs one.
+pub fn apply_env_overrides(
+    table: &mut Table,
+    prefix: &str,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<(), Report> {
+    let prefix = format!("{prefix}{SEPARATOR}");
+    let mut vars = vars
+        .into_iter()
+        .filter(|(name, _)| name.starts_with(&prefix))
+        .collect::<Vec<_>>();
+    // Apply overrides in a consistent order.
+    vars.sort();
+    for (name, value) in vars {
+        let path = name[prefix.len()..]
+            .split(SEPARATOR)
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>();
+        set_path(table, &path, &value).wrap_err_with(|| format!("Applying {name}"))?;
+    }
+    Ok(())
+}
+
+fn set_path(table: &mut Table, path: &[String], value: &str) -> Result<(), Report> {
+    let (key, rest) = path
+        .split_first()
+        .ok_or_else(|| eyre!("Missing config key"))?;
+    if key.is_empty() {
+        bail!("Empty config key");
+    }
+    if rest.is_empty() {
+        let new_value = parse_value(table.get(key), value)?;
+        table.insert(key.clone(), new_value);
+        return Ok(());
+    }
+    match table
+        .entry(key.clone())
+        .or_insert_with(|| Value::Table(Table::new()))
+    {
+        Value::Table(table) => set_path(table, rest, value),
+        Value::Array(array) => {
+            let Some((index, rest)) = rest.split_first() else {
+                bail!("Missing index into array {key}");
+            };
+            let index = index
+                .parse::<usize>()
+                .wrap_err_with(|| format!("Invalid index {index:?} into array {key}"))?;
+            let Some(Value::Table(table)) = array.get_mut(index) else {
+                bail!("No table at index {index} of array {key}");
+            };
+            set_path(table, rest, value)
+        }
+        _ => bail!("{key} is not a table"),
+    }
+}
+
+/// Parses the given string as a value of the same type as `existing`, or as a string if there is
+/// no existing value.
+fn parse_value(existing: Option<&Value>, value: &str) -> Result<Value, Report> {
+    Ok(match existing {
+        None | Some(Value::String(_)) => Value::String(value.to_owned()),
+        Some(Value::Integer(_)) => Value::Integer(value.parse()?),
+        Some(Value::Float(_)) => Value::Float(value.parse()?),
+        Some(Value::Boolean(_)) => Value::Boolean(value.parse()?),
+        Some(_) => parse_toml_value(value)?,
+    })
+}
+
+fn parse_toml_value(value: &str) -> Result<Value, Report> {
+    let mut table = toml::from_str::<Table>(&format!("value = {value}"))?;
+    Ok(table.remove("value").unwrap())
+}
+
+/// Deserialises the given config table, parsing strings as booleans or numbers wherever the type
+/// being deserialised expects one.
+///
+/// This allows environment variable overrides to set keys which aren't in the config file, whose
+/// values are always strings.
+pub fn deserialize_config<T: DeserializeOwned>(table: Table) -> Result<T, Error> {
+    T::deserialize(ConfigDeserializer(Value::Table(table)))
+}
+
+/// A deserialiser for a config value which parses strings as booleans or numbers where they are
+/// expected.
+struct ConfigDeserializer(Value);
+
+impl IntoDeserializer<'_, Error> for ConfigDeserializer {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+/// Implements the given `Deserializer` methods by parsing a string value as the type which the
+/// corresponding visitor method takes.
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                match self.0 {
+                    Value::String(value) => match value.parse() {
+                        Ok(parsed) => visitor.$visit(parsed),
+                        Err(_) => Err(Error::invalid_type(Unexpected::Str(&value), &visitor)),
+                    },
+                    value => value.deserialize_any(visitor),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for ConfigDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Array(array) => {
+                let mut seq = SeqDeserializer::new(array.into_iter().map(ConfigDeserializer));
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            Value::Table(table) => {
+                let mut map = MapDeserializer::new(
+                    table
+                        .into_iter()
+                        .map(|(key, value)| (key, ConfigDeserializer(value))),
+                );
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+            value => value.deserialize_any(visitor),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i64,
+        deserialize_i16 => visit_i64,
+        deserialize_i32 => visit_i64,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u64,
+        deserialize_u16 => visit_u64,
+        deserialize_u32 => visit_u64,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f64,
+        deserialize_f64 => visit_f64,
+    }
+
+    // A missing key is `None`, so any value which is present is `Some`.
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.0.deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_deserialize_any! {
+        char str string bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+/// Replaces every key ending in `_file` in the given table and any tables nested within it with
+/// the key without the suffix, whose value is read from the file at the path given.
+///
+/// Any trailing newline is removed from the contents of the file. It is an error to give both the
+/// key and its `_file` variant.
+pub fn read_secret_files(table: &mut Table) -> Result<(), Report> {
+    let file_keys = table
+        .keys()
+        .filter(|key| key.len() > FILE_SUFFIX.len() && key.ends_with(FILE_SUFFIX))
+        .cloned()
+        .collect::<Vec<_>>();
+    for file_key in file_keys {
+        let key = &file_key[..file_key.len() - FILE_SUFFIX.len()];
+        if table.contains_key(key) {
+            bail!("Both {key} and {file_key} are set");
+        }
+        let Some(Value::String(path)) = table.remove(&file_key) else {
+            bail!("{file_key} must be a string");
+        };
+        let contents =
+            read_to_string(&path).wrap_err_with(|| format!("Reading {file_key} {path}"))?;
+        let contents = contents
+            .strip_suffix('\n')
+            .map(|contents| contents.strip_suffix('\r').unwrap_or(contents))
+            .unwrap_or(&contents);
+        table.insert(key.to_owned(), Value::String(contents.to_owned()));
+    }
+    for (_, value) in table.iter_mut() {
+        match value {
+            Value::Table(table) => read_secret_files(table)?,
+            Value::Array(array) => {
+                for value in array {
+                    if let Value::Table(table) = value {
+                        read_secret_files(table)?;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::{env::temp_dir, fs::write, process};
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn env_overrides() {
+        let mut table = toml::from_str::<Table>(
+            r#"
+            num_readings = 1000
+
+            [octopus]
+            email_address = "email@address.host"
+            password = "password"
+            "#,
+        )
+        .unwrap();
+        apply_env_overrides(
+            &mut table,
+            "OCTO_INFLUX",
+            vars(&[
+                ("OCTO_INFLUX__OCTOPUS__PASSWORD", "1234"),
+                ("OCTO_INFLUX__NUM_READINGS", "500"),
+                ("OCTO_INFLUX__INFLUXDB__URL", "http://influxdb:8086"),
+                ("OCTO_INFLUX__OCTOPUS__SERIAL", "0123456789"),
+                ("OTHER__OCTOPUS__PASSWORD", "ignored"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(
+            table,
+            toml::from_str::<Table>(
+                r#"
+                num_readings = 500
+
+                [octopus]
+                email_address = "email@address.host"
+                password = "1234"
+                serial = "0123456789"
+
+                [influxdb]
+                url = "http://influxdb:8086"
+                "#,
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn env_override_array() {
+        let mut table = toml::from_str::<Table>(
+            r#"
+            [[octopus]]
+            password = "one"
+
+            [[octopus]]
+            password = "two"
+            "#,
+        )
+        .unwrap();
+        apply_env_overrides(
+            &mut table,
+            "OCTO_INFLUX",
+            vars(&[("OCTO_INFLUX__OCTOPUS__1__PASSWORD", "secret")]),
+        )
+        .unwrap();
+        assert_eq!(table["octopus"][0]["password"].as_str(), Some("one"));
+        assert_eq!(table["octopus"][1]["password"].as_str(), Some("secret"));
+
+        assert!(
+            apply_env_overrides(
+                &mut table,
+                "OCTO_INFLUX",
+                vars(&[("OCTO_INFLUX__OCTOPUS__PASSWORD", "secret")]),
+            )
+            .is_err()
+        );
+        assert!(
+            apply_env_overrides(
+                &mut table,
+                "OCTO_INFLUX",
+                vars(&[("OCTO_INFLUX__OCTOPUS__2__PASSWORD", "secret")]),
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn invalid_override() {
+        let mut table = toml::from_str::<Table>("num_readings = 1000").unwrap();
+        assert!(
+            apply_env_overrides(
+                &mut table,
+                "OCTO_INFLUX",
+                vars(&[("OCTO_INFLUX__NUM_READINGS", "lots")]),
+            )
+            .is_err()
+        );
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    struct Config {
+        num_readings: u32,
+        retries: Option<u8>,
+        ratio: f64,
+        octopus: Vec<Account>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    struct Account {
+        password: String,
+        #[serde(default)]
+        dispatches: bool,
+    }
+
+    /// New values which are strings should be parsed as whatever type the config needs.
+    #[test]
+    fn deserialize_new_values() {
+        let mut table = toml::from_str::<Table>(
+            r#"
+            num_readings = 1000
+            ratio = 0.5
+
+            [[octopus]]
+            password = "password"
+            "#,
+        )
+        .unwrap();
+        apply_env_overrides(
+            &mut table,
+            "OCTO_INFLUX",
+            vars(&[
+                ("OCTO_INFLUX__RETRIES", "5"),
+                ("OCTO_INFLUX__OCTOPUS__0__PASSWORD", "1234"),
+                ("OCTO_INFLUX__OCTOPUS__0__DISPATCHES", "true"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(
+            deserialize_config::<Config>(table.clone()).unwrap(),
+            Config {
+                num_readings: 1000,
+                retries: Some(5),
+                ratio: 0.5,
+                octopus: vec![Account {
+                    password: "1234".to_owned(),
+                    dispatches: true,
+                }],
+            }
+        );
+
+        apply_env_overrides(
+            &mut table,
+            "OCTO_INFLUX",
+            vars(&[("OCTO_INFLUX__OCTOPUS__0__DISPATCHES", "yes")]),
+        )
+        .unwrap();
+        assert!(deserialize_config::<Config>(table.clone()).is_err());
+
+        table.insert("unknown".to_owned(), Value::Integer(1));
+        assert!(deserialize_config::<Config>(table).is_err());
+    }
+
+    #[test]
+    fn secret_files() {
+        let path = temp_dir().join(format!("octo-influx-secret-{}", process::id()));
+        write(&path, "secret\n").unwrap();
+        let mut table = toml::from_str::<Table>(&format!(
+            r#"
+            [[octopus]]
+            password_file = '{}'
+            "#,
+            path.display()
+        ))
+        .unwrap();
+        read_secret_files(&mut table).unwrap();
+        assert_eq!(
+            table,
+            toml::from_str::<Table>(
+                r#"
+                [[octopus]]
+                password = "secret"
+                "#,
+            )
+            .unwrap()
+        );
+
+        let mut table = toml::from_str::<Table>(&format!(
+            r#"
+            password = "password"
+            password_file = '{}'
+            "#,
+            path.display()
+        ))
+        .unwrap();
+        assert!(read_secret_files(&mut table).is_err());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -2,43 +2,235 @@
 // This project is dual-licensed under Apache 2.0 and MIT terms.
 // See LICENSE-APACHE and LICENSE-MIT for details.
 
-use eyre::{Report, WrapErr};
-use influx_db_client::{Client, reqwest::Url};
-use serde::Deserialize;
-use std::fs::read_to_string;
+use chrono::NaiveTime;
+use eyre::{Report, WrapErr, bail};
+use influx_common::{
+    influxdb::{ConnectionConfig, Precision},
+    overrides::{apply_env_overrides, deserialize_config, read_secret_files},
+};
+use influx_db_client::reqwest::Url;
+use octopower::Grouping;
+use serde::{
+    Deserialize, Deserializer,
+    de::{
+        Error as _, MapAccess, SeqAccess, Visitor,
+        value::{MapAccessDeserializer, SeqAccessDeserializer},
+    },
+};
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::{self, Formatter},
+    fs::read_to_string,
+    marker::PhantomData,
+    net::SocketAddr,
+    path::Path,
+    time::Duration,
+};
+use toml::Table;
 
 const DEFAULT_DATABASE: &str = "octopower";
 const DEFAULT_MEASUREMENT: &str = "octopower";
+const DEFAULT_PRICES_MEASUREMENT: &str = "octopower_prices";
+const DEFAULT_DISPATCHES_MEASUREMENT: &str = "octopower_dispatches";
 const DEFAULT_INFLUXDB_URL: &str = "http://localhost:8086";
 const DEFAULT_NUM_READINGS: usize = 1000;
-const CONFIG_FILENAME: &str = "octo-influx.toml";
+const DEFAULT_OVERLAP: Duration = Duration::from_secs(2 * 24 * 60 * 60);
+const DEFAULT_RETRIES: u32 = 2;
+const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(10);
+const DEFAULT_DAEMON_PERIOD: Duration = Duration::from_secs(60 * 60);
+const DEFAULT_RESYNC_OVERLAP: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+const DEFAULT_RUN_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+const DEFAULT_PROMETHEUS_LISTEN_ADDRESS: &str = "0.0.0.0:9184";
+const DEFAULT_PROMETHEUS_LOOKBACK: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// The default path of the config file.
+pub const CONFIG_FILENAME: &str = "octo-influx.toml";
+/// The prefix of environment variables which override config values.
+const ENV_PREFIX: &str = "OCTO_INFLUX";
 
 #[derive(Clone, Debug, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct Config {
+    /// Where to send imported data.
+    #[serde(default)]
+    pub sink: SinkType,
     #[serde(default)]
     pub influxdb: InfluxDbConfig,
-    pub octopus: OctopusConfig,
+    #[serde(default)]
+    pub prometheus: PrometheusConfig,
+    /// The accounts from which to import. This may be given either as a single `[octopus]` table or
+    /// as several `[[octopus]]` tables.
+    #[serde(deserialize_with = "de_one_or_many")]
+    pub octopus: Vec<OctopusConfig>,
     #[serde(default = "default_num_readings")]
     pub num_readings: usize,
+    #[serde(
+        default = "default_overlap",
+        deserialize_with = "de_duration_hours",
+        rename = "overlap_hours"
+    )]
+    pub overlap: Duration,
+    /// The number of times to retry importing a meter after a failure.
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    /// How long to wait before the first retry. This doubles for each subsequent retry.
+    #[serde(
+        default = "default_retry_delay",
+        deserialize_with = "de_duration_seconds",
+        rename = "retry_delay_seconds"
+    )]
+    pub retry_delay: Duration,
+    #[serde(default)]
+    pub daemon: DaemonConfig,
+    #[serde(default)]
+    pub meters: MetersConfig,
+    #[serde(default)]
+    pub tags: TagsConfig,
 }
 
 fn default_num_readings() -> usize {
     DEFAULT_NUM_READINGS
 }
 
+fn default_overlap() -> Duration {
+    DEFAULT_OVERLAP
+}
+
+fn default_retries() -> u32 {
+    DEFAULT_RETRIES
+}
+
+fn default_retry_delay() -> Duration {
+    DEFAULT_RETRY_DELAY
+}
+
+/// Deserialises either a single table or a list of tables.
+///
+/// This doesn't use an untagged enum, as that would buffer the values and so lose the parsing of
+/// strings done by [`deserialize_config`].
+fn de_one_or_many<'de, D: Deserializer<'de>, T: Deserialize<'de>>(
+    d: D,
+) -> Result<Vec<T>, D::Error> {
+    struct OneOrManyVisitor<T>(PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de>> Visitor<'de> for OneOrManyVisitor<T> {
+        type Value = Vec<T>;
+
+        fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+            formatter.write_str("a table or an array of tables")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+            Ok(vec![T::deserialize(MapAccessDeserializer::new(map))?])
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+            Vec::deserialize(SeqAccessDeserializer::new(seq))
+        }
+    }
+
+    d.deserialize_any(OneOrManyVisitor(PhantomData))
+}
+
+pub fn de_duration_seconds<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+    let seconds = u64::deserialize(d)?;
+    Ok(Duration::from_secs(seconds))
+}
+
+pub fn de_duration_minutes<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+    de_duration_units(d, 60)
+}
+
+pub fn de_duration_hours<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+    de_duration_units(d, 60 * 60)
+}
+
+pub fn de_duration_days<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+    de_duration_units(d, 24 * 60 * 60)
+}
+
+/// Deserialises a whole number of units of the given number of seconds each, rejecting durations
+/// too long to represent.
+fn de_duration_units<'de, D: Deserializer<'de>>(
+    d: D,
+    unit_seconds: u64,
+) -> Result<Duration, D::Error> {
+    let units = u64::deserialize(d)?;
+    let seconds = units
+        .checked_mul(unit_seconds)
+        .ok_or_else(|| D::Error::custom("duration too large"))?;
+    Ok(Duration::from_secs(seconds))
+}
+
 impl Config {
-    pub fn from_file() -> Result<Config, Report> {
-        Config::read(CONFIG_FILENAME)
+    /// Reads the config from the given file, applying any overrides from environment variables
+    /// and reading secrets from files as described in [`apply_env_overrides`] and
+    /// [`read_secret_files`].
+    pub fn from_file(filename: &Path) -> Result<Config, Report> {
+        Config::read_with_env(filename, env::vars())
     }
 
+    #[cfg(test)]
     fn read(filename: &str) -> Result<Config, Report> {
+        Config::read_with_env(Path::new(filename), [])
+    }
+
+    fn read_with_env(
+        filename: &Path,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Config, Report> {
         let config_file =
-            read_to_string(filename).wrap_err_with(|| format!("Reading {filename}"))?;
-        Ok(toml::from_str(&config_file)?)
+            read_to_string(filename).wrap_err_with(|| format!("Reading {}", filename.display()))?;
+        let mut table = toml::from_str::<Table>(&config_file)
+            .wrap_err_with(|| format!("Parsing {}", filename.display()))?;
+        apply_env_overrides(&mut table, ENV_PREFIX, vars)?;
+        read_secret_files(&mut table)?;
+        let config: Config = deserialize_config(table)
+            .wrap_err_with(|| format!("Parsing {}", filename.display()))?;
+        if config.octopus.is_empty() {
+            bail!("No Octopus accounts configured in {}", filename.display());
+        }
+        Ok(config)
+    }
+}
+
+/// Where to send imported data.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SinkType {
+    /// Write data to InfluxDB, as configured in the `[influxdb]` section.
+    #[default]
+    InfluxDb,
+    /// Serve the latest data as Prometheus metrics, as configured in the `[prometheus]` section.
+    Prometheus,
+}
+
+/// Settings for serving Prometheus metrics.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrometheusConfig {
+    /// The address and port on which to serve metrics.
+    pub listen_address: SocketAddr,
+    /// How far back to import readings of each meter when `octo-influx` starts, as nothing is
+    /// stored between runs.
+    #[serde(deserialize_with = "de_duration_days", rename = "lookback_days")]
+    pub lookback: Duration,
+}
+
+impl Default for PrometheusConfig {
+    fn default() -> Self {
+        Self {
+            listen_address: DEFAULT_PROMETHEUS_LISTEN_ADDRESS.parse().unwrap(),
+            lookback: DEFAULT_PROMETHEUS_LOOKBACK,
+        }
     }
 }
 
+/// Connection details for InfluxDB.
+///
+/// If `bucket` is set then the 2.x API is used, authenticating with `token` if given. This also
+/// works with InfluxDB 3.x. Otherwise the 1.x API is used, with `database` and optionally
+/// `username` and `password`.
 #[derive(Clone, Debug, Deserialize)]
 #[serde(default, deny_unknown_fields)]
 pub struct InfluxDbConfig {
@@ -46,7 +238,15 @@ pub struct InfluxDbConfig {
     pub username: Option<String>,
     pub password: Option<String>,
     pub database: String,
+    pub token: Option<String>,
+    pub org: Option<String>,
+    pub bucket: Option<String>,
+    pub precision: Precision,
     pub measurement: String,
+    /// The measurement to which to write upcoming unit rates for Agile tariffs.
+    pub prices_measurement: String,
+    /// The measurement to which to write Intelligent Octopus dispatches.
+    pub dispatches_measurement: String,
 }
 
 impl Default for InfluxDbConfig {
@@ -56,19 +256,202 @@ impl Default for InfluxDbConfig {
             username: None,
             password: None,
             database: DEFAULT_DATABASE.to_owned(),
+            token: None,
+            org: None,
+            bucket: None,
+            precision: Precision::default(),
             measurement: DEFAULT_MEASUREMENT.to_owned(),
+            prices_measurement: DEFAULT_PRICES_MEASUREMENT.to_owned(),
+            dispatches_measurement: DEFAULT_DISPATCHES_MEASUREMENT.to_owned(),
+        }
+    }
+}
+
+impl InfluxDbConfig {
+    /// Returns the details with which to connect to InfluxDB.
+    pub fn connection(&self) -> ConnectionConfig {
+        ConnectionConfig {
+            url: self.url.clone(),
+            username: self.username.clone(),
+            password: self.password.clone(),
+            database: self.database.clone(),
+            token: self.token.clone(),
+            org: self.org.clone(),
+            bucket: self.bucket.clone(),
+            precision: self.precision,
+        }
+    }
+}
+
+/// Settings for running repeatedly with `--daemon`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    #[serde(deserialize_with = "de_duration_minutes", rename = "period_minutes")]
+    pub period: Duration,
+    /// The local time of day at which to do a deep resync, if any.
+    pub resync_time: Option<NaiveTime>,
+    #[serde(deserialize_with = "de_duration_days", rename = "resync_days")]
+    pub resync_overlap: Duration,
+    #[serde(
+        deserialize_with = "de_duration_seconds",
+        rename = "run_timeout_seconds"
+    )]
+    pub run_timeout: Duration,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> DaemonConfig {
+        DaemonConfig {
+            period: DEFAULT_DAEMON_PERIOD,
+            resync_time: None,
+            resync_overlap: DEFAULT_RESYNC_OVERLAP,
+            run_timeout: DEFAULT_RUN_TIMEOUT,
+        }
+    }
+}
+
+/// Which optional tags to add to readings.
+///
+/// Adding or removing tags changes which series readings are written to, so these should be set
+/// before the first import.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TagsConfig {
+    /// Add a `serial` tag with the serial number of the meter. Otherwise the serial number is
+    /// written as a field, so that readings from all meters of a meter point are written to the
+    /// same series.
+    pub serial: bool,
+    /// Add a `property_id` tag with the Octopus ID of the property.
+    pub property_id: bool,
+    /// Add a `postcode` tag with the postcode of the property.
+    pub postcode: bool,
+    /// Add a `tariff` tag with the tariff code which applied to each reading.
+    pub tariff: bool,
+    /// Add a `product` tag with the product code of the tariff which applied to each reading.
+    pub product: bool,
+    /// Add a `region` tag with the region letter of the tariff which applied to each reading.
+    pub region: bool,
+    /// Add a `direction` tag of `import` or `export`.
+    pub direction: bool,
+}
+
+impl Default for TagsConfig {
+    fn default() -> Self {
+        Self {
+            serial: true,
+            property_id: false,
+            postcode: false,
+            tariff: false,
+            product: false,
+            region: false,
+            direction: false,
+        }
+    }
+}
+
+/// Settings for which meters to import and how.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetersConfig {
+    /// Whether to import meters of properties which the account has moved out of.
+    pub include_moved_out: bool,
+    /// If this is not empty, only meters whose MPAN, MPRN or serial number is listed are imported.
+    pub include: Vec<String>,
+    /// Meters whose MPAN, MPRN or serial number is listed are not imported.
+    pub exclude: Vec<String>,
+    /// The unit in which gas meters report consumption, unless overridden for a particular meter.
+    pub gas_unit: GasUnit,
+    /// Settings for particular meters. The first which matches a meter applies.
+    pub overrides: Vec<MeterOverride>,
+}
+
+impl MetersConfig {
+    /// Returns whether the meter with the given MPAN or MPRN and serial number should be imported.
+    pub fn is_included(&self, mpxn: &str, serial: &str) -> bool {
+        let matches = |id: &String| id == mpxn || id == serial;
+        (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+
+    /// Returns the settings for the meter with the given MPAN or MPRN and serial number.
+    pub fn settings(&self, mpxn: &str, serial: &str) -> MeterSettings {
+        let meter_override = self
+            .overrides
+            .iter()
+            .find(|meter_override| meter_override.matches(mpxn, serial));
+        MeterSettings {
+            alias: meter_override.and_then(|meter_override| meter_override.alias.clone()),
+            group_by: meter_override.and_then(|meter_override| meter_override.group_by),
+            gas_unit: meter_override
+                .and_then(|meter_override| meter_override.gas_unit)
+                .unwrap_or(self.gas_unit),
         }
     }
 }
 
-/// Construct a new InfluxDB [`Client`] based on the given configuration options, for the given
-/// database.
-pub fn get_influxdb_client(config: &InfluxDbConfig) -> Result<Client, Report> {
-    let mut influxdb_client = Client::new(config.url.to_owned(), &config.database);
-    if let (Some(username), Some(password)) = (&config.username, &config.password) {
-        influxdb_client = influxdb_client.set_authentication(username, password);
+/// Settings for the meters with the given MPAN or MPRN and/or serial number.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct MeterOverride {
+    pub mpxn: Option<String>,
+    pub serial: Option<String>,
+    /// A name for the meter to add as an `alias` tag, such as `"house-main"`.
+    pub alias: Option<String>,
+    /// The level of aggregation with which to import readings. If this is not given then
+    /// half-hourly readings are imported.
+    pub group_by: Option<GroupBy>,
+    pub gas_unit: Option<GasUnit>,
+}
+
+impl MeterOverride {
+    fn matches(&self, mpxn: &str, serial: &str) -> bool {
+        self.mpxn.as_ref().is_none_or(|id| id == mpxn)
+            && self.serial.as_ref().is_none_or(|id| id == serial)
     }
-    Ok(influxdb_client)
+}
+
+/// The settings which apply to a particular meter.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MeterSettings {
+    pub alias: Option<String>,
+    pub group_by: Option<GroupBy>,
+    pub gas_unit: GasUnit,
+}
+
+/// The level of aggregation of consumption readings.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+    Hour,
+    Day,
+    Week,
+    Month,
+    Quarter,
+}
+
+impl From<GroupBy> for Grouping {
+    fn from(group_by: GroupBy) -> Self {
+        match group_by {
+            GroupBy::Hour => Self::Hour,
+            GroupBy::Day => Self::Day,
+            GroupBy::Week => Self::Week,
+            GroupBy::Month => Self::Month,
+            GroupBy::Quarter => Self::Quarter,
+        }
+    }
+}
+
+/// The unit in which a gas meter reports consumption.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+pub enum GasUnit {
+    /// Kilowatt-hours, as reported by SMETS1 gas meters.
+    #[serde(rename = "kwh")]
+    Kwh,
+    /// Cubic metres, as reported by SMETS2 gas meters.
+    #[default]
+    #[serde(rename = "m3")]
+    M3,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -77,11 +460,48 @@ pub struct OctopusConfig {
     pub email_address: String,
     pub password: String,
     pub account_id: String,
+    /// The measurement to which to write readings for this account, instead of
+    /// `influxdb.measurement`.
+    pub measurement: Option<String>,
+    /// The measurement to which to write Agile prices for this account, instead of
+    /// `influxdb.prices_measurement`.
+    pub prices_measurement: Option<String>,
+    /// Whether to import Intelligent Octopus smart charging dispatches for this account.
+    #[serde(default)]
+    pub dispatches: bool,
+    /// The measurement to which to write dispatches for this account, instead of
+    /// `influxdb.dispatches_measurement`.
+    pub dispatches_measurement: Option<String>,
+    /// Extra tags to add to every point for this account.
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+}
+
+impl OctopusConfig {
+    /// Returns the measurement to which to write readings for this account.
+    pub fn measurement<'a>(&'a self, influxdb: &'a InfluxDbConfig) -> &'a str {
+        self.measurement.as_deref().unwrap_or(&influxdb.measurement)
+    }
+
+    /// Returns the measurement to which to write Agile prices for this account.
+    pub fn prices_measurement<'a>(&'a self, influxdb: &'a InfluxDbConfig) -> &'a str {
+        self.prices_measurement
+            .as_deref()
+            .unwrap_or(&influxdb.prices_measurement)
+    }
+
+    /// Returns the measurement to which to write dispatches for this account.
+    pub fn dispatches_measurement<'a>(&'a self, influxdb: &'a InfluxDbConfig) -> &'a str {
+        self.dispatches_measurement
+            .as_deref()
+            .unwrap_or(&influxdb.dispatches_measurement)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::{env::temp_dir, fs::write, process};
 
     /// Parsing the example config file should not give any errors.
     #[test]
@@ -89,6 +509,266 @@ mod tests {
         Config::read("octo-influx.example.toml").unwrap();
     }
 
+    /// Parsing a daemon schedule should give the expected values.
+    #[test]
+    fn daemon_config() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [octopus]
+            email_address = "email@address.host"
+            password = "password"
+            account_id = "A-1234ABCD"
+
+            [daemon]
+            period_minutes = 30
+            resync_time = "04:00:00"
+            resync_days = 7
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.daemon.period, Duration::from_secs(30 * 60));
+        assert_eq!(
+            config.daemon.resync_time,
+            Some(NaiveTime::from_hms_opt(4, 0, 0).unwrap())
+        );
+        assert_eq!(
+            config.daemon.resync_overlap,
+            Duration::from_secs(7 * 24 * 60 * 60)
+        );
+        assert_eq!(config.daemon.run_timeout, DEFAULT_RUN_TIMEOUT);
+    }
+
+    /// The sink defaults to InfluxDB, but Prometheus can be chosen instead.
+    #[test]
+    fn prometheus_sink() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [octopus]
+            email_address = "email@address.host"
+            password = "password"
+            account_id = "A-1234ABCD"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.sink, SinkType::InfluxDb);
+
+        let config = toml::from_str::<Config>(
+            r#"
+            sink = "prometheus"
+
+            [octopus]
+            email_address = "email@address.host"
+            password = "password"
+            account_id = "A-1234ABCD"
+
+            [prometheus]
+            listen_address = "127.0.0.1:9999"
+            lookback_days = 2
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.sink, SinkType::Prometheus);
+        assert_eq!(
+            config.prometheus.listen_address,
+            "127.0.0.1:9999".parse().unwrap()
+        );
+        assert_eq!(
+            config.prometheus.lookback,
+            Duration::from_secs(2 * 24 * 60 * 60)
+        );
+    }
+
+    /// Several accounts can be given, each with their own overrides.
+    #[test]
+    fn multiple_accounts() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [influxdb]
+            measurement = "home"
+
+            [[octopus]]
+            email_address = "email@address.host"
+            password = "password"
+            account_id = "A-1234ABCD"
+
+            [[octopus]]
+            email_address = "other@address.host"
+            password = "password2"
+            account_id = "A-5678EFGH"
+            measurement = "flat"
+            dispatches = true
+            tags = { site = "flat" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.octopus.len(), 2);
+        assert_eq!(config.octopus[0].account_id, "A-1234ABCD");
+        assert_eq!(config.octopus[0].measurement(&config.influxdb), "home");
+        assert_eq!(config.octopus[1].account_id, "A-5678EFGH");
+        assert_eq!(config.octopus[1].measurement(&config.influxdb), "flat");
+        assert_eq!(
+            config.octopus[1].prices_measurement(&config.influxdb),
+            DEFAULT_PRICES_MEASUREMENT
+        );
+        assert!(!config.octopus[0].dispatches);
+        assert!(config.octopus[1].dispatches);
+        assert_eq!(
+            config.octopus[1].dispatches_measurement(&config.influxdb),
+            DEFAULT_DISPATCHES_MEASUREMENT
+        );
+        assert_eq!(
+            config.octopus[1].tags,
+            BTreeMap::from([("site".to_owned(), "flat".to_owned())])
+        );
+    }
+
+    /// Meters should be filtered and overridden as configured.
+    #[test]
+    fn meter_selection() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [octopus]
+            email_address = "email@address.host"
+            password = "password"
+            account_id = "A-1234ABCD"
+
+            [meters]
+            exclude = ["OLD123"]
+
+            [[meters.overrides]]
+            mpxn = "1234"
+            alias = "house-main"
+            group_by = "day"
+
+            [[meters.overrides]]
+            serial = "GAS1"
+            gas_unit = "kwh"
+            "#,
+        )
+        .unwrap();
+        assert!(!config.meters.include_moved_out);
+        assert!(config.meters.is_included("1234", "NEW456"));
+        assert!(!config.meters.is_included("1234", "OLD123"));
+        assert_eq!(
+            config.meters.settings("1234", "NEW456"),
+            MeterSettings {
+                alias: Some("house-main".to_owned()),
+                group_by: Some(GroupBy::Day),
+                gas_unit: GasUnit::M3,
+            }
+        );
+        assert_eq!(
+            config.meters.settings("5678", "GAS1"),
+            MeterSettings {
+                alias: None,
+                group_by: None,
+                gas_unit: GasUnit::Kwh,
+            }
+        );
+        assert_eq!(
+            config.meters.settings("5678", "GAS2"),
+            MeterSettings {
+                alias: None,
+                group_by: None,
+                gas_unit: GasUnit::M3,
+            }
+        );
+    }
+
+    /// If meters are listed to include then no others should be included.
+    #[test]
+    fn include_meters() {
+        let meters = MetersConfig {
+            include: vec!["1234".to_owned(), "GAS1".to_owned()],
+            ..Default::default()
+        };
+        assert!(meters.is_included("1234", "A"));
+        assert!(meters.is_included("5678", "GAS1"));
+        assert!(!meters.is_included("5678", "GAS2"));
+    }
+
+    /// Environment variables should override values from the config file.
+    #[test]
+    fn env_overrides() {
+        let config = Config::read_with_env(
+            Path::new("octo-influx.example.toml"),
+            [
+                (
+                    "OCTO_INFLUX__OCTOPUS__PASSWORD".to_owned(),
+                    "secret".to_owned(),
+                ),
+                ("OCTO_INFLUX__RETRIES".to_owned(), "5".to_owned()),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.octopus[0].password, "secret");
+        assert_eq!(config.retries, 5);
+    }
+
+    /// Environment variables should be able to set numbers and booleans which aren't in the config
+    /// file, without turning strings which look like numbers into numbers.
+    #[test]
+    fn env_overrides_new_keys() {
+        let path = temp_dir().join(format!("octo-influx-config-{}.toml", process::id()));
+        write(
+            &path,
+            r#"
+            [octopus]
+            email_address = "email@address.host"
+            password = "password"
+            account_id = "A-1234ABCD"
+            "#,
+        )
+        .unwrap();
+        let config = Config::read_with_env(
+            &path,
+            [
+                (
+                    "OCTO_INFLUX__OCTOPUS__DISPATCHES".to_owned(),
+                    "true".to_owned(),
+                ),
+                ("OCTO_INFLUX__RETRIES".to_owned(), "5".to_owned()),
+                (
+                    "OCTO_INFLUX__DAEMON__RUN_TIMEOUT_SECONDS".to_owned(),
+                    "60".to_owned(),
+                ),
+                (
+                    "OCTO_INFLUX__INFLUXDB__PASSWORD".to_owned(),
+                    "1234".to_owned(),
+                ),
+            ],
+        )
+        .unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert!(config.octopus[0].dispatches);
+        assert_eq!(config.retries, 5);
+        assert_eq!(config.daemon.run_timeout, Duration::from_secs(60));
+        assert_eq!(config.influxdb.password.as_deref(), Some("1234"));
+    }
+
+    /// Durations too long to represent should give an error rather than overflowing.
+    #[test]
+    fn duration_overflow() {
+        #[derive(Debug, Deserialize)]
+        struct Durations {
+            #[serde(default, deserialize_with = "de_duration_minutes")]
+            minutes: Duration,
+            #[serde(default, deserialize_with = "de_duration_hours")]
+            hours: Duration,
+            #[serde(default, deserialize_with = "de_duration_days")]
+            days: Duration,
+        }
+
+        let durations = toml::from_str::<Durations>("minutes = 5\nhours = 48\ndays = 30").unwrap();
+        assert_eq!(durations.minutes, Duration::from_secs(5 * 60));
+        assert_eq!(durations.hours, Duration::from_secs(48 * 60 * 60));
+        assert_eq!(durations.days, Duration::from_secs(30 * 24 * 60 * 60));
+        for unit in ["minutes", "hours", "days"] {
+            let error = toml::from_str::<Durations>(&format!("{unit} = {}", i64::MAX)).unwrap_err();
+            assert!(error.to_string().contains("duration too large"));
+        }
+    }
+
     /// Parsing an empty config file should give an error.
     #[test]
     fn empty_config() {
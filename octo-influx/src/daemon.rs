@@ -0,0 +1,134 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Running imports repeatedly on a schedule.
+
+use crate::{config::Config, import_all, sink::Sink, summary::Status};
+use chrono::{DateTime, Days, Local, NaiveTime, TimeZone};
+use eyre::Report;
+use log::{error, info};
+use std::pin::pin;
+use tokio::{select, signal::ctrl_c, time::timeout};
+
+/// Imports readings on the schedule configured in `config.daemon` until asked to stop by SIGTERM or
+/// Ctrl+C.
+pub async fn run_daemon(config: &Config, sink: &Sink) -> Result<(), Report> {
+    let mut tokens = vec![None; config.octopus.len()];
+    let mut shutdown = pin!(shutdown_signal());
+    let mut next_resync = config
+        .daemon
+        .resync_time
+        .map(|time| next_occurrence(Local::now(), time));
+
+    loop {
+        let resync = next_resync.is_some_and(|next_resync| Local::now() >= next_resync);
+        let overlap = if resync {
+            info!("Starting deep resync");
+            config.daemon.resync_overlap
+        } else {
+            info!("Starting import");
+            config.overlap
+        };
+        let run = timeout(
+            config.daemon.run_timeout,
+            import_all(config, sink, &mut tokens, overlap),
+        );
+        select! {
+            result = run => match result {
+                Ok(summary) => {
+                    sink.record_summary(&summary);
+                    match summary.status() {
+                        Status::Ok => info!("Import finished\n{summary}"),
+                        Status::Degraded | Status::Failed => error!("Import failed\n{summary}"),
+                    }
+                }
+                Err(_) => {
+                    sink.record_timeout();
+                    error!("Import timed out after {:?}", config.daemon.run_timeout);
+                }
+            },
+            result = &mut shutdown => {
+                info!("Stopping in the middle of import");
+                return result;
+            }
+        }
+
+        if resync {
+            next_resync = config
+                .daemon
+                .resync_time
+                .map(|time| next_occurrence(Local::now(), time));
+        }
+        let next_run = next_resync.map_or(Local::now() + config.daemon.period, |next_resync| {
+            next_resync.min(Local::now() + config.daemon.period)
+        });
+        info!("Next import at {next_run}");
+        let delay = (next_run - Local::now()).to_std().unwrap_or_default();
+        select! {
+            () = tokio::time::sleep(delay) => {}
+            result = &mut shutdown => {
+                info!("Stopping");
+                return result;
+            }
+        }
+    }
+}
+
+/// Waits until the process receives SIGTERM or Ctrl+C.
+async fn shutdown_signal() -> Result<(), Report> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        select! {
+            _ = terminate.recv() => info!("Received SIGTERM"),
+            result = ctrl_c() => result?,
+        }
+    }
+    #[cfg(not(unix))]
+    ctrl_c().await?;
+    Ok(())
+}
+
+/// Returns the next time after `now` which has the given time of day.
+fn next_occurrence<Tz: TimeZone>(now: DateTime<Tz>, time: NaiveTime) -> DateTime<Tz> {
+    let timezone = now.timezone();
+    let mut date = now.date_naive();
+    loop {
+        // If the time doesn't exist on some date because of a daylight saving change, skip it.
+        if let Some(next) = timezone
+            .from_local_datetime(&date.and_time(time))
+            .earliest()
+            && next > now
+        {
+            return next;
+        }
+        date = date + Days::new(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn next_occurrence_today() {
+        let now: DateTime<Utc> = "2024-06-01T03:00:00Z".parse().unwrap();
+        assert_eq!(
+            next_occurrence(now, NaiveTime::from_hms_opt(4, 0, 0).unwrap()),
+            "2024-06-01T04:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+    }
+
+    #[test]
+    fn next_occurrence_tomorrow() {
+        let now: DateTime<Utc> = "2024-06-01T04:00:00Z".parse().unwrap();
+        assert_eq!(
+            next_occurrence(now, NaiveTime::from_hms_opt(4, 0, 0).unwrap()),
+            "2024-06-02T04:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+    }
+}
@@ -0,0 +1,166 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Converting Intelligent Octopus dispatches to points.
+
+use chrono::{DateTime, Utc};
+use influx_db_client::Point;
+use octopower::results::dispatches::{Dispatch, Dispatches};
+
+/// Returns points for all the given planned and completed dispatches.
+///
+/// Each point is timestamped with the start of the dispatch and has its `status` as a field rather
+/// than a tag, so when a planned dispatch is completed, or the same dispatch is written again, the
+/// existing point is overwritten rather than a new one being added.
+pub fn points_for_dispatches(measurement: &str, dispatches: &Dispatches) -> Vec<Point<'static>> {
+    dispatches
+        .planned
+        .iter()
+        .map(|dispatch| point_for_dispatch(measurement, "planned", dispatch))
+        .chain(
+            dispatches
+                .completed
+                .iter()
+                .map(|dispatch| point_for_dispatch(measurement, "completed", dispatch)),
+        )
+        .collect()
+}
+
+fn point_for_dispatch(measurement: &str, status: &str, dispatch: &Dispatch) -> Point<'static> {
+    let mut point = Point::new(measurement)
+        .add_timestamp(dispatch.start.timestamp())
+        .add_field("status", status.to_owned())
+        .add_field("end", dispatch.end.timestamp())
+        .add_field("duration", (dispatch.end - dispatch.start).num_seconds());
+    if let Some(delta_kwh) = dispatch.delta_kwh {
+        point = point.add_field("delta_kwh", delta_kwh);
+    }
+    if let Some(source) = &dispatch.source {
+        point = point.add_field("source", source.clone());
+    }
+    if let Some(location) = &dispatch.location {
+        point = point.add_field("location", location.clone());
+    }
+    point
+}
+
+/// Returns the time before which dispatches may have been dropped from the list of completed
+/// dispatches, so planned dispatches which aren't in the current lists can't be assumed to have
+/// been cancelled.
+pub fn cancellation_cutoff(dispatches: &Dispatches, now: DateTime<Utc>) -> DateTime<Utc> {
+    dispatches
+        .completed
+        .iter()
+        .map(|dispatch| dispatch.start)
+        .min()
+        .map_or(now, |earliest| earliest.min(now))
+}
+
+/// Returns points marking as `cancelled` the previously planned dispatches starting at the given
+/// timestamps which are no longer planned or completed.
+///
+/// `stored_planned` should only include dispatches starting from [`cancellation_cutoff`].
+pub fn points_for_cancelled_dispatches(
+    measurement: &str,
+    dispatches: &Dispatches,
+    stored_planned: impl IntoIterator<Item = i64>,
+) -> Vec<Point<'static>> {
+    stored_planned
+        .into_iter()
+        .filter(|&start| {
+            !dispatches
+                .planned
+                .iter()
+                .chain(&dispatches.completed)
+                .any(|dispatch| dispatch.start.timestamp() == start)
+        })
+        .map(|start| {
+            Point::new(measurement)
+                .add_timestamp(start)
+                .add_field("status", "cancelled")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dispatch_points() {
+        let dispatches = Dispatches {
+            planned: vec![Dispatch {
+                start: "2024-01-02T01:00:00Z".parse().unwrap(),
+                end: "2024-01-02T02:30:00Z".parse().unwrap(),
+                delta_kwh: Some(-10.0),
+                source: Some("smart-charge".to_owned()),
+                location: None,
+            }],
+            completed: vec![Dispatch {
+                start: "2024-01-01T23:30:00Z".parse().unwrap(),
+                end: "2024-01-02T00:00:00Z".parse().unwrap(),
+                delta_kwh: None,
+                source: None,
+                location: Some("AT_HOME".to_owned()),
+            }],
+        };
+        assert_eq!(
+            points_for_dispatches("octopower_dispatches", &dispatches),
+            vec![
+                Point::new("octopower_dispatches")
+                    .add_timestamp(1704157200)
+                    .add_field("status", "planned")
+                    .add_field("end", 1704162600)
+                    .add_field("duration", 5400)
+                    .add_field("delta_kwh", -10.0)
+                    .add_field("source", "smart-charge"),
+                Point::new("octopower_dispatches")
+                    .add_timestamp(1704151800)
+                    .add_field("status", "completed")
+                    .add_field("end", 1704153600)
+                    .add_field("duration", 1800)
+                    .add_field("location", "AT_HOME"),
+            ]
+        );
+    }
+
+    #[test]
+    fn cancelled_dispatches() {
+        let dispatches = Dispatches {
+            planned: vec![Dispatch {
+                start: "2024-01-02T01:00:00Z".parse().unwrap(),
+                end: "2024-01-02T02:30:00Z".parse().unwrap(),
+                delta_kwh: None,
+                source: None,
+                location: None,
+            }],
+            completed: vec![Dispatch {
+                start: "2024-01-01T23:30:00Z".parse().unwrap(),
+                end: "2024-01-02T00:00:00Z".parse().unwrap(),
+                delta_kwh: None,
+                source: None,
+                location: None,
+            }],
+        };
+        let now = "2024-01-02T00:30:00Z".parse().unwrap();
+        assert_eq!(
+            cancellation_cutoff(&dispatches, now),
+            "2024-01-01T23:30:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        assert_eq!(cancellation_cutoff(&Dispatches::default(), now), now);
+        // The first is still planned, the second has been completed and the third was cancelled.
+        assert_eq!(
+            points_for_cancelled_dispatches(
+                "octopower_dispatches",
+                &dispatches,
+                [1704157200, 1704151800, 1704160800]
+            ),
+            vec![
+                Point::new("octopower_dispatches")
+                    .add_timestamp(1704160800)
+                    .add_field("status", "cancelled")
+            ]
+        );
+    }
+}
@@ -3,60 +3,309 @@
 // See LICENSE-APACHE and LICENSE-MIT for details.
 
 mod config;
+mod daemon;
+mod dispatches;
+mod prices;
+mod prometheus;
+mod sink;
+mod summary;
 
-use config::{Config, get_influxdb_client};
-use eyre::Report;
-use influx_db_client::{Client, Point, Precision};
-use log::info;
+use chrono::{DateTime, TimeDelta, Utc};
+use clap::Parser;
+use config::{
+    CONFIG_FILENAME, Config, GasUnit, MeterSettings, MetersConfig, OctopusConfig, SinkType,
+    TagsConfig,
+};
+use daemon::run_daemon;
+use dispatches::{cancellation_cutoff, points_for_cancelled_dispatches, points_for_dispatches};
+use eyre::{Report, WrapErr, bail};
+use influx_common::influxdb::InfluxDb;
+use influx_db_client::{Node, Point};
+use log::{error, info, warn};
 use octopower::{
-    AuthToken, MeterType, authenticate, get_account, get_consumption,
-    results::consumption::Consumption,
+    ApiError, AuthToken, ConsumptionOptions, Grouping, MeterType, Order,
+    active::ActiveMeter,
+    authenticate, authenticate_with_refresh_token, get_account, get_account_balance,
+    get_active_meters, get_consumption_with_options, get_dispatches,
+    results::{
+        account::{self, Account, Agreement, Property},
+        consumption::Consumption,
+    },
+    tariff::{RateType, TariffCode},
+};
+use prices::{Prices, PricesAt, fetch_rates, is_agile, point_for_unit_rate};
+use prometheus::serve;
+use sink::Sink;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::{self, Display, Formatter},
+    path::PathBuf,
+    process::ExitCode,
+    sync::Arc,
+    time::Duration,
 };
+use summary::Summary;
+use tokio::{net::TcpListener, select, time::sleep};
+
+/// How long before a token expires to get a new one.
+const TOKEN_EXPIRY_MARGIN: TimeDelta = TimeDelta::minutes(5);
 
-const INFLUXDB_PRECISION: Option<Precision> = Some(Precision::Seconds);
+/// The end time of the latest reading stored for each (MPXN, serial) pair.
+type LatestReadings = HashMap<(String, String), DateTime<Utc>>;
+
+/// A tool to import smart metering data from the Octopus Energy API into InfluxDB.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// The config file to use.
+    #[arg(long, default_value = CONFIG_FILENAME)]
+    config: PathBuf,
+    /// Keep running and import readings on the schedule configured in the `[daemon]` section of
+    /// the config file, rather than importing once and exiting.
+    #[arg(long)]
+    daemon: bool,
+    /// Print the points which would be written to InfluxDB in line protocol, rather than writing
+    /// them.
+    #[arg(long)]
+    dry_run: bool,
+    /// The file to which to write points with `--dry-run`, rather than stdout.
+    #[arg(long, requires = "dry_run")]
+    output: Option<PathBuf>,
+}
 
 #[tokio::main]
-async fn main() -> Result<(), Report> {
+async fn main() -> Result<ExitCode, Report> {
     pretty_env_logger::init();
 
-    let config = Config::from_file()?;
-    let influxdb_client = get_influxdb_client(&config.influxdb)?;
+    let args = Args::parse();
+    let config = Config::from_file(&args.config)?;
+    let sink = match config.sink {
+        SinkType::InfluxDb => {
+            let mut influxdb = InfluxDb::new(&config.influxdb.connection())?;
+            if args.dry_run {
+                influxdb = influxdb.dry_run(args.output.as_deref())?;
+            }
+            Sink::InfluxDb(influxdb)
+        }
+        SinkType::Prometheus => {
+            if args.dry_run {
+                bail!("--dry-run can't be used with the Prometheus sink");
+            }
+            Sink::Prometheus(Arc::default())
+        }
+    };
 
-    let token = authenticate(&config.octopus.email_address, &config.octopus.password).await?;
-    let account = get_account(&token, &config.octopus.account_id).await?;
+    if let Sink::Prometheus(metrics) = &sink {
+        // Metrics are only useful if they are kept up to date, so always run as a daemon.
+        let listener = TcpListener::bind(config.prometheus.listen_address)
+            .await
+            .wrap_err_with(|| format!("Listening on {}", config.prometheus.listen_address))?;
+        select! {
+            result = run_daemon(&config, &sink) => result?,
+            result = serve(listener, metrics.clone()) => result?,
+        }
+        Ok(ExitCode::SUCCESS)
+    } else if args.daemon {
+        run_daemon(&config, &sink).await?;
+        Ok(ExitCode::SUCCESS)
+    } else {
+        let mut tokens = vec![None; config.octopus.len()];
+        let summary = import_all(&config, &sink, &mut tokens, config.overlap).await;
+        // Don't mix the summary with the points being printed.
+        if args.dry_run && args.output.is_none() {
+            eprint!("{summary}");
+        } else {
+            print!("{summary}");
+        }
+        Ok(summary.status().exit_code())
+    }
+}
 
-    for property in &account.properties {
-        info!("Property {}", property.address_line_1);
-        for electricity_meter_point in &property.electricity_meter_points {
-            info!("Electricity MPAN {}", electricity_meter_point.mpan);
-            for meter in &electricity_meter_point.meters {
-                info!("Meter serial {}", meter.serial_number);
-                import_readings(
-                    &token,
-                    MeterType::Electricity,
-                    &electricity_meter_point.mpan,
-                    &meter.serial_number,
-                    &influxdb_client,
-                    &config.influxdb.measurement,
-                    config.num_readings,
-                )
-                .await?;
+/// Imports readings for all configured accounts, reusing the corresponding tokens if they are still
+/// valid or replacing them otherwise.
+///
+/// A failure for one account or meter doesn't stop the others from being imported. All failures are
+/// recorded in the returned summary.
+async fn import_all(
+    config: &Config,
+    sink: &Sink,
+    tokens: &mut [Option<AuthToken>],
+    overlap: Duration,
+) -> Summary {
+    let mut summary = Summary::default();
+    for (account, token) in config.octopus.iter().zip(tokens) {
+        info!("Account {}", account.account_id);
+        let failures_before = summary.failures.len();
+        let result = match valid_token(account, token).await {
+            Ok(token) => import(config, account, sink, token, overlap, &mut summary).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            error!("Import for account {} failed: {e:?}", account.account_id);
+            summary.fail(&account.account_id, "account", format!("{e:#}"));
+        }
+        if summary.failures.len() > failures_before {
+            // The token may be the problem, so get a new one next time.
+            *token = None;
+        }
+    }
+    summary
+}
+
+/// Returns the given token if it is still valid, or replaces it with a new one otherwise.
+async fn valid_token<'a>(
+    account: &OctopusConfig,
+    token: &'a mut Option<AuthToken>,
+) -> Result<&'a AuthToken, Report> {
+    let needs_new_token = token.as_ref().is_none_or(|token| {
+        token
+            .expires_at()
+            .is_none_or(|expires_at| expires_at - Utc::now() < TOKEN_EXPIRY_MARGIN)
+    });
+    if needs_new_token {
+        *token = Some(new_token(account, token.as_ref()).await?);
+    }
+    Ok(token.as_ref().unwrap())
+}
+
+/// Gets a new token, using the refresh token from the old one if possible.
+async fn new_token(
+    account: &OctopusConfig,
+    old_token: Option<&AuthToken>,
+) -> Result<AuthToken, Report> {
+    if let Some(refresh_token) = old_token.and_then(AuthToken::refresh_token) {
+        match authenticate_with_refresh_token(refresh_token).await {
+            Ok(token) => return Ok(token),
+            Err(e) => warn!("Failed to refresh token, logging in again: {e}"),
+        }
+    }
+    Ok(authenticate(&account.email_address, &account.password).await?)
+}
+
+/// Imports readings for all meters of the given account, starting `overlap` before the latest
+/// reading already stored for each.
+async fn import(
+    config: &Config,
+    account_config: &OctopusConfig,
+    sink: &Sink,
+    token: &AuthToken,
+    overlap: Duration,
+    summary: &mut Summary,
+) -> Result<(), Report> {
+    let account = get_account(token, &account_config.account_id).await?;
+    let measurement = account_config.measurement(&config.influxdb);
+    let mut tags = account_config.tags.clone();
+    tags.insert("account".to_owned(), account.number.clone());
+
+    let importer = Importer {
+        token,
+        sink,
+        measurement,
+        tags: &tags,
+        tag_config: &config.tags,
+        num_readings: config.num_readings,
+        overlap,
+        retries: config.retries,
+        retry_delay: config.retry_delay,
+        latest: latest_readings(sink, measurement).await?,
+        earliest: sink
+            .metrics()
+            .map(|_| Utc::now() - config.prometheus.lookback),
+    };
+
+    let now = Utc::now();
+    for meter_point in meter_points_to_import(&account, &config.meters, now) {
+        let active_meters = match get_active_meters(
+            token,
+            meter_point.meter_type,
+            meter_point.mpxn,
+            &meter_point.meters,
+            meter_point.agreements,
+        )
+        .await
+        {
+            Ok(active_meters) => active_meters,
+            Err(e) => {
+                error!("Finding active meters for {meter_point} failed: {e:?}");
+                summary.meters_attempted += 1;
+                summary.fail(&account.number, meter_point.to_string(), format!("{e:#}"));
+                continue;
+            }
+        };
+        if active_meters.is_empty() {
+            info!("No meters of {meter_point} have any readings");
+        }
+        for active_meter in &active_meters {
+            let meter = meter_point.meter(active_meter, &config.meters);
+            summary.meters_attempted += 1;
+            match importer.import_readings_with_retries(&meter).await {
+                Ok(points) => {
+                    summary.meters_succeeded += 1;
+                    summary.points_written += points;
+                }
+                Err(e) => {
+                    error!("Import for {meter} failed: {e:?}");
+                    summary.fail(&account.number, meter.to_string(), format!("{e:#}"));
+                }
             }
         }
-        for gas_meter_point in &property.gas_meter_points {
-            info!("Gas MPRN {}", gas_meter_point.mprn);
-            for meter in &gas_meter_point.meters {
-                info!("Meter serial {}", meter.serial_number);
-                import_readings(
-                    &token,
-                    MeterType::Gas,
-                    &gas_meter_point.mprn,
-                    &meter.serial_number,
-                    &influxdb_client,
-                    &config.influxdb.measurement,
-                    config.num_readings,
-                )
-                .await?;
+    }
+
+    // Prometheus metrics include the current and next unit rate for all tariffs, not just Agile.
+    let upcoming_price_tariffs = account
+        .properties
+        .iter()
+        .flat_map(|property| &property.electricity_meter_points)
+        .filter_map(|meter_point| meter_point.agreement_at(now))
+        .filter_map(|agreement| agreement.tariff_code.parse::<TariffCode>().ok())
+        .filter(|tariff| is_agile(tariff) || (sink.metrics().is_some() && tariff.registers == 1))
+        .collect::<HashSet<_>>();
+    for tariff in upcoming_price_tariffs {
+        match import_upcoming_prices(
+            sink,
+            account_config.prices_measurement(&config.influxdb),
+            &tags,
+            &tariff,
+            now,
+        )
+        .await
+        {
+            Ok(points) => summary.points_written += points,
+            Err(e) => {
+                error!("Import of prices for {tariff} failed: {e:?}");
+                summary.fail(
+                    &account.number,
+                    format!("prices for {tariff}"),
+                    format!("{e:#}"),
+                );
+            }
+        }
+    }
+
+    if let Some(metrics) = sink.metrics() {
+        match get_account_balance(token, &account.number).await {
+            Ok(balance) => metrics.set_balance(&account.number, balance),
+            Err(e) => {
+                error!("Fetching balance failed: {e:?}");
+                summary.fail(&account.number, "balance", format!("{e:#}"));
+            }
+        }
+    }
+
+    if account_config.dispatches {
+        match import_dispatches(
+            sink,
+            token,
+            &account.number,
+            account_config.dispatches_measurement(&config.influxdb),
+            &tags,
+        )
+        .await
+        {
+            Ok(points) => summary.points_written += points,
+            Err(e) => {
+                error!("Import of dispatches failed: {e:?}");
+                summary.fail(&account.number, "dispatches", format!("{e:#}"));
             }
         }
     }
@@ -64,45 +313,822 @@ async fn main() -> Result<(), Report> {
     Ok(())
 }
 
-async fn import_readings(
-    token: &AuthToken,
+/// A meter point from which to import readings.
+#[derive(Clone, Debug, PartialEq)]
+struct MeterPoint<'a> {
     meter_type: MeterType,
-    mpxn: &str,
-    serial: &str,
-    influxdb_client: &Client,
+    mpxn: &'a str,
+    /// The meters of the meter point which aren't excluded by the config.
+    meters: Vec<account::Meter>,
+    /// The agreements of the meter point, used to look up prices and tariffs.
+    agreements: &'a [Agreement],
+    is_export: bool,
+    property: &'a Property,
+}
+
+impl Display for MeterPoint<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} meter point {}", self.meter_type, self.mpxn)
+    }
+}
+
+impl<'a> MeterPoint<'a> {
+    /// Returns the meter of this meter point for the given active period.
+    fn meter(&self, active_meter: &'a ActiveMeter, config: &MetersConfig) -> Meter<'a> {
+        Meter {
+            meter_type: self.meter_type,
+            mpxn: self.mpxn,
+            serial: &active_meter.serial_number,
+            from: active_meter.from,
+            to: active_meter.to,
+            agreements: self.agreements,
+            is_export: self.is_export,
+            property: self.property,
+            settings: config.settings(self.mpxn, &active_meter.serial_number),
+        }
+    }
+}
+
+/// A meter from which to import readings, during the period when it was active.
+#[derive(Clone, Debug, PartialEq)]
+struct Meter<'a> {
+    meter_type: MeterType,
+    mpxn: &'a str,
+    serial: &'a str,
+    /// The time from which the meter was active.
+    from: DateTime<Utc>,
+    /// The time until which the meter was active, or `None` if it still is.
+    to: Option<DateTime<Utc>>,
+    /// The agreements of the meter point, used to look up prices and tariffs.
+    agreements: &'a [Agreement],
+    is_export: bool,
+    property: &'a Property,
+    settings: MeterSettings,
+}
+
+impl Display for Meter<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} meter {} serial {}",
+            self.meter_type, self.mpxn, self.serial
+        )
+    }
+}
+
+impl Meter<'_> {
+    /// Returns whether the meter reports consumption in kWh.
+    fn is_kwh(&self) -> bool {
+        self.meter_type == MeterType::Electricity || self.settings.gas_unit == GasUnit::Kwh
+    }
+}
+
+/// Returns the meter points of the given account which should be imported according to the given
+/// config, with only the meters which aren't excluded.
+fn meter_points_to_import<'a>(
+    account: &'a Account,
+    config: &MetersConfig,
+    now: DateTime<Utc>,
+) -> Vec<MeterPoint<'a>> {
+    let mut meter_points = Vec::new();
+    for property in &account.properties {
+        if !config.include_moved_out
+            && property
+                .moved_out_at
+                .is_some_and(|moved_out_at| moved_out_at <= now)
+        {
+            info!("Skipping moved out property {}", property.address_line_1);
+            continue;
+        }
+        for meter_point in &property.electricity_meter_points {
+            meter_points.push(MeterPoint {
+                meter_type: MeterType::Electricity,
+                mpxn: &meter_point.mpan,
+                meters: meter_point.meters.clone(),
+                agreements: &meter_point.agreements,
+                is_export: meter_point.is_export,
+                property,
+            });
+        }
+        for meter_point in &property.gas_meter_points {
+            meter_points.push(MeterPoint {
+                meter_type: MeterType::Gas,
+                mpxn: &meter_point.mprn,
+                meters: meter_point.meters.clone(),
+                agreements: &meter_point.agreements,
+                is_export: false,
+                property,
+            });
+        }
+    }
+    for meter_point in &mut meter_points {
+        meter_point.meters.retain(|meter| {
+            let included = config.is_included(meter_point.mpxn, &meter.serial_number);
+            if !included {
+                info!(
+                    "Skipping excluded {} meter {} {}",
+                    meter_point.meter_type, meter_point.mpxn, meter.serial_number
+                );
+            }
+            included
+        });
+    }
+    meter_points.retain(|meter_point| !meter_point.meters.is_empty());
+    meter_points
+}
+
+/// Imports the unit rates of the given tariff from the start of the current day onwards, including
+/// any which have been published for the future.
+async fn import_upcoming_prices(
+    sink: &Sink,
     measurement: &str,
-    num_readings: usize,
-) -> Result<(), Report> {
-    let consumption =
-        get_consumption(token, meter_type, mpxn, serial, 0, num_readings, None).await?;
+    tags: &BTreeMap<String, String>,
+    tariff: &TariffCode,
+    now: DateTime<Utc>,
+) -> Result<usize, Report> {
+    let from = now.date_naive().and_time(Default::default()).and_utc();
+    let rates = fetch_rates(tariff, RateType::StandardUnit, from, None).await?;
+    info!("Importing {} unit rates for {tariff}", rates.len());
+    let points = rates
+        .iter()
+        .map(|rate| add_tags(point_for_unit_rate(measurement, tariff, rate), tags))
+        .collect::<Vec<_>>();
+    let count = points.len();
+    if !points.is_empty() {
+        sink.write_points(points).await?;
+    }
+    Ok(count)
+}
+
+/// Imports the planned and completed Intelligent Octopus dispatches of the given account.
+///
+/// The API reports all recent completed dispatches every time, but as they are written with the
+/// same timestamp and tags each time they simply overwrite the existing points. Previously planned
+/// dispatches which are no longer planned or completed are marked as cancelled.
+async fn import_dispatches(
+    sink: &Sink,
+    token: &AuthToken,
+    account_id: &str,
+    measurement: &str,
+    tags: &BTreeMap<String, String>,
+) -> Result<usize, Report> {
+    let dispatches = get_dispatches(token, account_id).await?;
     info!(
-        "{:?} consumption: {}/{} records",
-        meter_type,
-        consumption.results.len(),
-        consumption.count
+        "Importing {} planned and {} completed dispatches",
+        dispatches.planned.len(),
+        dispatches.completed.len()
     );
-    let points = consumption
-        .results
+    let cutoff = cancellation_cutoff(&dispatches, Utc::now());
+    let stored_planned = stored_planned_dispatches(sink, measurement, account_id, cutoff).await?;
+    let points = points_for_dispatches(measurement, &dispatches)
         .into_iter()
-        .map(|reading| point_for_reading(measurement, meter_type, mpxn, serial, reading));
-    influxdb_client
-        .write_points(points, INFLUXDB_PRECISION, None)
-        .await?;
+        .chain(points_for_cancelled_dispatches(
+            measurement,
+            &dispatches,
+            stored_planned,
+        ))
+        .map(|point| add_tags(point, tags))
+        .collect::<Vec<_>>();
+    let count = points.len();
+    if !points.is_empty() {
+        sink.write_points(points).await?;
+    }
+    Ok(count)
+}
 
-    Ok(())
+/// Queries the sink for the start times of dispatches of the given account from `cutoff` onwards
+/// which are stored as planned.
+async fn stored_planned_dispatches(
+    sink: &Sink,
+    measurement: &str,
+    account_id: &str,
+    cutoff: DateTime<Utc>,
+) -> Result<Vec<i64>, Report> {
+    match sink {
+        Sink::InfluxDb(influxdb) => {
+            let query = format!(
+                "SELECT \"status\" FROM {} WHERE \"status\"::field = 'planned' AND \"account\" = {} \
+                 AND time >= {}s",
+                quote_identifier(measurement),
+                quote_string(account_id),
+                cutoff.timestamp()
+            );
+            let nodes = influxdb.query(&query).await?;
+            Ok(parse_timestamps(nodes))
+        }
+        // Dispatches aren't exported as metrics.
+        Sink::Prometheus(_) => Ok(Vec::new()),
+    }
+}
+
+/// Returns the timestamps of all values in the given query results.
+fn parse_timestamps(nodes: Vec<Node>) -> Vec<i64> {
+    nodes
+        .into_iter()
+        .flat_map(|node| node.series.unwrap_or_default())
+        .flat_map(|series| {
+            let time_index = series.columns.iter().position(|column| column == "time");
+            series
+                .values
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(move |value| value.get(time_index?)?.as_i64())
+        })
+        .collect()
+}
+
+/// Queries the sink for the time of the latest reading stored for each meter.
+async fn latest_readings(sink: &Sink, measurement: &str) -> Result<LatestReadings, Report> {
+    match sink {
+        Sink::InfluxDb(influxdb) => {
+            let query = format!(
+                "SELECT last(\"consumption\") FROM {} GROUP BY \"mpxn\", \"serial\"",
+                quote_identifier(measurement)
+            );
+            let nodes = influxdb.query(&query).await?;
+            Ok(parse_latest_readings(nodes))
+        }
+        Sink::Prometheus(metrics) => Ok(metrics.latest_readings(measurement)),
+    }
+}
+
+fn parse_latest_readings(nodes: Vec<Node>) -> LatestReadings {
+    nodes
+        .into_iter()
+        .flat_map(|node| node.series.unwrap_or_default())
+        .filter_map(|series| {
+            let tags = series.tags?;
+            let mpxn = tags.get("mpxn")?.as_str()?.to_owned();
+            let serial = tags.get("serial")?.as_str()?.to_owned();
+            let time_index = series.columns.iter().position(|column| column == "time")?;
+            let timestamp = series.values?.first()?.get(time_index)?.as_i64()?;
+            Some(((mpxn, serial), DateTime::from_timestamp(timestamp, 0)?))
+        })
+        .collect()
+}
+
+/// Quotes the given string as an InfluxQL string literal.
+fn quote_string(string: &str) -> String {
+    format!("'{}'", string.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Quotes the given string as an InfluxQL identifier.
+fn quote_identifier(identifier: &str) -> String {
+    format!(
+        "\"{}\"",
+        identifier.replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+/// Imports readings from the Octopus API into the configured sink.
+struct Importer<'a> {
+    token: &'a AuthToken,
+    sink: &'a Sink,
+    measurement: &'a str,
+    /// Tags to add to every point.
+    tags: &'a BTreeMap<String, String>,
+    /// Which optional tags to add to readings.
+    tag_config: &'a TagsConfig,
+    num_readings: usize,
+    overlap: Duration,
+    retries: u32,
+    retry_delay: Duration,
+    latest: LatestReadings,
+    /// The earliest readings to import for meters with no readings stored yet, or `None` to import
+    /// their full history.
+    earliest: Option<DateTime<Utc>>,
+}
+
+impl Importer<'_> {
+    /// Imports readings for the given meter as [`Self::import_readings`], retrying with exponential
+    /// backoff if it fails with an error which might be temporary.
+    async fn import_readings_with_retries(&self, meter: &Meter<'_>) -> Result<usize, Report> {
+        let mut delay = self.retry_delay;
+        let mut attempt = 0;
+        loop {
+            match self.import_readings(meter).await {
+                Ok(points) => return Ok(points),
+                Err(e) if attempt < self.retries && is_retryable(&e) => {
+                    attempt += 1;
+                    warn!("Import for {meter} failed, retrying in {delay:?}: {e:#}");
+                    sleep(delay).await;
+                    delay *= 2;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Imports all readings for the given meter since shortly before the latest one already stored,
+    /// or since `earliest` or its entire history if none are stored yet, along with the prices which
+    /// applied to them.
+    ///
+    /// Returns the number of points written.
+    async fn import_readings(&self, meter: &Meter<'_>) -> Result<usize, Report> {
+        info!("Importing {meter}");
+        // If readings from all meters of a meter point are written to the same series then the
+        // latest reading is for the whole meter point.
+        let series_serial = if self.tag_config.serial {
+            meter.serial
+        } else {
+            ""
+        };
+        let period_from = match self
+            .latest
+            .get(&(meter.mpxn.to_owned(), series_serial.to_owned()))
+        {
+            Some(latest) => {
+                let period_from = (*latest - self.overlap).max(meter.from);
+                info!("Importing readings since {period_from}");
+                period_from
+            }
+            None => match self.earliest {
+                Some(earliest) if earliest > meter.from => {
+                    info!("No existing readings, importing readings since {earliest}");
+                    earliest
+                }
+                _ => {
+                    info!("No existing readings, importing full history");
+                    meter.from
+                }
+            },
+        };
+        if meter.to.is_some_and(|to| to <= period_from) {
+            info!("{meter} is no longer active and is up to date");
+            return Ok(0);
+        }
+        let options = ConsumptionOptions {
+            period_from: Some(period_from),
+            period_to: meter.to,
+            grouping: meter.settings.group_by.map(Into::into),
+            // Import the oldest readings first, so that if the import is interrupted the next run
+            // will carry on from where it stopped rather than leaving a gap.
+            order: Order::OldestFirst,
+        };
+
+        let mut count = 0;
+        for page in 0.. {
+            let consumption = get_consumption_with_options(
+                self.token,
+                meter.meter_type,
+                meter.mpxn,
+                meter.serial,
+                page,
+                self.num_readings,
+                &options,
+            )
+            .await?;
+            count += consumption.results.len();
+            info!(
+                "{:?} consumption: {}/{} records",
+                meter.meter_type, count, consumption.count
+            );
+            // Prices change within a day, so can't be given for grouped readings.
+            let prices = match (consumption.results.first(), consumption.results.last()) {
+                (Some(first), Some(last)) if meter.settings.group_by.is_none() => {
+                    let from = first.interval_start.min(last.interval_start);
+                    let to = first.interval_end.max(last.interval_end);
+                    Prices::fetch(meter.agreements, from, to).await?
+                }
+                _ => Prices::default(),
+            };
+            let points = consumption
+                .results
+                .into_iter()
+                .map(|reading| {
+                    let prices = prices.at(meter.agreements, reading.interval_start);
+                    let point = point_for_reading(
+                        self.measurement,
+                        meter,
+                        reading,
+                        prices,
+                        self.tag_config,
+                    );
+                    add_tags(point, self.tags)
+                })
+                .collect::<Vec<_>>();
+            if !points.is_empty() {
+                self.sink.write_points(points).await?;
+            }
+            if consumption.next.is_none() {
+                break;
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+/// Returns whether the given error might go away if the request is retried.
+///
+/// Client errors from the Octopus API, such as a 404 for a meter which no longer exists, are
+/// assumed to be permanent, except for rate limiting.
+fn is_retryable(error: &Report) -> bool {
+    match error.downcast_ref::<ApiError>() {
+        Some(ApiError::RestError { status, .. }) => {
+            !status.is_client_error() || status.as_u16() == 429
+        }
+        _ => true,
+    }
+}
+
+/// Adds the given tags to the given point.
+fn add_tags<'a>(mut point: Point<'a>, tags: &'a BTreeMap<String, String>) -> Point<'a> {
+    for (key, value) in tags {
+        point = point.add_tag(key.as_str(), value.as_str());
+    }
+    point
 }
 
+/// Returns a point for the given reading, including the prices which applied to it if they are
+/// known, and its cost in pence if it is also in kWh.
 fn point_for_reading<'a>(
     measurement: &str,
-    meter_type: MeterType,
-    mpxn: &'a str,
-    serial: &'a str,
+    meter: &Meter<'a>,
     reading: Consumption,
+    prices: Option<PricesAt>,
+    tags: &TagsConfig,
 ) -> Point<'a> {
-    Point::new(measurement)
+    let consumption = f64::from(reading.consumption);
+    let mut point = Point::new(measurement)
         .add_timestamp(reading.interval_end.timestamp())
-        .add_tag("type", meter_type.to_string())
-        .add_tag("mpxn", mpxn)
-        .add_tag("serial", serial)
-        .add_field("consumption", reading.consumption as f64)
+        .add_tag("type", meter.meter_type.to_string())
+        .add_tag("mpxn", meter.mpxn)
+        .add_field("consumption", consumption);
+    if tags.serial {
+        point = point.add_tag("serial", meter.serial);
+    } else {
+        point = point.add_field("serial", meter.serial);
+    }
+    if let Some(group_by) = meter.settings.group_by {
+        point = point.add_tag("grouping", Grouping::from(group_by).to_string());
+    }
+    if let Some(alias) = &meter.settings.alias {
+        point = point.add_tag("alias", alias.clone());
+    }
+    if tags.property_id {
+        point = point.add_tag("property_id", i64::from(meter.property.id));
+    }
+    if tags.postcode {
+        point = point.add_tag("postcode", meter.property.postcode.as_str());
+    }
+    if tags.direction {
+        point = point.add_tag(
+            "direction",
+            if meter.is_export { "export" } else { "import" },
+        );
+    }
+    if tags.tariff || tags.product || tags.region {
+        let tariff_code = meter
+            .agreements
+            .iter()
+            .find(|agreement| agreement.is_valid_at(reading.interval_start))
+            .map(|agreement| agreement.tariff_code.as_str());
+        let tariff = tariff_code.and_then(|tariff_code| tariff_code.parse::<TariffCode>().ok());
+        if tags.tariff
+            && let Some(tariff_code) = tariff_code
+        {
+            point = point.add_tag("tariff", tariff_code);
+        }
+        if let Some(tariff) = tariff {
+            if tags.product {
+                point = point.add_tag("product", tariff.product_code);
+            }
+            if tags.region {
+                point = point.add_tag("region", tariff.region.to_string());
+            }
+        }
+    }
+    if let Some(prices) = prices {
+        point = point
+            .add_field("unit_rate", prices.unit_rate)
+            .add_field("standing_charge", prices.standing_charge);
+        if meter.is_kwh() {
+            point = point.add_field("cost", consumption * prices.unit_rate);
+        }
+    }
+    point
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::GroupBy;
+    use influx_db_client::reqwest::StatusCode;
+
+    #[test]
+    fn parse_latest() {
+        let nodes: Vec<Node> = serde_json::from_str(
+            r#"[
+                {
+                    "statement_id": 0,
+                    "series": [
+                        {
+                            "name": "octopower",
+                            "tags": { "mpxn": "1234", "serial": "A" },
+                            "columns": ["time", "last"],
+                            "values": [[1704067200, 0.5]]
+                        },
+                        {
+                            "name": "octopower",
+                            "tags": { "mpxn": "5678", "serial": "B" },
+                            "columns": ["time", "last"],
+                            "values": [[1704070800, 1.25]]
+                        }
+                    ]
+                }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            parse_latest_readings(nodes),
+            LatestReadings::from([
+                (
+                    ("1234".to_owned(), "A".to_owned()),
+                    "2024-01-01T00:00:00Z".parse().unwrap()
+                ),
+                (
+                    ("5678".to_owned(), "B".to_owned()),
+                    "2024-01-01T01:00:00Z".parse().unwrap()
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_latest_empty() {
+        let nodes: Vec<Node> = serde_json::from_str(r#"[{ "statement_id": 0 }]"#).unwrap();
+        assert_eq!(parse_latest_readings(nodes), LatestReadings::new());
+    }
+
+    #[test]
+    fn parse_planned_dispatches() {
+        let nodes: Vec<Node> = serde_json::from_str(
+            r#"[{
+                "statement_id": 0,
+                "series": [{
+                    "name": "octopower_dispatches",
+                    "columns": ["time", "status"],
+                    "values": [[1704157200, "planned"], [1704160800, "planned"]]
+                }]
+            }]"#,
+        )
+        .unwrap();
+        assert_eq!(parse_timestamps(nodes), vec![1704157200, 1704160800]);
+    }
+
+    fn test_account() -> Account {
+        serde_json::from_str(
+            r#"{
+                "number": "A-1234ABCD",
+                "properties": [
+                    {
+                        "id": 1,
+                        "moved_in_at": "2020-01-01T00:00:00Z",
+                        "moved_out_at": "2023-01-01T00:00:00Z",
+                        "address_line_1": "Old house",
+                        "address_line_2": "",
+                        "address_line_3": "",
+                        "town": "",
+                        "county": "",
+                        "postcode": "",
+                        "electricity_meter_points": [
+                            {
+                                "mpan": "1111",
+                                "profile_class": 1,
+                                "consumption_standard": 2000,
+                                "meters": [{ "serial_number": "OLD" }],
+                                "agreements": [],
+                                "is_export": false
+                            }
+                        ],
+                        "gas_meter_points": []
+                    },
+                    {
+                        "id": 2,
+                        "moved_in_at": "2023-01-01T00:00:00Z",
+                        "moved_out_at": null,
+                        "address_line_1": "New house",
+                        "address_line_2": "",
+                        "address_line_3": "",
+                        "town": "",
+                        "county": "",
+                        "postcode": "AB1 2CD",
+                        "electricity_meter_points": [],
+                        "gas_meter_points": [
+                            {
+                                "mprn": "2222",
+                                "consumption_standard": 10000,
+                                "meters": [{ "serial_number": "GAS1" }, { "serial_number": "GAS2" }],
+                                "agreements": [
+                                    {
+                                        "tariff_code": "G-1R-VAR-22-11-01-A",
+                                        "valid_from": "2023-01-01T00:00:00Z",
+                                        "valid_to": null
+                                    }
+                                ]
+                            }
+                        ]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn skip_moved_out() {
+        let account = test_account();
+        let now = "2024-01-01T00:00:00Z".parse().unwrap();
+        let config = MetersConfig {
+            exclude: vec!["GAS1".to_owned()],
+            ..Default::default()
+        };
+
+        let meter_points = meter_points_to_import(&account, &config, now);
+        assert_eq!(
+            meter_points
+                .iter()
+                .map(|meter_point| (
+                    meter_point.mpxn,
+                    meter_point
+                        .meters
+                        .iter()
+                        .map(|meter| meter.serial_number.as_str())
+                        .collect::<Vec<_>>()
+                ))
+                .collect::<Vec<_>>(),
+            vec![("2222", vec!["GAS2"])]
+        );
+
+        let config = MetersConfig {
+            include_moved_out: true,
+            ..Default::default()
+        };
+        assert_eq!(meter_points_to_import(&account, &config, now).len(), 2);
+    }
+
+    #[test]
+    fn meter_for_active_period() {
+        let account = test_account();
+        let now = "2024-01-01T00:00:00Z".parse().unwrap();
+        let config = MetersConfig::default();
+        let meter_points = meter_points_to_import(&account, &config, now);
+        let active_meter = ActiveMeter {
+            serial_number: "GAS2".to_owned(),
+            from: "2023-06-01T00:00:00Z".parse().unwrap(),
+            to: None,
+        };
+        let meter = meter_points[0].meter(&active_meter, &config);
+        assert_eq!(meter.to_string(), "gas meter 2222 serial GAS2");
+        assert_eq!(meter.from, active_meter.from);
+        assert_eq!(meter.to, None);
+    }
+
+    #[test]
+    fn point_without_cost() {
+        let account = test_account();
+        let meter = Meter {
+            meter_type: MeterType::Gas,
+            mpxn: "2222",
+            serial: "GAS1",
+            from: "2023-01-01T00:00:00Z".parse().unwrap(),
+            to: None,
+            agreements: &[],
+            is_export: false,
+            property: &account.properties[1],
+            settings: MeterSettings {
+                alias: None,
+                group_by: None,
+                gas_unit: GasUnit::M3,
+            },
+        };
+        let reading = Consumption {
+            consumption: 0.5,
+            interval_start: "2024-01-01T00:00:00Z".parse().unwrap(),
+            interval_end: "2024-01-01T00:30:00Z".parse().unwrap(),
+        };
+        let prices = PricesAt {
+            unit_rate: 6.0,
+            standing_charge: 30.0,
+        };
+        assert_eq!(
+            point_for_reading(
+                "octopower",
+                &meter,
+                reading.clone(),
+                Some(prices),
+                &TagsConfig::default()
+            ),
+            Point::new("octopower")
+                .add_timestamp(1704069000)
+                .add_tag("type", "gas")
+                .add_tag("mpxn", "2222")
+                .add_tag("serial", "GAS1")
+                .add_field("consumption", 0.5)
+                .add_field("unit_rate", 6.0)
+                .add_field("standing_charge", 30.0)
+        );
+
+        let meter = Meter {
+            settings: MeterSettings {
+                alias: None,
+                group_by: Some(GroupBy::Day),
+                gas_unit: GasUnit::Kwh,
+            },
+            ..meter
+        };
+        assert_eq!(
+            point_for_reading(
+                "octopower",
+                &meter,
+                reading,
+                Some(prices),
+                &TagsConfig::default()
+            ),
+            Point::new("octopower")
+                .add_timestamp(1704069000)
+                .add_tag("type", "gas")
+                .add_tag("mpxn", "2222")
+                .add_tag("serial", "GAS1")
+                .add_tag("grouping", "day")
+                .add_field("consumption", 0.5)
+                .add_field("unit_rate", 6.0)
+                .add_field("standing_charge", 30.0)
+                .add_field("cost", 3.0)
+        );
+    }
+
+    #[test]
+    fn optional_tags() {
+        let account = test_account();
+        let meter_point = &account.properties[1].gas_meter_points[0];
+        let meter = Meter {
+            meter_type: MeterType::Gas,
+            mpxn: "2222",
+            serial: "GAS1",
+            from: "2023-01-01T00:00:00Z".parse().unwrap(),
+            to: None,
+            agreements: &meter_point.agreements,
+            is_export: false,
+            property: &account.properties[1],
+            settings: MeterSettings {
+                alias: Some("house-gas".to_owned()),
+                group_by: None,
+                gas_unit: GasUnit::Kwh,
+            },
+        };
+        let reading = Consumption {
+            consumption: 0.5,
+            interval_start: "2024-01-01T00:00:00Z".parse().unwrap(),
+            interval_end: "2024-01-01T00:30:00Z".parse().unwrap(),
+        };
+        let tags = TagsConfig {
+            serial: false,
+            property_id: true,
+            postcode: true,
+            tariff: true,
+            product: true,
+            region: true,
+            direction: true,
+        };
+        assert_eq!(
+            point_for_reading("octopower", &meter, reading, None, &tags),
+            Point::new("octopower")
+                .add_timestamp(1704069000)
+                .add_tag("type", "gas")
+                .add_tag("mpxn", "2222")
+                .add_tag("alias", "house-gas")
+                .add_tag("property_id", 2)
+                .add_tag("postcode", "AB1 2CD")
+                .add_tag("direction", "import")
+                .add_tag("tariff", "G-1R-VAR-22-11-01-A")
+                .add_tag("product", "VAR-22-11-01")
+                .add_tag("region", "A")
+                .add_field("consumption", 0.5)
+                .add_field("serial", "GAS1")
+        );
+    }
+
+    #[test]
+    fn retryable() {
+        let rest_error = |status| {
+            Report::new(ApiError::RestError {
+                status,
+                body: String::new(),
+            })
+        };
+        assert!(!is_retryable(&rest_error(StatusCode::NOT_FOUND)));
+        assert!(is_retryable(&rest_error(StatusCode::TOO_MANY_REQUESTS)));
+        assert!(is_retryable(&rest_error(StatusCode::BAD_GATEWAY)));
+        assert!(is_retryable(&eyre::eyre!("Connection reset")));
+    }
+
+    #[test]
+    fn quote() {
+        assert_eq!(quote_identifier("octopower"), "\"octopower\"");
+        assert_eq!(quote_identifier("a \"b\""), "\"a \\\"b\\\"\"");
+        assert_eq!(quote_string("A-1234ABCD"), "'A-1234ABCD'");
+        assert_eq!(quote_string("a 'b'"), "'a \\'b\\''");
+    }
 }
@@ -0,0 +1,219 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Looking up the prices which applied to readings.
+
+use chrono::{DateTime, Utc};
+use eyre::Report;
+use influx_db_client::Point;
+use log::warn;
+use octopower::{
+    get_tariff_rates,
+    results::{
+        account::Agreement,
+        rates::{Rate, rate_at},
+    },
+    tariff::{RateType, TariffCode},
+};
+use std::collections::HashMap;
+
+/// The payment method of prices which don't apply to most customers, who pay by direct debit.
+const NON_DIRECT_DEBIT: &str = "NON_DIRECT_DEBIT";
+
+/// The prices which applied to a meter point at some time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PricesAt {
+    /// The unit rate in pence per kWh, including VAT.
+    pub unit_rate: f64,
+    /// The standing charge in pence per day, including VAT.
+    pub standing_charge: f64,
+}
+
+/// Unit rates and standing charges for the tariffs of a meter point over some period.
+#[derive(Debug, Default)]
+pub struct Prices {
+    tariffs: HashMap<String, TariffPrices>,
+}
+
+#[derive(Debug)]
+struct TariffPrices {
+    unit_rates: Vec<Rate>,
+    standing_charges: Vec<Rate>,
+}
+
+impl Prices {
+    /// Fetches the prices for all the given agreements which apply at some point between `from` and
+    /// `to`.
+    ///
+    /// Tariffs with more than one register are skipped, as there's no way to tell which register
+    /// a reading belongs to.
+    pub async fn fetch(
+        agreements: &[Agreement],
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Self, Report> {
+        let mut tariffs = HashMap::new();
+        for agreement in agreements {
+            if agreement.valid_from >= to
+                || agreement.valid_to.is_some_and(|valid_to| valid_to <= from)
+                || tariffs.contains_key(&agreement.tariff_code)
+            {
+                continue;
+            }
+            let tariff: TariffCode = match agreement.tariff_code.parse() {
+                Ok(tariff) => tariff,
+                Err(e) => {
+                    warn!("Not fetching prices: {e}");
+                    continue;
+                }
+            };
+            if tariff.registers != 1 {
+                warn!(
+                    "Not fetching prices for tariff {tariff} with {} registers",
+                    tariff.registers
+                );
+                continue;
+            }
+            let unit_rates = fetch_rates(&tariff, RateType::StandardUnit, from, Some(to)).await?;
+            let standing_charges =
+                fetch_rates(&tariff, RateType::StandingCharge, from, Some(to)).await?;
+            tariffs.insert(
+                agreement.tariff_code.clone(),
+                TariffPrices {
+                    unit_rates,
+                    standing_charges,
+                },
+            );
+        }
+        Ok(Self { tariffs })
+    }
+
+    /// Returns the prices which applied at the given time, according to the given agreements.
+    pub fn at(&self, agreements: &[Agreement], time: DateTime<Utc>) -> Option<PricesAt> {
+        let agreement = agreements
+            .iter()
+            .find(|agreement| agreement.is_valid_at(time))?;
+        let tariff = self.tariffs.get(&agreement.tariff_code)?;
+        Some(PricesAt {
+            unit_rate: rate_at(&tariff.unit_rates, time)?.value_inc_vat,
+            standing_charge: rate_at(&tariff.standing_charges, time)?.value_inc_vat,
+        })
+    }
+}
+
+/// Returns whether the given tariff has prices which change every half hour and are published in
+/// advance.
+pub fn is_agile(tariff: &TariffCode) -> bool {
+    tariff.registers == 1 && tariff.product_code.starts_with("AGILE")
+}
+
+/// Fetches the rates of the given type for the given tariff, excluding those which don't apply to
+/// direct debit customers.
+pub async fn fetch_rates(
+    tariff: &TariffCode,
+    rate_type: RateType,
+    from: DateTime<Utc>,
+    to: Option<DateTime<Utc>>,
+) -> Result<Vec<Rate>, Report> {
+    Ok(get_tariff_rates(tariff, rate_type, Some(from), to)
+        .await?
+        .into_iter()
+        .filter(|rate| rate.payment_method.as_deref() != Some(NON_DIRECT_DEBIT))
+        .collect())
+}
+
+/// Returns a point for the given unit rate of the given tariff, timestamped with the start of the
+/// period for which it applies.
+pub fn point_for_unit_rate(measurement: &str, tariff: &TariffCode, rate: &Rate) -> Point<'static> {
+    Point::new(measurement)
+        .add_timestamp(rate.valid_from.timestamp())
+        .add_tag("tariff", tariff.to_string())
+        .add_tag("product", tariff.product_code.clone())
+        .add_tag("region", tariff.region.to_string())
+        .add_field("unit_rate", rate.value_inc_vat)
+        .add_field("unit_rate_exc_vat", rate.value_exc_vat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate(value: f64, valid_from: &str, valid_to: Option<&str>) -> Rate {
+        Rate {
+            value_exc_vat: value / 1.05,
+            value_inc_vat: value,
+            valid_from: valid_from.parse().unwrap(),
+            valid_to: valid_to.map(|valid_to| valid_to.parse().unwrap()),
+            payment_method: None,
+        }
+    }
+
+    #[test]
+    fn prices_at() {
+        let agreements = vec![
+            Agreement {
+                tariff_code: "E-1R-VAR-22-11-01-C".to_owned(),
+                valid_from: "2024-01-01T00:00:00Z".parse().unwrap(),
+                valid_to: Some("2024-02-01T00:00:00Z".parse().unwrap()),
+            },
+            Agreement {
+                tariff_code: "E-1R-AGILE-FLEX-22-11-25-C".to_owned(),
+                valid_from: "2024-02-01T00:00:00Z".parse().unwrap(),
+                valid_to: None,
+            },
+        ];
+        let prices = Prices {
+            tariffs: HashMap::from([
+                (
+                    "E-1R-VAR-22-11-01-C".to_owned(),
+                    TariffPrices {
+                        unit_rates: vec![rate(25.0, "2024-01-01T00:00:00Z", None)],
+                        standing_charges: vec![rate(50.0, "2024-01-01T00:00:00Z", None)],
+                    },
+                ),
+                (
+                    "E-1R-AGILE-FLEX-22-11-25-C".to_owned(),
+                    TariffPrices {
+                        unit_rates: vec![
+                            rate(15.0, "2024-02-01T00:30:00Z", Some("2024-02-01T01:00:00Z")),
+                            rate(12.0, "2024-02-01T00:00:00Z", Some("2024-02-01T00:30:00Z")),
+                        ],
+                        standing_charges: vec![rate(45.0, "2024-01-01T00:00:00Z", None)],
+                    },
+                ),
+            ]),
+        };
+
+        assert_eq!(
+            prices.at(&agreements, "2024-01-31T23:30:00Z".parse().unwrap()),
+            Some(PricesAt {
+                unit_rate: 25.0,
+                standing_charge: 50.0
+            })
+        );
+        assert_eq!(
+            prices.at(&agreements, "2024-02-01T00:30:00Z".parse().unwrap()),
+            Some(PricesAt {
+                unit_rate: 15.0,
+                standing_charge: 45.0
+            })
+        );
+        assert_eq!(
+            prices.at(&agreements, "2024-02-01T01:00:00Z".parse().unwrap()),
+            None
+        );
+        assert_eq!(
+            prices.at(&agreements, "2023-12-31T00:00:00Z".parse().unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn agile() {
+        assert!(is_agile(&"E-1R-AGILE-FLEX-22-11-25-C".parse().unwrap()));
+        assert!(is_agile(&"E-1R-AGILE-OUTGOING-19-05-13-C".parse().unwrap()));
+        assert!(!is_agile(&"E-1R-VAR-22-11-01-C".parse().unwrap()));
+        assert!(!is_agile(&"E-2R-VAR-22-11-01-C".parse().unwrap()));
+    }
+}
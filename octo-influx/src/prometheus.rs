@@ -0,0 +1,608 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Serving imported data as Prometheus metrics, as an alternative to writing it to InfluxDB.
+
+use crate::{LatestReadings, summary::Summary};
+use chrono::{DateTime, Utc};
+use eyre::Report;
+use influx_db_client::{Point, Value};
+use log::{info, warn};
+use std::{
+    collections::{BTreeMap, btree_map::Entry},
+    fmt::Write,
+    io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+
+/// The labels of a metric, sorted by name.
+type Labels = BTreeMap<String, String>;
+
+/// Unit rates of a tariff, keyed by the timestamp in seconds from which they apply.
+type UnitRates = BTreeMap<i64, f64>;
+
+/// The maximum size of a request line and headers.
+const MAX_REQUEST_SIZE: u64 = 8 * 1024;
+/// How long to wait for a client to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The latest imported data, to be served as Prometheus metrics.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Consumption readings, by measurement and tags.
+    readings: BTreeMap<(String, Labels), ReadingSeries>,
+    /// Current and future unit rates, by measurement and tags.
+    unit_rates: BTreeMap<(String, Labels), UnitRates>,
+    /// Account balances in pence, by account number.
+    balances: BTreeMap<String, i64>,
+    /// The time at which the last import finished without any failures.
+    last_success: Option<DateTime<Utc>>,
+    /// The number of imports which have finished or timed out.
+    runs: u64,
+    /// The total number of failures across all imports.
+    failures: u64,
+}
+
+/// The latest reading of a meter, and the total of all readings imported.
+#[derive(Debug)]
+struct ReadingSeries {
+    /// The timestamp of the latest reading in seconds.
+    timestamp: i64,
+    /// The consumption in the latest reading.
+    consumption: f64,
+    /// The total consumption of all readings imported.
+    total: f64,
+}
+
+impl Metrics {
+    /// Stores the given points, forgetting unit rates which had been superseded by the given time.
+    ///
+    /// Points with a `consumption` field are treated as readings, and points with a
+    /// `unit_rate_exc_vat` field as unit rates. Others are ignored. Readings no newer than the
+    /// latest one stored for the same series are ignored, so that readings fetched again because
+    /// of the overlap aren't counted twice. A unit rate with the same timestamp and tags as an
+    /// existing one replaces it, as it would in InfluxDB.
+    pub fn write_points(&self, points: &[Point], now: DateTime<Utc>) {
+        let mut state = self.state.lock().unwrap();
+        for point in points {
+            let Some(timestamp) = point.timestamp else {
+                continue;
+            };
+            let key = (point.measurement.clone(), labels(point));
+            if let Some(consumption) = point.fields.get("consumption").and_then(as_f64) {
+                match state.readings.entry(key) {
+                    Entry::Vacant(entry) => {
+                        entry.insert(ReadingSeries {
+                            timestamp,
+                            consumption,
+                            total: consumption,
+                        });
+                    }
+                    Entry::Occupied(mut entry) => {
+                        let series = entry.get_mut();
+                        if timestamp > series.timestamp {
+                            series.timestamp = timestamp;
+                            series.consumption = consumption;
+                            series.total += consumption;
+                        }
+                    }
+                }
+            } else if point.fields.contains_key("unit_rate_exc_vat")
+                && let Some(unit_rate) = point.fields.get("unit_rate").and_then(as_f64)
+            {
+                state
+                    .unit_rates
+                    .entry(key)
+                    .or_default()
+                    .insert(timestamp, unit_rate);
+            }
+        }
+        for unit_rates in state.unit_rates.values_mut() {
+            if let Some((&current, _)) = unit_rates.range(..=now.timestamp()).next_back() {
+                *unit_rates = unit_rates.split_off(&current);
+            }
+        }
+    }
+
+    /// Returns the time of the latest reading stored for each meter in the given measurement.
+    ///
+    /// Meters without a `serial` tag are given an empty serial number, as InfluxDB would.
+    pub fn latest_readings(&self, measurement: &str) -> LatestReadings {
+        let state = self.state.lock().unwrap();
+        let mut latest = LatestReadings::new();
+        for ((series_measurement, labels), series) in &state.readings {
+            if series_measurement != measurement {
+                continue;
+            }
+            let Some(mpxn) = labels.get("mpxn") else {
+                continue;
+            };
+            let Some(time) = DateTime::from_timestamp(series.timestamp, 0) else {
+                continue;
+            };
+            let serial = labels.get("serial").cloned().unwrap_or_default();
+            latest
+                .entry((mpxn.clone(), serial))
+                .and_modify(|latest| *latest = time.max(*latest))
+                .or_insert(time);
+        }
+        latest
+    }
+
+    /// Sets the current balance of the given account, in pence.
+    pub fn set_balance(&self, account: &str, balance: i64) {
+        self.state
+            .lock()
+            .unwrap()
+            .balances
+            .insert(account.to_owned(), balance);
+    }
+
+    /// Records the outcome of an import which finished at the given time.
+    pub fn record_summary(&self, summary: &Summary, now: DateTime<Utc>) {
+        let mut state = self.state.lock().unwrap();
+        state.runs += 1;
+        state.failures += summary.failures.len() as u64;
+        if summary.failures.is_empty() {
+            state.last_success = Some(now);
+        }
+    }
+
+    /// Records that an import timed out.
+    pub fn record_timeout(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.runs += 1;
+        state.failures += 1;
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format, with unit rates as of the
+    /// given time.
+    pub fn render(&self, now: DateTime<Utc>) -> String {
+        let state = self.state.lock().unwrap();
+        let mut output = String::new();
+
+        write_header(
+            &mut output,
+            "octopus_consumption",
+            "gauge",
+            "Consumption in the latest reading, in kWh or m³ depending on the meter.",
+        );
+        for ((_, labels), series) in &state.readings {
+            write_sample(
+                &mut output,
+                "octopus_consumption",
+                labels,
+                series.consumption,
+            );
+        }
+        write_header(
+            &mut output,
+            "octopus_consumption_timestamp_seconds",
+            "gauge",
+            "The start time of the latest reading.",
+        );
+        for ((_, labels), series) in &state.readings {
+            write_sample(
+                &mut output,
+                "octopus_consumption_timestamp_seconds",
+                labels,
+                series.timestamp,
+            );
+        }
+        write_header(
+            &mut output,
+            "octopus_consumption_total",
+            "counter",
+            "Total consumption of all readings imported, in kWh or m³ depending on the meter.",
+        );
+        for ((_, labels), series) in &state.readings {
+            write_sample(
+                &mut output,
+                "octopus_consumption_total",
+                labels,
+                series.total,
+            );
+        }
+
+        write_header(
+            &mut output,
+            "octopus_unit_rate_pence",
+            "gauge",
+            "The current unit rate in pence per kWh, including VAT.",
+        );
+        for ((_, labels), unit_rates) in &state.unit_rates {
+            if let Some((_, unit_rate)) = unit_rates.range(..=now.timestamp()).next_back() {
+                write_sample(&mut output, "octopus_unit_rate_pence", labels, *unit_rate);
+            }
+        }
+        write_header(
+            &mut output,
+            "octopus_next_unit_rate_pence",
+            "gauge",
+            "The next unit rate in pence per kWh, including VAT, if it has been published.",
+        );
+        for ((_, labels), unit_rates) in &state.unit_rates {
+            if let Some((_, unit_rate)) = unit_rates.range(now.timestamp() + 1..).next() {
+                write_sample(
+                    &mut output,
+                    "octopus_next_unit_rate_pence",
+                    labels,
+                    *unit_rate,
+                );
+            }
+        }
+
+        write_header(
+            &mut output,
+            "octopus_account_balance_pence",
+            "gauge",
+            "The account balance in pence. Positive means that the account is in credit.",
+        );
+        for (account, balance) in &state.balances {
+            let labels = Labels::from([("account".to_owned(), account.clone())]);
+            write_sample(
+                &mut output,
+                "octopus_account_balance_pence",
+                &labels,
+                *balance,
+            );
+        }
+
+        write_header(
+            &mut output,
+            "octo_influx_last_success_timestamp_seconds",
+            "gauge",
+            "The time at which the last import without any failures finished.",
+        );
+        if let Some(last_success) = state.last_success {
+            write_sample(
+                &mut output,
+                "octo_influx_last_success_timestamp_seconds",
+                &Labels::new(),
+                last_success.timestamp(),
+            );
+        }
+        write_header(
+            &mut output,
+            "octo_influx_runs_total",
+            "counter",
+            "The number of imports which have finished or timed out.",
+        );
+        write_sample(
+            &mut output,
+            "octo_influx_runs_total",
+            &Labels::new(),
+            state.runs,
+        );
+        write_header(
+            &mut output,
+            "octo_influx_failures_total",
+            "counter",
+            "The number of accounts, meters or other items which failed to be imported, and \
+             imports which timed out.",
+        );
+        write_sample(
+            &mut output,
+            "octo_influx_failures_total",
+            &Labels::new(),
+            state.failures,
+        );
+
+        output
+    }
+}
+
+/// Returns the tags of the given point as metric labels.
+fn labels(point: &Point) -> Labels {
+    point
+        .tags
+        .iter()
+        .map(|(name, value)| {
+            let value = match value {
+                Value::String(value) => value.to_string(),
+                Value::Integer(value) => value.to_string(),
+                Value::Float(value) => value.to_string(),
+                Value::Boolean(value) => value.to_string(),
+            };
+            (label_name(name), value)
+        })
+        .collect()
+}
+
+fn as_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Float(value) => Some(*value),
+        Value::Integer(value) => Some(*value as f64),
+        Value::String(_) | Value::Boolean(_) => None,
+    }
+}
+
+/// Converts the given tag name to a valid label name, by replacing any invalid characters with
+/// underscores.
+fn label_name(name: &str) -> String {
+    name.char_indices()
+        .map(|(i, c)| {
+            if c.is_ascii_alphabetic() || c == '_' || (i > 0 && c.is_ascii_digit()) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn write_header(output: &mut String, name: &str, metric_type: &str, help: &str) {
+    writeln!(output, "# HELP {name} {help}").unwrap();
+    writeln!(output, "# TYPE {name} {metric_type}").unwrap();
+}
+
+fn write_sample(output: &mut String, name: &str, labels: &Labels, value: impl ToString) {
+    output.push_str(name);
+    if !labels.is_empty() {
+        output.push('{');
+        for (i, (label, value)) in labels.iter().enumerate() {
+            if i > 0 {
+                output.push(',');
+            }
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            write!(output, "{label}=\"{value}\"").unwrap();
+        }
+        output.push('}');
+    }
+    writeln!(output, " {}", value.to_string()).unwrap();
+}
+
+/// Serves the given metrics at `/metrics` over HTTP on the given listener, until an error occurs
+/// accepting a connection.
+pub async fn serve(listener: TcpListener, metrics: Arc<Metrics>) -> Result<(), Report> {
+    info!(
+        "Serving metrics on http://{}/metrics",
+        listener.local_addr()?
+    );
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &metrics).await {
+                warn!("Error serving metrics to {peer}: {e}");
+            }
+        });
+    }
+}
+
+/// Handles a single HTTP request, then closes the connection.
+async fn handle_connection(mut stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    let request_line = timeout(REQUEST_TIMEOUT, read_request_line(&mut stream))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "Timed out reading request"))??;
+
+    let (status, body) = if let Some(request_line) = request_line {
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default();
+        let path = parts.next().unwrap_or_default();
+        let path = path.split_once('?').map_or(path, |(path, _)| path);
+        match (method, path) {
+            ("GET", "/metrics") => ("200 OK", metrics.render(Utc::now())),
+            ("GET", _) => ("404 Not Found", "Not found\n".to_owned()),
+            _ => ("405 Method Not Allowed", "Method not allowed\n".to_owned()),
+        }
+    } else {
+        ("400 Bad Request", "Bad request\n".to_owned())
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Reads the request line and skips the headers, as none of them matter.
+///
+/// Returns `None` if the headers don't end within `MAX_REQUEST_SIZE` bytes.
+async fn read_request_line(stream: &mut TcpStream) -> io::Result<Option<String>> {
+    let mut reader = BufReader::new(stream.take(MAX_REQUEST_SIZE));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        if line.trim_end().is_empty() {
+            return Ok(Some(request_line));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(mpxn: &str, serial: &str, timestamp: i64, consumption: f64) -> Point<'static> {
+        Point::new("octopower")
+            .add_timestamp(timestamp)
+            .add_tag("type", "electricity")
+            .add_tag("mpxn", mpxn.to_owned())
+            .add_tag("serial", serial.to_owned())
+            .add_field("consumption", consumption)
+    }
+
+    fn unit_rate(timestamp: i64, unit_rate: f64) -> Point<'static> {
+        Point::new("octopower_prices")
+            .add_timestamp(timestamp)
+            .add_tag("tariff", "E-1R-AGILE-FLEX-22-11-25-C")
+            .add_field("unit_rate", unit_rate)
+            .add_field("unit_rate_exc_vat", unit_rate / 1.05)
+    }
+
+    #[test]
+    fn readings() {
+        let metrics = Metrics::default();
+        let now = "2024-01-01T02:00:00Z".parse().unwrap();
+        metrics.write_points(
+            &[
+                reading("1234", "A", 1704067200, 0.5),
+                reading("1234", "A", 1704069000, 0.25),
+            ],
+            now,
+        );
+        // Readings fetched again because of the overlap shouldn't be counted twice.
+        metrics.write_points(
+            &[
+                reading("1234", "A", 1704067200, 0.5),
+                reading("1234", "A", 1704069000, 0.25),
+                reading("1234", "A", 1704070800, 1.0),
+            ],
+            now,
+        );
+        assert_eq!(
+            metrics.latest_readings("octopower"),
+            LatestReadings::from([(
+                ("1234".to_owned(), "A".to_owned()),
+                "2024-01-01T01:00:00Z".parse().unwrap()
+            )])
+        );
+        assert_eq!(metrics.latest_readings("other"), LatestReadings::new());
+
+        let rendered = metrics.render(now);
+        assert!(rendered.contains(
+            "\noctopus_consumption{mpxn=\"1234\",serial=\"A\",type=\"electricity\"} 1\n"
+        ));
+        assert!(rendered.contains(
+            "\noctopus_consumption_timestamp_seconds{mpxn=\"1234\",serial=\"A\",\
+             type=\"electricity\"} 1704070800\n"
+        ));
+        assert!(rendered.contains(
+            "\noctopus_consumption_total{mpxn=\"1234\",serial=\"A\",type=\"electricity\"} 1.75\n"
+        ));
+    }
+
+    #[test]
+    fn unit_rates() {
+        let metrics = Metrics::default();
+        metrics.write_points(
+            &[
+                unit_rate(1704067200, 20.0),
+                unit_rate(1704069000, 15.0),
+                unit_rate(1704070800, 30.0),
+            ],
+            "2024-01-01T00:15:00Z".parse().unwrap(),
+        );
+        let rendered = metrics.render("2024-01-01T00:45:00Z".parse().unwrap());
+        assert!(
+            rendered
+                .contains("\noctopus_unit_rate_pence{tariff=\"E-1R-AGILE-FLEX-22-11-25-C\"} 15\n")
+        );
+        assert!(rendered.contains(
+            "\noctopus_next_unit_rate_pence{tariff=\"E-1R-AGILE-FLEX-22-11-25-C\"} 30\n"
+        ));
+
+        let rendered = metrics.render("2024-01-01T01:15:00Z".parse().unwrap());
+        assert!(
+            rendered
+                .contains("\noctopus_unit_rate_pence{tariff=\"E-1R-AGILE-FLEX-22-11-25-C\"} 30\n")
+        );
+        assert!(!rendered.contains("\noctopus_next_unit_rate_pence{"));
+
+        // Unit rates which have been superseded are forgotten.
+        metrics.write_points(&[], "2024-01-01T00:45:00Z".parse().unwrap());
+        assert_eq!(
+            metrics
+                .state
+                .lock()
+                .unwrap()
+                .unit_rates
+                .values()
+                .collect::<Vec<_>>(),
+            [&UnitRates::from([(1704069000, 15.0), (1704070800, 30.0)])]
+        );
+    }
+
+    #[test]
+    fn health() {
+        let metrics = Metrics::default();
+        metrics.set_balance("A-1234ABCD", -1250);
+        metrics.record_summary(&Summary::default(), "2024-01-01T00:00:00Z".parse().unwrap());
+        let mut summary = Summary::default();
+        summary.fail("A-1234ABCD", "dispatches", "GraphQL errors");
+        metrics.record_summary(&summary, "2024-01-01T01:00:00Z".parse().unwrap());
+        metrics.record_timeout();
+
+        let rendered = metrics.render("2024-01-01T02:00:00Z".parse().unwrap());
+        assert!(
+            rendered.contains("\noctopus_account_balance_pence{account=\"A-1234ABCD\"} -1250\n")
+        );
+        assert!(rendered.contains("\nocto_influx_last_success_timestamp_seconds 1704067200\n"));
+        assert!(rendered.contains("\nocto_influx_runs_total 3\n"));
+        assert!(rendered.contains("\nocto_influx_failures_total 2\n"));
+    }
+
+    #[test]
+    fn label_names() {
+        assert_eq!(label_name("site"), "site");
+        assert_eq!(label_name("site-name"), "site_name");
+        assert_eq!(label_name("2nd"), "_nd");
+    }
+
+    #[tokio::test]
+    async fn serve_metrics() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let metrics = Arc::new(Metrics::default());
+        metrics.set_balance("A-1234ABCD", 500);
+        tokio::spawn(serve(listener, metrics));
+
+        let client = influx_db_client::reqwest::Client::new();
+        let response = client
+            .get(format!("http://{address}/metrics"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body = response.text().await.unwrap();
+        assert!(body.contains("\noctopus_account_balance_pence{account=\"A-1234ABCD\"} 500\n"));
+
+        let response = client
+            .get(format!("http://{address}/other"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn request_too_large() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, Arc::new(Metrics::default())));
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        // Send exactly the maximum size without ending the headers, so that the server reads all of
+        // it before responding.
+        let mut request = "GET /metrics HTTP/1.1\r\nX-Padding: ".to_owned();
+        request.push_str(&"a".repeat(MAX_REQUEST_SIZE as usize - request.len()));
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    }
+}
@@ -0,0 +1,55 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! The destinations to which imported points can be sent.
+
+use crate::{prometheus::Metrics, summary::Summary};
+use chrono::Utc;
+use eyre::Report;
+use influx_common::influxdb::InfluxDb;
+use influx_db_client::Point;
+use std::sync::Arc;
+
+/// Where to send imported points.
+pub enum Sink {
+    /// Write points to InfluxDB.
+    InfluxDb(InfluxDb),
+    /// Keep the latest values in memory to be served as Prometheus metrics.
+    Prometheus(Arc<Metrics>),
+}
+
+impl Sink {
+    /// Sends the given points to the sink. Their timestamps must be in seconds.
+    pub async fn write_points(&self, points: Vec<Point<'_>>) -> Result<(), Report> {
+        match self {
+            Self::InfluxDb(influxdb) => influxdb.write_points(points).await,
+            Self::Prometheus(metrics) => {
+                metrics.write_points(&points, Utc::now());
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the Prometheus metrics, if that is the sink being used.
+    pub fn metrics(&self) -> Option<&Metrics> {
+        match self {
+            Self::InfluxDb(_) => None,
+            Self::Prometheus(metrics) => Some(metrics),
+        }
+    }
+
+    /// Records the outcome of an import, for sinks which report on the importer's health.
+    pub fn record_summary(&self, summary: &Summary) {
+        if let Some(metrics) = self.metrics() {
+            metrics.record_summary(summary, Utc::now());
+        }
+    }
+
+    /// Records that an import timed out, for sinks which report on the importer's health.
+    pub fn record_timeout(&self) {
+        if let Some(metrics) = self.metrics() {
+            metrics.record_timeout();
+        }
+    }
+}
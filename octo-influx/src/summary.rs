@@ -0,0 +1,151 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! A summary of the results of an import.
+
+use std::{
+    fmt::{self, Display, Formatter},
+    process::ExitCode,
+};
+
+/// Exit code when nothing could be imported.
+const EXIT_FAILED: u8 = 1;
+// Exit code 2 is used by clap for invalid command-line arguments.
+/// Exit code when some meters or accounts were imported but others failed.
+const EXIT_DEGRADED: u8 = 3;
+
+/// The results of importing from all configured accounts.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Summary {
+    /// The number of meters from which an import was attempted.
+    pub meters_attempted: usize,
+    /// The number of meters which were imported successfully.
+    pub meters_succeeded: usize,
+    /// The total number of points written to InfluxDB.
+    pub points_written: usize,
+    /// Details of everything which failed.
+    pub failures: Vec<Failure>,
+}
+
+/// Something which failed to be imported.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Failure {
+    /// The account number.
+    pub account: String,
+    /// What failed within the account, such as a particular meter.
+    pub what: String,
+    /// The error which caused the failure.
+    pub reason: String,
+}
+
+/// The overall outcome of an import.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Status {
+    /// Everything was imported successfully.
+    Ok,
+    /// Some things were imported but others failed.
+    Degraded,
+    /// Nothing was imported successfully.
+    Failed,
+}
+
+impl Summary {
+    /// Records a failure.
+    pub fn fail(&mut self, account: &str, what: impl Into<String>, reason: impl Display) {
+        self.failures.push(Failure {
+            account: account.to_owned(),
+            what: what.into(),
+            reason: reason.to_string(),
+        });
+    }
+
+    /// Returns the overall outcome.
+    pub fn status(&self) -> Status {
+        if self.failures.is_empty() {
+            Status::Ok
+        } else if self.meters_succeeded == 0 {
+            Status::Failed
+        } else {
+            Status::Degraded
+        }
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "Meters attempted: {}", self.meters_attempted)?;
+        writeln!(f, "Meters succeeded: {}", self.meters_succeeded)?;
+        writeln!(
+            f,
+            "Meters failed: {}",
+            self.meters_attempted - self.meters_succeeded
+        )?;
+        writeln!(f, "Points written: {}", self.points_written)?;
+        if !self.failures.is_empty() {
+            writeln!(f, "Failures:")?;
+            for failure in &self.failures {
+                writeln!(
+                    f,
+                    "  {} {}: {}",
+                    failure.account, failure.what, failure.reason
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Status {
+    /// Returns the process exit code to use for this outcome.
+    pub fn exit_code(self) -> ExitCode {
+        match self {
+            Self::Ok => ExitCode::SUCCESS,
+            Self::Degraded => ExitCode::from(EXIT_DEGRADED),
+            Self::Failed => ExitCode::from(EXIT_FAILED),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status() {
+        let mut summary = Summary {
+            meters_attempted: 2,
+            meters_succeeded: 2,
+            points_written: 96,
+            failures: vec![],
+        };
+        assert_eq!(summary.status(), Status::Ok);
+
+        summary.meters_succeeded = 1;
+        summary.fail("A-1234ABCD", "gas meter 2222 serial GAS1", "404 Not Found");
+        assert_eq!(summary.status(), Status::Degraded);
+
+        summary.meters_succeeded = 0;
+        assert_eq!(summary.status(), Status::Failed);
+    }
+
+    #[test]
+    fn display() {
+        let mut summary = Summary {
+            meters_attempted: 2,
+            meters_succeeded: 1,
+            points_written: 48,
+            failures: vec![],
+        };
+        summary.fail("A-1234ABCD", "gas meter 2222 serial GAS1", "404 Not Found");
+        assert_eq!(
+            summary.to_string(),
+            "Meters attempted: 2\n\
+             Meters succeeded: 1\n\
+             Meters failed: 1\n\
+             Points written: 48\n\
+             Failures:\n  \
+             A-1234ABCD gas meter 2222 serial GAS1: 404 Not Found\n"
+        );
+    }
+}
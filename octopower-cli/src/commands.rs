@@ -0,0 +1,629 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Implementations of the subcommands.
+
+use crate::{
+    CliError, ExportArgs, MeterFilter, Period,
+    credentials::{Credentials, Secret},
+    export::{ExportRow, ExportWriter, FileFormat},
+    output::{Format, Record, write_records},
+};
+use chrono::{DateTime, Days, TimeDelta, Utc};
+use log::info;
+use octopower::{
+    AuthToken, ConsumptionOptions, Grouping, MeterType, Order, authenticate,
+    authenticate_with_api_key, get_account, get_consumption_with_options, get_tariff_rates,
+    results::{
+        account::{Account, Agreement, agreement_at},
+        consumption::Consumption,
+        rates::{Rate, rate_at},
+    },
+    tariff::{RateType, TariffCode},
+};
+use serde::Serialize;
+use std::{collections::HashMap, io::stdout, path::Path};
+
+/// The number of consumption records to fetch per request.
+const PAGE_SIZE: usize = 5000;
+
+/// Prompts for any missing secret, checks it and saves it along with the account ID.
+pub async fn login(
+    config_dir: &Path,
+    account_id: String,
+    email: Option<String>,
+    api_key: Option<String>,
+) -> Result<(), CliError> {
+    let (token, secret) = if let Some(email) = email {
+        let password = rpassword::prompt_password("Password: ")?;
+        let token = authenticate(&email, &password)
+            .await
+            .map_err(CliError::Authentication)?;
+        let refresh_token = token
+            .refresh_token()
+            .ok_or_else(|| CliError::Other("No refresh token returned".to_owned()))?
+            .to_owned();
+        (token, Secret::RefreshToken(refresh_token))
+    } else {
+        let api_key = match api_key {
+            Some(api_key) => api_key,
+            None => rpassword::prompt_password("API key: ")?,
+        };
+        let token = authenticate_with_api_key(&api_key)
+            .await
+            .map_err(CliError::Authentication)?;
+        (token, Secret::ApiKey(api_key))
+    };
+    // Make sure the account is accessible before saving it as the default.
+    get_account(&token, &account_id).await?;
+    Credentials { account_id, secret }.save(config_dir)?;
+    eprintln!("Saved credentials to {}", config_dir.display());
+    Ok(())
+}
+
+/// Prints unit rates or standing charges for the given tariff.
+pub async fn rates(
+    format: Format,
+    tariff: &TariffCode,
+    standing_charges: bool,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+) -> Result<(), CliError> {
+    let rate_type = if standing_charges {
+        RateType::StandingCharge
+    } else {
+        unit_rate_type(tariff)?
+    };
+    let rates = get_tariff_rates(tariff, rate_type, from, to).await?;
+    let records = rates.into_iter().map(RateRecord::from).collect::<Vec<_>>();
+    write_records(stdout(), format, &records)?;
+    Ok(())
+}
+
+/// State for commands which need to be logged in.
+pub struct Session {
+    pub token: AuthToken,
+    pub account_id: String,
+    pub format: Format,
+}
+
+impl Session {
+    /// Prints the properties of the account.
+    pub async fn account(&self) -> Result<(), CliError> {
+        let account = get_account(&self.token, &self.account_id).await?;
+        let records = account
+            .properties
+            .iter()
+            .map(|property| PropertyRecord {
+                account: account.number.clone(),
+                property_id: property.id,
+                address: [
+                    &property.address_line_1,
+                    &property.address_line_2,
+                    &property.address_line_3,
+                    &property.town,
+                    &property.county,
+                ]
+                .into_iter()
+                .filter(|line| !line.is_empty())
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", "),
+                postcode: property.postcode.clone(),
+                moved_in_at: property.moved_in_at.to_utc(),
+                moved_out_at: property.moved_out_at.map(|time| time.to_utc()),
+            })
+            .collect::<Vec<_>>();
+        write_records(stdout(), self.format, &records)?;
+        Ok(())
+    }
+
+    /// Prints all meters of the account.
+    pub async fn meters(&self) -> Result<(), CliError> {
+        let account = get_account(&self.token, &self.account_id).await?;
+        let now = Utc::now();
+        let records = meters(&account)
+            .into_iter()
+            .map(|meter| MeterRecord {
+                property_id: meter.property_id,
+                fuel: meter.meter_type.to_string(),
+                mpxn: meter.mpxn.to_owned(),
+                serial: meter.serial.to_owned(),
+                direction: direction(meter.is_export).to_owned(),
+                tariff_code: agreement_at(meter.agreements, now)
+                    .map(|agreement| agreement.tariff_code.clone()),
+            })
+            .collect::<Vec<_>>();
+        write_records(stdout(), self.format, &records)?;
+        Ok(())
+    }
+
+    /// Prints consumption records for the selected meters.
+    pub async fn consumption(
+        &self,
+        period: &Period,
+        grouping: Option<Grouping>,
+        filter: &MeterFilter,
+    ) -> Result<(), CliError> {
+        let account = get_account(&self.token, &self.account_id).await?;
+        let mut records = Vec::new();
+        for meter in filtered_meters(&account, filter)? {
+            records.extend(
+                self.fetch_consumption(&meter, period, grouping)
+                    .await?
+                    .into_iter()
+                    .map(|consumption| ConsumptionRecord {
+                        fuel: meter.meter_type.to_string(),
+                        mpxn: meter.mpxn.to_owned(),
+                        serial: meter.serial.to_owned(),
+                        interval_start: consumption.interval_start,
+                        interval_end: consumption.interval_end,
+                        consumption: consumption.consumption,
+                    }),
+            );
+        }
+        write_records(stdout(), self.format, &records)?;
+        Ok(())
+    }
+
+    /// Prints the cost of consumption over the period for the selected meters.
+    pub async fn cost(&self, period: &Period, filter: &MeterFilter) -> Result<(), CliError> {
+        let account = get_account(&self.token, &self.account_id).await?;
+        let period_to = period.to.unwrap_or_else(Utc::now);
+        let mut rates_cache = RatesCache::default();
+        let mut records = Vec::new();
+        for meter in filtered_meters(&account, filter)? {
+            let readings = self.fetch_consumption(&meter, period, None).await?;
+
+            let mut consumption_kwh = 0.0;
+            let mut energy_cost = 0.0;
+            for reading in &readings {
+                let Some(agreement) = agreement_at(meter.agreements, reading.interval_start) else {
+                    return Err(no_agreement(&meter, reading.interval_start));
+                };
+                let tariff = parse_tariff(agreement)?;
+                let unit_rates = rates_cache
+                    .get(&tariff, unit_rate_type(&tariff)?, period.from, period_to)
+                    .await?;
+                let unit_rate = rate_at(unit_rates, reading.interval_start)
+                    .ok_or_else(|| no_rate(&tariff, reading.interval_start))?;
+                consumption_kwh += f64::from(reading.consumption);
+                energy_cost += f64::from(reading.consumption) * unit_rate.value_inc_vat;
+            }
+
+            let mut standing_charge = 0.0;
+            let mut day = period.from;
+            while day < period_to {
+                if let Some(agreement) = agreement_at(meter.agreements, day) {
+                    let tariff = parse_tariff(agreement)?;
+                    let standing_charges = rates_cache
+                        .get(&tariff, RateType::StandingCharge, period.from, period_to)
+                        .await?;
+                    let rate =
+                        rate_at(standing_charges, day).ok_or_else(|| no_rate(&tariff, day))?;
+                    standing_charge += rate.value_inc_vat;
+                }
+                day = day + Days::new(1);
+            }
+
+            records.push(CostRecord {
+                fuel: meter.meter_type.to_string(),
+                mpxn: meter.mpxn.to_owned(),
+                serial: meter.serial.to_owned(),
+                consumption_kwh,
+                energy_cost_pence: energy_cost,
+                standing_charge_pence: standing_charge,
+                total_cost_pence: energy_cost + standing_charge,
+            });
+        }
+        write_records(stdout(), self.format, &records)?;
+        Ok(())
+    }
+
+    /// Exports consumption records for the selected meters to a file.
+    pub async fn export(&self, args: &ExportArgs) -> Result<(), CliError> {
+        let format = args
+            .file_format
+            .or_else(|| FileFormat::from_path(&args.output))
+            .ok_or_else(|| {
+                CliError::Config(format!(
+                    "Can't guess file format for {}, use --file-format",
+                    args.output.display()
+                ))
+            })?;
+        let account = get_account(&self.token, &self.account_id).await?;
+        let meters = filtered_meters(&account, &args.meter)?;
+        let (mut writer, latest) = ExportWriter::open(&args.output, format, args.append)?;
+
+        for meter in meters {
+            let latest = latest.get(&(meter.mpxn.to_owned(), meter.serial.to_owned()));
+            let options = ConsumptionOptions {
+                // Start just after the latest record already exported, if any.
+                period_from: args
+                    .from
+                    .max(latest.map(|latest| *latest + TimeDelta::seconds(1))),
+                period_to: args.to,
+                grouping: args.group_by.map(Into::into),
+                order: Order::OldestFirst,
+            };
+            let mut count = 0;
+            for page in 0.. {
+                let readings = get_consumption_with_options(
+                    &self.token,
+                    meter.meter_type,
+                    meter.mpxn,
+                    meter.serial,
+                    page,
+                    PAGE_SIZE,
+                    &options,
+                )
+                .await?;
+                let rows = readings
+                    .results
+                    .iter()
+                    .filter(|consumption| {
+                        latest.is_none_or(|latest| consumption.interval_start > *latest)
+                    })
+                    .map(|consumption| {
+                        ExportRow::new(
+                            meter.meter_type,
+                            meter.is_export,
+                            meter.mpxn,
+                            meter.serial,
+                            args.gas_unit,
+                            consumption,
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                writer.write(&rows)?;
+                count += rows.len();
+                if readings.next.is_none() {
+                    break;
+                }
+            }
+            info!(
+                "Exported {count} records for {} meter {} {}",
+                meter.meter_type, meter.mpxn, meter.serial
+            );
+        }
+
+        writer.finish()
+    }
+
+    /// Fetches all consumption records for the given meter within the period, oldest first.
+    async fn fetch_consumption(
+        &self,
+        meter: &MeterInfo<'_>,
+        period: &Period,
+        grouping: Option<Grouping>,
+    ) -> Result<Vec<Consumption>, CliError> {
+        let options = ConsumptionOptions {
+            period_from: Some(period.from),
+            period_to: period.to,
+            grouping,
+            order: Order::OldestFirst,
+        };
+        let mut results = Vec::new();
+        for page in 0.. {
+            let readings = get_consumption_with_options(
+                &self.token,
+                meter.meter_type,
+                meter.mpxn,
+                meter.serial,
+                page,
+                PAGE_SIZE,
+                &options,
+            )
+            .await?;
+            results.extend(readings.results);
+            if readings.next.is_none() {
+                break;
+            }
+        }
+        Ok(results)
+    }
+}
+
+/// Unit rates and standing charges fetched so far, to avoid fetching the same ones repeatedly.
+#[derive(Default)]
+struct RatesCache {
+    rates: HashMap<(TariffCode, RateType), Vec<Rate>>,
+}
+
+impl RatesCache {
+    async fn get(
+        &mut self,
+        tariff: &TariffCode,
+        rate_type: RateType,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<&[Rate], CliError> {
+        let key = (tariff.clone(), rate_type);
+        if !self.rates.contains_key(&key) {
+            let rates = get_tariff_rates(tariff, rate_type, Some(from), Some(to))
+                .await?
+                .into_iter()
+                // Prices may be listed separately for those not paying by direct debit, but most
+                // customers do.
+                .filter(|rate| rate.payment_method.as_deref() != Some("NON_DIRECT_DEBIT"))
+                .collect();
+            self.rates.insert(key.clone(), rates);
+        }
+        Ok(&self.rates[&key])
+    }
+}
+
+/// A single electricity or gas meter along with the relevant information about its meter point.
+struct MeterInfo<'a> {
+    property_id: u32,
+    meter_type: MeterType,
+    mpxn: &'a str,
+    serial: &'a str,
+    is_export: bool,
+    agreements: &'a [Agreement],
+}
+
+/// Returns all meters of all properties of the account.
+fn meters(account: &Account) -> Vec<MeterInfo<'_>> {
+    let mut meters = Vec::new();
+    for property in &account.properties {
+        for meter_point in &property.electricity_meter_points {
+            for meter in &meter_point.meters {
+                meters.push(MeterInfo {
+                    property_id: property.id,
+                    meter_type: MeterType::Electricity,
+                    mpxn: &meter_point.mpan,
+                    serial: &meter.serial_number,
+                    is_export: meter_point.is_export,
+                    agreements: &meter_point.agreements,
+                });
+            }
+        }
+        for meter_point in &property.gas_meter_points {
+            for meter in &meter_point.meters {
+                meters.push(MeterInfo {
+                    property_id: property.id,
+                    meter_type: MeterType::Gas,
+                    mpxn: &meter_point.mprn,
+                    serial: &meter.serial_number,
+                    is_export: false,
+                    agreements: &meter_point.agreements,
+                });
+            }
+        }
+    }
+    meters
+}
+
+/// Returns the meters of the account which match the filter, or an error if there are none.
+fn filtered_meters<'a>(
+    account: &'a Account,
+    filter: &MeterFilter,
+) -> Result<Vec<MeterInfo<'a>>, CliError> {
+    let meters = meters(account)
+        .into_iter()
+        .filter(|meter| {
+            filter.mpxn.as_deref().is_none_or(|mpxn| mpxn == meter.mpxn)
+                && filter
+                    .serial
+                    .as_deref()
+                    .is_none_or(|serial| serial == meter.serial)
+        })
+        .collect::<Vec<_>>();
+    if meters.is_empty() {
+        Err(CliError::Other("No matching meters found".to_owned()))
+    } else {
+        Ok(meters)
+    }
+}
+
+fn parse_tariff(agreement: &Agreement) -> Result<TariffCode, CliError> {
+    agreement
+        .tariff_code
+        .parse()
+        .map_err(|e| CliError::Other(format!("{e}")))
+}
+
+/// Returns the type of unit rate to use for the given tariff, or an error if it has more than one.
+fn unit_rate_type(tariff: &TariffCode) -> Result<RateType, CliError> {
+    if tariff.registers == 1 {
+        Ok(RateType::StandardUnit)
+    } else {
+        Err(CliError::Other(format!(
+            "Tariff {tariff} has {} registers, only single register tariffs are supported",
+            tariff.registers
+        )))
+    }
+}
+
+fn no_agreement(meter: &MeterInfo, time: DateTime<Utc>) -> CliError {
+    CliError::Other(format!(
+        "No agreement for {} meter {} at {time}",
+        meter.meter_type, meter.mpxn
+    ))
+}
+
+fn no_rate(tariff: &TariffCode, time: DateTime<Utc>) -> CliError {
+    CliError::Other(format!("No rate for tariff {tariff} at {time}"))
+}
+
+fn direction(is_export: bool) -> &'static str {
+    if is_export { "export" } else { "import" }
+}
+
+#[derive(Serialize)]
+struct PropertyRecord {
+    account: String,
+    property_id: u32,
+    address: String,
+    postcode: String,
+    moved_in_at: DateTime<Utc>,
+    moved_out_at: Option<DateTime<Utc>>,
+}
+
+impl Record for PropertyRecord {
+    const HEADERS: &'static [&'static str] = &[
+        "account",
+        "property_id",
+        "address",
+        "postcode",
+        "moved_in_at",
+        "moved_out_at",
+    ];
+
+    fn values(&self) -> Vec<String> {
+        vec![
+            self.account.clone(),
+            self.property_id.to_string(),
+            self.address.clone(),
+            self.postcode.clone(),
+            self.moved_in_at.to_rfc3339(),
+            self.moved_out_at
+                .map(|time| time.to_rfc3339())
+                .unwrap_or_default(),
+        ]
+    }
+}
+
+#[derive(Serialize)]
+struct MeterRecord {
+    property_id: u32,
+    fuel: String,
+    mpxn: String,
+    serial: String,
+    direction: String,
+    tariff_code: Option<String>,
+}
+
+impl Record for MeterRecord {
+    const HEADERS: &'static [&'static str] = &[
+        "property_id",
+        "fuel",
+        "mpxn",
+        "serial",
+        "direction",
+        "tariff_code",
+    ];
+
+    fn values(&self) -> Vec<String> {
+        vec![
+            self.property_id.to_string(),
+            self.fuel.clone(),
+            self.mpxn.clone(),
+            self.serial.clone(),
+            self.direction.clone(),
+            self.tariff_code.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+#[derive(Serialize)]
+struct ConsumptionRecord {
+    fuel: String,
+    mpxn: String,
+    serial: String,
+    interval_start: DateTime<Utc>,
+    interval_end: DateTime<Utc>,
+    consumption: f32,
+}
+
+impl Record for ConsumptionRecord {
+    const HEADERS: &'static [&'static str] = &[
+        "fuel",
+        "mpxn",
+        "serial",
+        "interval_start",
+        "interval_end",
+        "consumption",
+    ];
+
+    fn values(&self) -> Vec<String> {
+        vec![
+            self.fuel.clone(),
+            self.mpxn.clone(),
+            self.serial.clone(),
+            self.interval_start.to_rfc3339(),
+            self.interval_end.to_rfc3339(),
+            self.consumption.to_string(),
+        ]
+    }
+}
+
+#[derive(Serialize)]
+struct RateRecord {
+    valid_from: DateTime<Utc>,
+    valid_to: Option<DateTime<Utc>>,
+    value_exc_vat: f64,
+    value_inc_vat: f64,
+    payment_method: Option<String>,
+}
+
+impl From<Rate> for RateRecord {
+    fn from(rate: Rate) -> Self {
+        Self {
+            valid_from: rate.valid_from,
+            valid_to: rate.valid_to,
+            value_exc_vat: rate.value_exc_vat,
+            value_inc_vat: rate.value_inc_vat,
+            payment_method: rate.payment_method,
+        }
+    }
+}
+
+impl Record for RateRecord {
+    const HEADERS: &'static [&'static str] = &[
+        "valid_from",
+        "valid_to",
+        "value_exc_vat",
+        "value_inc_vat",
+        "payment_method",
+    ];
+
+    fn values(&self) -> Vec<String> {
+        vec![
+            self.valid_from.to_rfc3339(),
+            self.valid_to
+                .map(|time| time.to_rfc3339())
+                .unwrap_or_default(),
+            self.value_exc_vat.to_string(),
+            self.value_inc_vat.to_string(),
+            self.payment_method.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+#[derive(Serialize)]
+struct CostRecord {
+    fuel: String,
+    mpxn: String,
+    serial: String,
+    consumption_kwh: f64,
+    energy_cost_pence: f64,
+    standing_charge_pence: f64,
+    total_cost_pence: f64,
+}
+
+impl Record for CostRecord {
+    const HEADERS: &'static [&'static str] = &[
+        "fuel",
+        "mpxn",
+        "serial",
+        "consumption_kwh",
+        "energy_cost_pence",
+        "standing_charge_pence",
+        "total_cost_pence",
+    ];
+
+    fn values(&self) -> Vec<String> {
+        vec![
+            self.fuel.clone(),
+            self.mpxn.clone(),
+            self.serial.clone(),
+            format!("{:.3}", self.consumption_kwh),
+            format!("{:.2}", self.energy_cost_pence),
+            format!("{:.2}", self.standing_charge_pence),
+            format!("{:.2}", self.total_cost_pence),
+        ]
+    }
+}
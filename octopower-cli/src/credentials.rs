@@ -0,0 +1,142 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Storage of login credentials between invocations.
+
+use crate::CliError;
+use octopower::{AuthToken, authenticate_with_api_key, authenticate_with_refresh_token};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+const CREDENTIALS_FILENAME: &str = "credentials.toml";
+
+/// Credentials saved by the `login` subcommand.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Credentials {
+    /// The default account number to use.
+    pub account_id: String,
+    #[serde(flatten)]
+    pub secret: Secret,
+}
+
+/// The secret used to obtain an authentication token.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Secret {
+    /// A long-lived API key.
+    ApiKey(String),
+    /// A refresh token obtained by logging in with an email address and password. This expires
+    /// after a few days.
+    RefreshToken(String),
+}
+
+impl Credentials {
+    /// Returns the default directory in which to store credentials.
+    pub fn default_dir() -> Option<PathBuf> {
+        Some(dirs::config_dir()?.join("octopower"))
+    }
+
+    /// Loads credentials from the given directory.
+    pub fn load(dir: &Path) -> Result<Self, CliError> {
+        let path = dir.join(CREDENTIALS_FILENAME);
+        match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|e| CliError::Config(format!("Invalid {}: {e}", path.display()))),
+            Err(e) if e.kind() == ErrorKind::NotFound => Err(CliError::NotLoggedIn),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Saves the credentials to the given directory, creating it if necessary.
+    ///
+    /// On Unix the file is only readable and writable by the current user.
+    pub fn save(&self, dir: &Path) -> Result<(), CliError> {
+        fs::create_dir_all(dir)?;
+        let contents =
+            toml::to_string(self).map_err(|e| CliError::Config(format!("Serialising: {e}")))?;
+        let path = dir.join(CREDENTIALS_FILENAME);
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            options.mode(0o600);
+            // The mode only applies to newly created files, so also fix up any existing file.
+            if path.exists() {
+                fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+            }
+        }
+        options.open(&path)?.write_all(contents.as_bytes())?;
+        Ok(())
+    }
+
+    /// Uses the saved secret to obtain a new authentication token.
+    ///
+    /// Each use of a refresh token gives a new one, which is saved back to the given directory so
+    /// that the login keeps working after the original refresh token expires.
+    pub async fn authenticate(&mut self, dir: &Path) -> Result<AuthToken, CliError> {
+        match &self.secret {
+            Secret::ApiKey(api_key) => authenticate_with_api_key(api_key)
+                .await
+                .map_err(CliError::Authentication),
+            Secret::RefreshToken(refresh_token) => {
+                let token = authenticate_with_refresh_token(refresh_token)
+                    .await
+                    .map_err(CliError::Authentication)?;
+                if self.update_refresh_token(&token) {
+                    self.save(dir)?;
+                }
+                Ok(token)
+            }
+        }
+    }
+
+    /// Replaces a saved refresh token with the new one from the given authentication token, if
+    /// any, returning whether it changed.
+    fn update_refresh_token(&mut self, token: &AuthToken) -> bool {
+        match (&mut self.secret, token.refresh_token()) {
+            (Secret::RefreshToken(saved), Some(new)) if saved != new => {
+                *saved = new.to_owned();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+
+    #[test]
+    fn save_and_load() {
+        let dir = temp_dir().join(format!("octopower-cli-test-{}", std::process::id()));
+        assert!(matches!(
+            Credentials::load(&dir),
+            Err(CliError::NotLoggedIn)
+        ));
+
+        let credentials = Credentials {
+            account_id: "A-1234ABCD".to_owned(),
+            secret: Secret::ApiKey("sk_live_abc".to_owned()),
+        };
+        credentials.save(&dir).unwrap();
+        assert_eq!(Credentials::load(&dir).unwrap(), credentials);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = fs::metadata(dir.join(CREDENTIALS_FILENAME)).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
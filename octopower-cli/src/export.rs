@@ -0,0 +1,426 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Export of consumption records to CSV or Parquet files.
+
+use crate::CliError;
+use arrow_array::{
+    Array, ArrayRef, Float64Array, RecordBatch, StringArray, TimestampSecondArray, cast::AsArray,
+    types::TimestampSecondType,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use chrono::{DateTime, FixedOffset, Utc};
+use chrono_tz::Europe::London;
+use clap::ValueEnum;
+use octopower::{MeterType, results::consumption::Consumption};
+use parquet::{
+    arrow::{ArrowWriter, arrow_reader::ParquetRecordBatchReaderBuilder},
+    file::properties::WriterProperties,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// The maximum number of rows to buffer in memory before writing a Parquet row group.
+const MAX_ROW_GROUP_SIZE: usize = 65536;
+
+/// The file format to which to export.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum FileFormat {
+    Csv,
+    Parquet,
+}
+
+impl FileFormat {
+    /// Guesses the file format from the extension of the given path.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "csv" => Some(Self::Csv),
+            "parquet" => Some(Self::Parquet),
+            _ => None,
+        }
+    }
+}
+
+/// The unit in which gas consumption is reported.
+///
+/// Electricity consumption is always in kWh, as is gas consumption from SMETS1 meters, but SMETS2
+/// gas meters report in cubic metres.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum GasUnit {
+    Kwh,
+    #[default]
+    M3,
+}
+
+/// A single consumption record along with information about the meter it is from.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ExportRow {
+    pub fuel: String,
+    pub direction: String,
+    pub mpxn: String,
+    pub serial: String,
+    pub interval_start_utc: DateTime<Utc>,
+    pub interval_end_utc: DateTime<Utc>,
+    /// The start of the interval in UK local time.
+    pub interval_start_local: DateTime<FixedOffset>,
+    /// The end of the interval in UK local time.
+    pub interval_end_local: DateTime<FixedOffset>,
+    pub consumption: f64,
+    pub unit: String,
+}
+
+impl ExportRow {
+    pub fn new(
+        meter_type: MeterType,
+        is_export: bool,
+        mpxn: &str,
+        serial: &str,
+        gas_unit: GasUnit,
+        consumption: &Consumption,
+    ) -> Self {
+        let unit = match (meter_type, gas_unit) {
+            (MeterType::Gas, GasUnit::M3) => "m3",
+            _ => "kWh",
+        };
+        Self {
+            fuel: meter_type.to_string(),
+            direction: if is_export { "export" } else { "import" }.to_owned(),
+            mpxn: mpxn.to_owned(),
+            serial: serial.to_owned(),
+            interval_start_utc: consumption.interval_start,
+            interval_end_utc: consumption.interval_end,
+            interval_start_local: consumption
+                .interval_start
+                .with_timezone(&London)
+                .fixed_offset(),
+            interval_end_local: consumption
+                .interval_end
+                .with_timezone(&London)
+                .fixed_offset(),
+            consumption: consumption.consumption.into(),
+            unit: unit.to_owned(),
+        }
+    }
+}
+
+/// The start of the latest interval already exported for each (MPXN, serial) pair.
+pub type LatestIntervals = HashMap<(String, String), DateTime<Utc>>;
+
+/// Writes rows to a CSV or Parquet file incrementally.
+pub enum ExportWriter {
+    Csv(csv::Writer<File>),
+    Parquet {
+        writer: ArrowWriter<File>,
+        /// The temporary file being written to, which will replace the destination when finished.
+        temp_path: PathBuf,
+        path: PathBuf,
+    },
+}
+
+impl ExportWriter {
+    /// Opens the given file for export.
+    ///
+    /// If `append` is true and the file already exists then new rows are added after the existing
+    /// ones, and the latest interval already present for each meter is returned so that rows
+    /// aren't duplicated. Otherwise any existing file is replaced.
+    pub fn open(
+        path: &Path,
+        format: FileFormat,
+        append: bool,
+    ) -> Result<(Self, LatestIntervals), CliError> {
+        let append = append && path.exists();
+        match format {
+            FileFormat::Csv => {
+                let latest = if append {
+                    latest_csv_intervals(path)?
+                } else {
+                    LatestIntervals::new()
+                };
+                let file = OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .append(append)
+                    .truncate(!append)
+                    .open(path)?;
+                let has_headers = !append || file.metadata()?.len() == 0;
+                let writer = csv::WriterBuilder::new()
+                    .has_headers(has_headers)
+                    .from_writer(file);
+                Ok((Self::Csv(writer), latest))
+            }
+            FileFormat::Parquet => {
+                let mut temp_path = path.as_os_str().to_owned();
+                temp_path.push(".tmp");
+                let temp_path = PathBuf::from(temp_path);
+                let properties = WriterProperties::builder()
+                    .set_max_row_group_size(MAX_ROW_GROUP_SIZE)
+                    .build();
+                let mut writer =
+                    ArrowWriter::try_new(File::create(&temp_path)?, schema(), Some(properties))
+                        .map_err(parquet_error)?;
+                let mut latest = LatestIntervals::new();
+                if append {
+                    // Parquet files can't be appended to in place, so copy the existing rows to a
+                    // new file one batch at a time.
+                    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)
+                        .map_err(parquet_error)?
+                        .build()
+                        .map_err(parquet_error)?;
+                    for batch in reader {
+                        let batch = batch.map_err(|e| CliError::Other(e.to_string()))?;
+                        update_latest_from_batch(&mut latest, &batch)?;
+                        writer.write(&batch).map_err(parquet_error)?;
+                    }
+                }
+                Ok((
+                    Self::Parquet {
+                        writer,
+                        temp_path,
+                        path: path.to_owned(),
+                    },
+                    latest,
+                ))
+            }
+        }
+    }
+
+    /// Writes the given rows to the file.
+    pub fn write(&mut self, rows: &[ExportRow]) -> Result<(), CliError> {
+        match self {
+            Self::Csv(writer) => {
+                for row in rows {
+                    writer
+                        .serialize(row)
+                        .map_err(|e| CliError::Other(e.to_string()))?;
+                }
+                writer.flush()?;
+            }
+            Self::Parquet { writer, .. } => {
+                writer.write(&to_batch(rows)?).map_err(parquet_error)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Finishes writing the file.
+    pub fn finish(self) -> Result<(), CliError> {
+        match self {
+            Self::Csv(mut writer) => writer.flush()?,
+            Self::Parquet {
+                writer,
+                temp_path,
+                path,
+            } => {
+                writer.close().map_err(parquet_error)?;
+                fs::rename(temp_path, path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn latest_csv_intervals(path: &Path) -> Result<LatestIntervals, CliError> {
+    let mut latest = LatestIntervals::new();
+    for row in csv::Reader::from_path(path)
+        .map_err(|e| CliError::Other(e.to_string()))?
+        .into_deserialize::<ExportRow>()
+    {
+        let row = row.map_err(|e| CliError::Other(format!("Reading {}: {e}", path.display())))?;
+        update_latest(&mut latest, row.mpxn, row.serial, row.interval_start_utc);
+    }
+    Ok(latest)
+}
+
+fn update_latest(
+    latest: &mut LatestIntervals,
+    mpxn: String,
+    serial: String,
+    interval_start: DateTime<Utc>,
+) {
+    latest
+        .entry((mpxn, serial))
+        .and_modify(|latest| *latest = interval_start.max(*latest))
+        .or_insert(interval_start);
+}
+
+fn schema() -> SchemaRef {
+    let timestamp = DataType::Timestamp(TimeUnit::Second, Some("UTC".into()));
+    Arc::new(Schema::new(vec![
+        Field::new("fuel", DataType::Utf8, false),
+        Field::new("direction", DataType::Utf8, false),
+        Field::new("mpxn", DataType::Utf8, false),
+        Field::new("serial", DataType::Utf8, false),
+        Field::new("interval_start_utc", timestamp.clone(), false),
+        Field::new("interval_end_utc", timestamp, false),
+        Field::new("interval_start_local", DataType::Utf8, false),
+        Field::new("interval_end_local", DataType::Utf8, false),
+        Field::new("consumption", DataType::Float64, false),
+        Field::new("unit", DataType::Utf8, false),
+    ]))
+}
+
+fn to_batch(rows: &[ExportRow]) -> Result<RecordBatch, CliError> {
+    fn strings(rows: &[ExportRow], f: impl Fn(&ExportRow) -> String) -> ArrayRef {
+        Arc::new(rows.iter().map(f).map(Some).collect::<StringArray>())
+    }
+    fn timestamps(rows: &[ExportRow], f: impl Fn(&ExportRow) -> DateTime<Utc>) -> ArrayRef {
+        let timestamps = rows
+            .iter()
+            .map(|row| f(row).timestamp())
+            .collect::<Vec<_>>();
+        Arc::new(TimestampSecondArray::from(timestamps).with_timezone("UTC"))
+    }
+
+    RecordBatch::try_new(
+        schema(),
+        vec![
+            strings(rows, |row| row.fuel.clone()),
+            strings(rows, |row| row.direction.clone()),
+            strings(rows, |row| row.mpxn.clone()),
+            strings(rows, |row| row.serial.clone()),
+            timestamps(rows, |row| row.interval_start_utc),
+            timestamps(rows, |row| row.interval_end_utc),
+            strings(rows, |row| row.interval_start_local.to_rfc3339()),
+            strings(rows, |row| row.interval_end_local.to_rfc3339()),
+            Arc::new(
+                rows.iter()
+                    .map(|row| row.consumption)
+                    .collect::<Float64Array>(),
+            ),
+            strings(rows, |row| row.unit.clone()),
+        ],
+    )
+    .map_err(|e| CliError::Other(e.to_string()))
+}
+
+fn update_latest_from_batch(
+    latest: &mut LatestIntervals,
+    batch: &RecordBatch,
+) -> Result<(), CliError> {
+    let column = |name| {
+        batch
+            .column_by_name(name)
+            .ok_or_else(|| CliError::Other(format!("Existing file is missing {name} column")))
+    };
+    let (Some(mpxns), Some(serials), Some(interval_starts)) = (
+        column("mpxn")?.as_string_opt::<i32>(),
+        column("serial")?.as_string_opt::<i32>(),
+        column("interval_start_utc")?.as_primitive_opt::<TimestampSecondType>(),
+    ) else {
+        return Err(CliError::Other(
+            "Existing file has unexpected column types".to_owned(),
+        ));
+    };
+    for i in 0..batch.num_rows() {
+        if mpxns.is_null(i) || serials.is_null(i) || interval_starts.is_null(i) {
+            continue;
+        }
+        let Some(interval_start) = DateTime::from_timestamp(interval_starts.value(i), 0) else {
+            continue;
+        };
+        update_latest(
+            latest,
+            mpxns.value(i).to_owned(),
+            serials.value(i).to_owned(),
+            interval_start,
+        );
+    }
+    Ok(())
+}
+
+fn parquet_error(e: parquet::errors::ParquetError) -> CliError {
+    CliError::Other(format!("Parquet error: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+
+    fn row(serial: &str, interval_start: &str) -> ExportRow {
+        let interval_start: DateTime<Utc> = interval_start.parse().unwrap();
+        ExportRow::new(
+            MeterType::Electricity,
+            false,
+            "1234",
+            serial,
+            GasUnit::default(),
+            &Consumption {
+                consumption: 0.5,
+                interval_start,
+                interval_end: interval_start + chrono::Duration::minutes(30),
+            },
+        )
+    }
+
+    fn test_append(format: FileFormat, extension: &str) {
+        let path = temp_dir().join(format!(
+            "octopower-export-test-{}.{extension}",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+
+        let (mut writer, latest) = ExportWriter::open(&path, format, true).unwrap();
+        assert!(latest.is_empty());
+        writer
+            .write(&[
+                row("A", "2024-06-01T10:00:00Z"),
+                row("A", "2024-06-01T10:30:00Z"),
+                row("B", "2024-06-01T09:00:00Z"),
+            ])
+            .unwrap();
+        writer.finish().unwrap();
+
+        let (mut writer, latest) = ExportWriter::open(&path, format, true).unwrap();
+        assert_eq!(
+            latest,
+            LatestIntervals::from([
+                (
+                    ("1234".to_owned(), "A".to_owned()),
+                    "2024-06-01T10:30:00Z".parse().unwrap()
+                ),
+                (
+                    ("1234".to_owned(), "B".to_owned()),
+                    "2024-06-01T09:00:00Z".parse().unwrap()
+                ),
+            ])
+        );
+        writer.write(&[row("A", "2024-06-01T11:00:00Z")]).unwrap();
+        writer.finish().unwrap();
+
+        let (writer, latest) = ExportWriter::open(&path, format, true).unwrap();
+        assert_eq!(
+            latest[&("1234".to_owned(), "A".to_owned())],
+            "2024-06-01T11:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        writer.finish().unwrap();
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn append_csv() {
+        test_append(FileFormat::Csv, "csv");
+    }
+
+    #[test]
+    fn append_parquet() {
+        test_append(FileFormat::Parquet, "parquet");
+    }
+
+    #[test]
+    fn local_time() {
+        let row = row("A", "2024-06-01T10:00:00Z");
+        assert_eq!(
+            row.interval_start_local.to_rfc3339(),
+            "2024-06-01T11:00:00+01:00"
+        );
+        assert_eq!(row.unit, "kWh");
+    }
+}
@@ -0,0 +1,316 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+mod commands;
+mod credentials;
+mod export;
+mod output;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use credentials::Credentials;
+use export::{FileFormat, GasUnit};
+use octopower::{ApiError, Grouping, tariff::TariffCode};
+use output::Format;
+use std::{io, path::PathBuf, process::ExitCode};
+use thiserror::Error;
+
+/// Exit code for errors not covered by a more specific code.
+const EXIT_FAILURE: u8 = 1;
+// Exit code 2 is used by clap for invalid command-line arguments.
+/// Exit code when there are no saved credentials, or they were rejected by the API.
+const EXIT_AUTHENTICATION: u8 = 3;
+/// Exit code when an API request fails for some other reason.
+const EXIT_API: u8 = 4;
+
+/// A command-line tool for the Octopus Energy API.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    /// The directory in which to store credentials. Defaults to an `octopower` directory in the
+    /// user's config directory.
+    #[arg(long, global = true, env = "OCTOPOWER_CONFIG_DIR")]
+    config_dir: Option<PathBuf>,
+    /// The account number to use, rather than the one saved by `login`.
+    #[arg(long, global = true)]
+    account: Option<String>,
+    /// The format in which to print results.
+    #[arg(long, global = true, value_enum, default_value_t)]
+    format: Format,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Checks the given credentials and saves them for future commands.
+    ///
+    /// If `--email` is given then the password is prompted for and a refresh token is saved, which
+    /// expires after a few days. Otherwise an API key is prompted for, unless it is given by
+    /// `--api-key`.
+    Login {
+        /// The email address of the Octopus account.
+        #[arg(long, conflicts_with = "api_key")]
+        email: Option<String>,
+        /// The API key from the Octopus account's personal details page.
+        #[arg(long, env = "OCTOPOWER_API_KEY", hide_env_values = true)]
+        api_key: Option<String>,
+    },
+    /// Shows the properties of the account.
+    Account,
+    /// Lists all meters of the account.
+    Meters,
+    /// Shows consumption records for meters of the account.
+    Consumption {
+        #[command(flatten)]
+        period: Period,
+        /// The level of aggregation. If not given then half-hourly records are shown.
+        #[arg(long, value_enum)]
+        group_by: Option<GroupBy>,
+        #[command(flatten)]
+        meter: MeterFilter,
+    },
+    /// Shows unit rates or standing charges for a tariff.
+    Rates {
+        /// The tariff code, such as `E-1R-AGILE-FLEX-22-11-25-C`.
+        tariff: TariffCode,
+        /// Show standing charges rather than unit rates.
+        #[arg(long)]
+        standing_charges: bool,
+        /// Only show rates which apply at or after this date or time.
+        #[arg(long, value_parser = parse_time)]
+        from: Option<DateTime<Utc>>,
+        /// Only show rates which apply before this date or time.
+        #[arg(long, value_parser = parse_time)]
+        to: Option<DateTime<Utc>>,
+    },
+    /// Calculates the cost of consumption for meters of the account, including VAT.
+    ///
+    /// Gas consumption is assumed to be in kWh, which is not the case for SMETS2 gas meters.
+    Cost {
+        #[command(flatten)]
+        period: Period,
+        #[command(flatten)]
+        meter: MeterFilter,
+    },
+    /// Exports consumption records for meters of the account to a CSV or Parquet file.
+    ///
+    /// Records are fetched and written one page at a time, so large exports don't need to fit in
+    /// memory.
+    Export(ExportArgs),
+    /// Prints a shell completion script.
+    Completions {
+        /// The shell for which to generate completions.
+        shell: Shell,
+    },
+}
+
+/// A period of time for which to fetch records.
+#[derive(Clone, Debug, clap::Args)]
+struct Period {
+    /// The start of the period, as a date (in UTC) or an RFC 3339 timestamp.
+    #[arg(long, value_parser = parse_time)]
+    from: DateTime<Utc>,
+    /// The end of the period, as a date (in UTC) or an RFC 3339 timestamp. Defaults to now.
+    #[arg(long, value_parser = parse_time)]
+    to: Option<DateTime<Utc>>,
+}
+
+/// Arguments for the `export` subcommand.
+#[derive(Clone, Debug, clap::Args)]
+struct ExportArgs {
+    /// The file to which to write records.
+    output: PathBuf,
+    /// The format of the file. Defaults to guessing from the file extension.
+    #[arg(long, value_enum)]
+    file_format: Option<FileFormat>,
+    /// Add records to the end of an existing file, skipping any which are already present, rather
+    /// than replacing it.
+    #[arg(long)]
+    append: bool,
+    /// Only export records starting at or after this date or time. Defaults to all available
+    /// records.
+    #[arg(long, value_parser = parse_time)]
+    from: Option<DateTime<Utc>>,
+    /// Only export records ending at or before this date or time.
+    #[arg(long, value_parser = parse_time)]
+    to: Option<DateTime<Utc>>,
+    /// The level of aggregation. If not given then half-hourly records are exported.
+    #[arg(long, value_enum)]
+    group_by: Option<GroupBy>,
+    /// The unit in which gas meters report consumption.
+    #[arg(long, value_enum, default_value_t)]
+    gas_unit: GasUnit,
+    #[command(flatten)]
+    meter: MeterFilter,
+}
+
+/// Options to select particular meters.
+#[derive(Clone, Debug, clap::Args)]
+struct MeterFilter {
+    /// Only include meters with this MPAN or MPRN.
+    #[arg(long)]
+    mpxn: Option<String>,
+    /// Only include meters with this serial number.
+    #[arg(long)]
+    serial: Option<String>,
+}
+
+/// The level of aggregation of consumption records.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum GroupBy {
+    Hour,
+    Day,
+    Week,
+    Month,
+    Quarter,
+}
+
+impl From<GroupBy> for Grouping {
+    fn from(group_by: GroupBy) -> Self {
+        match group_by {
+            GroupBy::Hour => Self::Hour,
+            GroupBy::Day => Self::Day,
+            GroupBy::Week => Self::Week,
+            GroupBy::Month => Self::Month,
+            GroupBy::Quarter => Self::Quarter,
+        }
+    }
+}
+
+/// An error running a command.
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error("Not logged in, run `octopower login` first")]
+    NotLoggedIn,
+    #[error("Authentication failed: {0}")]
+    Authentication(ApiError),
+    #[error("{0}")]
+    Api(#[from] ApiError),
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("{0}")]
+    Config(String),
+    #[error("{0}")]
+    Other(String),
+}
+
+impl CliError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            Self::NotLoggedIn | Self::Authentication(_) => EXIT_AUTHENTICATION,
+            Self::Api(e) if is_unauthorized(e) => EXIT_AUTHENTICATION,
+            Self::Api(_) => EXIT_API,
+            Self::Io(_) | Self::Config(_) | Self::Other(_) => EXIT_FAILURE,
+        }
+    }
+}
+
+/// Returns whether the given error was caused by the API rejecting our credentials.
+fn is_unauthorized(error: &ApiError) -> bool {
+    matches!(error, ApiError::RestError { status, .. } if status.as_u16() == 401)
+}
+
+/// Parses a date or RFC 3339 timestamp. Dates are taken to be midnight UTC.
+fn parse_time(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        Ok(time.to_utc())
+    } else if let Ok(date) = s.parse::<NaiveDate>() {
+        Ok(date.and_time(Default::default()).and_utc())
+    } else {
+        Err(format!("Invalid date or timestamp \"{s}\""))
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    pretty_env_logger::init();
+
+    let cli = Cli::parse();
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), CliError> {
+    let config_dir = cli
+        .config_dir
+        .or_else(Credentials::default_dir)
+        .ok_or_else(|| CliError::Config("Can't find config directory".to_owned()))?;
+
+    match cli.command {
+        Command::Login { email, api_key } => {
+            let account_id = cli
+                .account
+                .ok_or_else(|| CliError::Config("`--account` is required to log in".to_owned()))?;
+            commands::login(&config_dir, account_id, email, api_key).await
+        }
+        Command::Rates {
+            tariff,
+            standing_charges,
+            from,
+            to,
+        } => commands::rates(cli.format, &tariff, standing_charges, from, to).await,
+        Command::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "octopower", &mut io::stdout());
+            Ok(())
+        }
+        command => {
+            let mut credentials = Credentials::load(&config_dir)?;
+            let account_id = cli.account.unwrap_or(credentials.account_id.clone());
+            let token = credentials.authenticate(&config_dir).await?;
+            let session = commands::Session {
+                token,
+                account_id,
+                format: cli.format,
+            };
+            match command {
+                Command::Account => session.account().await,
+                Command::Meters => session.meters().await,
+                Command::Consumption {
+                    period,
+                    group_by,
+                    meter,
+                } => {
+                    session
+                        .consumption(&period, group_by.map(Into::into), &meter)
+                        .await
+                }
+                Command::Cost { period, meter } => session.cost(&period, &meter).await,
+                Command::Export(args) => session.export(&args).await,
+                Command::Login { .. } | Command::Rates { .. } | Command::Completions { .. } => {
+                    unreachable!()
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_cli() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn parse_date() {
+        assert_eq!(
+            parse_time("2024-03-01"),
+            Ok("2024-03-01T00:00:00Z".parse().unwrap())
+        );
+        assert_eq!(
+            parse_time("2024-03-01T12:30:00+01:00"),
+            Ok("2024-03-01T11:30:00Z".parse().unwrap())
+        );
+        assert!(parse_time("yesterday").is_err());
+    }
+}
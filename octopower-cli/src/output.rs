@@ -0,0 +1,138 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Formatting of command output as CSV, JSON or a table.
+
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::{self, Write};
+
+/// The format in which to print records.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum Format {
+    /// Comma-separated values with a header row.
+    Csv,
+    /// A JSON array of objects.
+    Json,
+    /// A table aligned for reading in a terminal.
+    #[default]
+    Table,
+}
+
+/// A flat record which can be printed in any [`Format`].
+pub trait Record: Serialize {
+    /// The names of the columns, in the same order as the values returned by [`Record::values`].
+    const HEADERS: &'static [&'static str];
+
+    /// Returns the values of the columns formatted as strings.
+    fn values(&self) -> Vec<String>;
+}
+
+/// Writes the given records to `writer` in the given format.
+pub fn write_records<R: Record>(
+    mut writer: impl Write,
+    format: Format,
+    records: &[R],
+) -> io::Result<()> {
+    match format {
+        Format::Csv => {
+            let mut csv_writer = csv::Writer::from_writer(writer);
+            csv_writer.write_record(R::HEADERS)?;
+            for record in records {
+                csv_writer.write_record(record.values())?;
+            }
+            csv_writer.flush()
+        }
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut writer, records)?;
+            writeln!(writer)
+        }
+        Format::Table => {
+            let rows = records.iter().map(Record::values).collect::<Vec<_>>();
+            let widths = R::HEADERS
+                .iter()
+                .enumerate()
+                .map(|(i, header)| {
+                    rows.iter()
+                        .map(|row| row[i].len())
+                        .chain([header.len()])
+                        .max()
+                        .unwrap_or_default()
+                })
+                .collect::<Vec<_>>();
+            let headers = R::HEADERS
+                .iter()
+                .map(|header| header.to_string())
+                .collect::<Vec<_>>();
+            for row in [headers].iter().chain(&rows) {
+                let line = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|(value, width)| format!("{value:width$}"))
+                    .collect::<Vec<_>>()
+                    .join("  ");
+                writeln!(writer, "{}", line.trim_end())?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct TestRecord {
+        name: &'static str,
+        value: f64,
+    }
+
+    impl Record for TestRecord {
+        const HEADERS: &'static [&'static str] = &["name", "value"];
+
+        fn values(&self) -> Vec<String> {
+            vec![self.name.to_owned(), self.value.to_string()]
+        }
+    }
+
+    const RECORDS: &[TestRecord] = &[
+        TestRecord {
+            name: "a",
+            value: 1.5,
+        },
+        TestRecord {
+            name: "longer",
+            value: 42.0,
+        },
+    ];
+
+    fn format(format: Format) -> String {
+        let mut output = Vec::new();
+        write_records(&mut output, format, RECORDS).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn csv() {
+        assert_eq!(format(Format::Csv), "name,value\na,1.5\nlonger,42\n");
+    }
+
+    #[test]
+    fn json() {
+        let json: serde_json::Value = serde_json::from_str(&format(Format::Json)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([{"name": "a", "value": 1.5}, {"name": "longer", "value": 42.0}])
+        );
+    }
+
+    #[test]
+    fn table() {
+        assert_eq!(
+            format(Format::Table),
+            "name    value\na       1.5\nlonger  42\n"
+        );
+    }
+}
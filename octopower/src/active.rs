@@ -0,0 +1,209 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Working out which meter of a meter point was recording consumption at each time.
+//!
+//! A meter point may list several meters, for example if the meter or its communications hub has
+//! been replaced, and some of them may never return any consumption records. Importing records
+//! from all of them would count the same consumption more than once.
+
+use crate::results::account::{Agreement, Meter};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A period during which a particular meter was the one recording consumption for its meter point,
+/// as returned by [`get_active_meters`](crate::get_active_meters).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ActiveMeter {
+    /// The serial number of the meter.
+    pub serial_number: String,
+    /// The time from which the meter was active.
+    pub from: DateTime<Utc>,
+    /// The time until which the meter was active, or `None` if it still is.
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl ActiveMeter {
+    /// Returns whether the meter was active at the given time.
+    pub fn is_active_at(&self, time: DateTime<Utc>) -> bool {
+        self.from <= time && self.to.is_none_or(|to| time < to)
+    }
+}
+
+/// The period covered by the consumption records available from a meter.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DataRange {
+    /// The serial number of the meter.
+    pub serial_number: String,
+    /// The start of the earliest record.
+    pub first: DateTime<Utc>,
+    /// The end of the latest record.
+    pub last: DateTime<Utc>,
+}
+
+/// Returns the meters which might record consumption for a meter point.
+///
+/// If any of the meters have a settlement register then those without one are excluded, as they
+/// don't return any data.
+pub fn candidate_meters(meters: &[Meter]) -> Vec<&Meter> {
+    let has_settlement_register = |meter: &Meter| {
+        meter
+            .registers
+            .iter()
+            .any(|register| register.is_settlement_register)
+    };
+    if meters.iter().any(has_settlement_register) {
+        meters
+            .iter()
+            .filter(|meter| has_settlement_register(meter))
+            .collect()
+    } else {
+        meters.iter().collect()
+    }
+}
+
+/// Works out which meter was active at each time, given the range of records available from each
+/// meter of a meter point and the agreements of the meter point.
+///
+/// Each meter is taken to be active from its first record until the first record of the next meter
+/// to start returning data. If there are any agreements, periods are restricted to when there was
+/// an agreement. The result is sorted by time, and the periods don't overlap.
+pub fn active_meters(ranges: &[DataRange], agreements: &[Agreement]) -> Vec<ActiveMeter> {
+    let mut ranges = ranges.iter().collect::<Vec<_>>();
+    // If two meters start at the same time then prefer the one with more recent data.
+    ranges.sort_by_key(|range| (range.first, range.last));
+
+    let agreements_from = agreements
+        .iter()
+        .map(|agreement| agreement.valid_from.to_utc())
+        .min();
+    let agreements_to = if agreements
+        .iter()
+        .any(|agreement| agreement.valid_to.is_none())
+    {
+        None
+    } else {
+        agreements
+            .iter()
+            .filter_map(|agreement| agreement.valid_to)
+            .map(|valid_to| valid_to.to_utc())
+            .max()
+    };
+
+    ranges
+        .iter()
+        .enumerate()
+        .filter_map(|(i, range)| {
+            let from = range.first.max(agreements_from.unwrap_or(range.first));
+            let to = ranges.get(i + 1).map(|next| next.first);
+            let to = match (to, agreements_to) {
+                (Some(to), Some(agreements_to)) => Some(to.min(agreements_to)),
+                (to, agreements_to) => to.or(agreements_to),
+            };
+            if to.is_some_and(|to| to <= from) {
+                None
+            } else {
+                Some(ActiveMeter {
+                    serial_number: range.serial_number.clone(),
+                    from,
+                    to,
+                })
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::results::account::Register;
+
+    fn range(serial_number: &str, first: &str, last: &str) -> DataRange {
+        DataRange {
+            serial_number: serial_number.to_owned(),
+            first: first.parse().unwrap(),
+            last: last.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn candidates_with_registers() {
+        let meters = vec![
+            Meter {
+                serial_number: "OLD".to_owned(),
+                registers: vec![],
+            },
+            Meter {
+                serial_number: "NEW".to_owned(),
+                registers: vec![Register {
+                    identifier: "1".to_owned(),
+                    rate: "STANDARD".to_owned(),
+                    is_settlement_register: true,
+                }],
+            },
+        ];
+        assert_eq!(candidate_meters(&meters), vec![&meters[1]]);
+        assert_eq!(candidate_meters(&meters[..1]), vec![&meters[0]]);
+    }
+
+    #[test]
+    fn replaced_meter() {
+        let ranges = vec![
+            range("NEW", "2023-06-01T00:00:00Z", "2024-01-01T00:00:00Z"),
+            range("OLD", "2020-01-01T00:00:00Z", "2023-06-02T00:00:00Z"),
+        ];
+        assert_eq!(
+            active_meters(&ranges, &[]),
+            vec![
+                ActiveMeter {
+                    serial_number: "OLD".to_owned(),
+                    from: "2020-01-01T00:00:00Z".parse().unwrap(),
+                    to: Some("2023-06-01T00:00:00Z".parse().unwrap()),
+                },
+                ActiveMeter {
+                    serial_number: "NEW".to_owned(),
+                    from: "2023-06-01T00:00:00Z".parse().unwrap(),
+                    to: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_serials() {
+        let ranges = vec![
+            range("A", "2023-01-01T00:00:00Z", "2024-01-01T00:00:00Z"),
+            range("B", "2023-01-01T00:00:00Z", "2023-12-01T00:00:00Z"),
+        ];
+        assert_eq!(
+            active_meters(&ranges, &[]),
+            vec![ActiveMeter {
+                serial_number: "A".to_owned(),
+                from: "2023-01-01T00:00:00Z".parse().unwrap(),
+                to: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn restricted_to_agreements() {
+        let ranges = vec![range("A", "2020-01-01T00:00:00Z", "2024-01-01T00:00:00Z")];
+        let agreements = vec![Agreement {
+            tariff_code: "E-1R-VAR-22-11-01-C".to_owned(),
+            valid_from: "2022-01-01T00:00:00Z".parse().unwrap(),
+            valid_to: Some("2023-01-01T00:00:00Z".parse().unwrap()),
+        }];
+        let active = active_meters(&ranges, &agreements);
+        assert_eq!(
+            active,
+            vec![ActiveMeter {
+                serial_number: "A".to_owned(),
+                from: "2022-01-01T00:00:00Z".parse().unwrap(),
+                to: Some("2023-01-01T00:00:00Z".parse().unwrap()),
+            }]
+        );
+        assert!(active[0].is_active_at("2022-06-01T00:00:00Z".parse().unwrap()));
+        assert!(!active[0].is_active_at("2023-01-01T00:00:00Z".parse().unwrap()));
+    }
+}
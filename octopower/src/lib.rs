@@ -18,20 +18,55 @@
 //! # Ok(()) }
 //! ```
 
+pub mod active;
 pub mod results;
+pub mod tariff;
 
+use active::{ActiveMeter, DataRange, active_meters, candidate_meters};
+use chrono::{DateTime, Utc};
 use graphql_client::{GraphQLQuery, Response};
 use reqwest::{Client, StatusCode, Url};
-use results::{account::Account, consumption::Readings};
+use results::{
+    account::{Account, Agreement, Meter},
+    consumption::Readings,
+    dispatches::{Dispatch, Dispatches, parse_dispatch_time},
+    rates::{Rate, Rates},
+};
 use std::fmt::{self, Display, Formatter};
+use tariff::{RateType, TariffCode};
 use thiserror::Error;
 use url::ParseError;
 
+/// The maximum number of rates which the API allows to be fetched in a single page.
+const RATES_PAGE_SIZE: usize = 1500;
+
 /// A JWT token used for authenticated API requests.
 ///
-/// This can be obtained by calling [`authenticate`].
+/// This can be obtained by calling [`authenticate`], [`authenticate_with_api_key`] or
+/// [`authenticate_with_refresh_token`].
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub struct AuthToken(String);
+pub struct AuthToken {
+    token: String,
+    expires_at: Option<DateTime<Utc>>,
+    refresh_token: Option<String>,
+}
+
+impl AuthToken {
+    /// Returns the time at which this token expires, if known.
+    ///
+    /// After this time a new token must be obtained to make further authenticated requests.
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.expires_at
+    }
+
+    /// Returns the refresh token which was issued along with this token, if any.
+    ///
+    /// This can be passed to [`authenticate_with_refresh_token`] to obtain a new token once this
+    /// one expires, without needing the original credentials.
+    pub fn refresh_token(&self) -> Option<&str> {
+        self.refresh_token.as_deref()
+    }
+}
 
 /// An error communicating with the Octopus API.
 #[derive(Debug, Error)]
@@ -48,17 +83,49 @@ pub enum ApiError {
     /// There was an error parsing a URL from a string.
     #[error("Error parsing URL: {0}")]
     UrlParseError(#[from] ParseError),
+    /// A date or time returned by the API couldn't be parsed.
+    #[error("Error parsing date/time: {0}")]
+    DateTimeParseError(#[from] chrono::ParseError),
 }
 
 /// Authenticate to the Octopus API using the given email address and password, returning an
 /// authentication token which can be used for subsequent authenticated requests.
 pub async fn authenticate(email: &str, password: &str) -> Result<AuthToken, ApiError> {
+    obtain_token(authenticate_query::ObtainJSONWebTokenInput {
+        email: Some(email.to_owned()),
+        password: Some(password.to_owned()),
+        ..Default::default()
+    })
+    .await
+}
+
+/// Authenticate to the Octopus API using the given API key, returning an authentication token which
+/// can be used for subsequent authenticated requests.
+///
+/// API keys can be generated from the personal details page of the Octopus Energy website.
+pub async fn authenticate_with_api_key(api_key: &str) -> Result<AuthToken, ApiError> {
+    obtain_token(authenticate_query::ObtainJSONWebTokenInput {
+        api_key: Some(api_key.to_owned()),
+        ..Default::default()
+    })
+    .await
+}
+
+/// Authenticate to the Octopus API using a refresh token previously returned by
+/// [`AuthToken::refresh_token`], returning a new authentication token.
+pub async fn authenticate_with_refresh_token(refresh_token: &str) -> Result<AuthToken, ApiError> {
+    obtain_token(authenticate_query::ObtainJSONWebTokenInput {
+        refresh_token: Some(refresh_token.to_owned()),
+        ..Default::default()
+    })
+    .await
+}
+
+async fn obtain_token(
+    input: authenticate_query::ObtainJSONWebTokenInput,
+) -> Result<AuthToken, ApiError> {
     let client = Client::new();
-    let variables = authenticate_query::Variables {
-        email: email.to_owned(),
-        password: password.to_owned(),
-    };
-    let query = AuthenticateQuery::build_query(variables);
+    let query = AuthenticateQuery::build_query(authenticate_query::Variables { input });
     let response: Response<authenticate_query::ResponseData> = client
         .post("https://api.octopus.energy/v1/graphql/")
         .json(&query)
@@ -70,26 +137,137 @@ pub async fn authenticate(email: &str, password: &str) -> Result<AuthToken, ApiE
         obtain_kraken_token: Some(token),
     }) = response.data
     {
-        Ok(AuthToken(token.token))
+        let expires_at = token.payload["exp"]
+            .as_i64()
+            .and_then(|exp| DateTime::from_timestamp(exp, 0));
+        Ok(AuthToken {
+            token: token.token,
+            expires_at,
+            refresh_token: token.refresh_token,
+        })
     } else {
         Err(ApiError::GraphQlErrors(response.errors))
     }
 }
 
+/// An arbitrary JSON value, used for the payload of a JWT.
+type GenericScalar = serde_json::Value;
+
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "graphql/schema.graphql",
-    query_path = "graphql/authenticate.graphql"
+    query_path = "graphql/authenticate.graphql",
+    variables_derives = "Default"
 )]
 struct AuthenticateQuery;
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/dispatches.graphql"
+)]
+struct DispatchesQuery;
+
+/// Fetch the planned and completed Intelligent Octopus smart charging dispatches of the given
+/// account.
+///
+/// Accounts which aren't on an Intelligent Octopus tariff have no dispatches, or may return an
+/// error. Dispatches with no start or end time are skipped.
+pub async fn get_dispatches(
+    auth_token: &AuthToken,
+    account_id: &str,
+) -> Result<Dispatches, ApiError> {
+    let client = Client::new();
+    let query = DispatchesQuery::build_query(dispatches_query::Variables {
+        account_number: account_id.to_owned(),
+    });
+    let response: Response<dispatches_query::ResponseData> = client
+        .post("https://api.octopus.energy/v1/graphql/")
+        .header("Authorization", &auth_token.token)
+        .json(&query)
+        .send()
+        .await?
+        .json()
+        .await?;
+    match response.data {
+        Some(data) if response.errors.as_ref().is_none_or(Vec::is_empty) => Ok(Dispatches {
+            planned: convert_dispatches(data.planned_dispatches)?,
+            completed: convert_dispatches(data.completed_dispatches)?,
+        }),
+        _ => Err(ApiError::GraphQlErrors(response.errors)),
+    }
+}
+
+/// Converts dispatches returned by the GraphQL API, skipping any without a start or end time.
+fn convert_dispatches(
+    dispatches: Option<Vec<Option<dispatches_query::DispatchFields>>>,
+) -> Result<Vec<Dispatch>, ApiError> {
+    let mut converted = Vec::new();
+    for dispatch in dispatches.into_iter().flatten().flatten() {
+        let (Some(start), Some(end)) = (dispatch.start_dt, dispatch.end_dt) else {
+            continue;
+        };
+        let (source, location) = dispatch
+            .meta
+            .map(|meta| (meta.source, meta.location))
+            .unwrap_or_default();
+        converted.push(Dispatch {
+            start: parse_dispatch_time(&start)?,
+            end: parse_dispatch_time(&end)?,
+            delta_kwh: dispatch.delta_kwh.map(|delta_kwh| delta_kwh as f64),
+            source,
+            location,
+        });
+    }
+    Ok(converted)
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/balance.graphql"
+)]
+struct BalanceQuery;
+
+/// Fetch the current balance of the given account, in pence.
+///
+/// A positive balance means that the account is in credit.
+pub async fn get_account_balance(
+    auth_token: &AuthToken,
+    account_id: &str,
+) -> Result<i64, ApiError> {
+    let client = Client::new();
+    let query = BalanceQuery::build_query(balance_query::Variables {
+        account_number: account_id.to_owned(),
+    });
+    let response: Response<balance_query::ResponseData> = client
+        .post("https://api.octopus.energy/v1/graphql/")
+        .header("Authorization", &auth_token.token)
+        .json(&query)
+        .send()
+        .await?
+        .json()
+        .await?;
+    if let Some(balance_query::ResponseData {
+        account:
+            Some(balance_query::BalanceQueryAccount {
+                balance: Some(balance),
+            }),
+    }) = response.data
+    {
+        Ok(balance)
+    } else {
+        Err(ApiError::GraphQlErrors(response.errors))
+    }
+}
+
 /// Fetch information about the given account from the Octopus REST API.
 pub async fn get_account(auth_token: &AuthToken, account_id: &str) -> Result<Account, ApiError> {
     let client = Client::new();
     let url = format!("https://api.octopus.energy/v1/accounts/{account_id}/");
     let response = client
         .get(url)
-        .header("Authorization", &auth_token.0)
+        .header("Authorization", &auth_token.token)
         .send()
         .await?;
 
@@ -103,7 +281,7 @@ pub async fn get_account(auth_token: &AuthToken, account_id: &str) -> Result<Acc
 }
 
 /// The type of meter, either electricity or gas.
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum MeterType {
     Electricity,
     Gas,
@@ -155,6 +333,39 @@ impl Display for Grouping {
     }
 }
 
+/// The order in which to return consumption records.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Order {
+    /// The most recent records come first. This is the default.
+    #[default]
+    NewestFirst,
+    /// The oldest records come first.
+    OldestFirst,
+}
+
+impl Order {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::NewestFirst => "-period",
+            Self::OldestFirst => "period",
+        }
+    }
+}
+
+/// Options to restrict and group the consumption records returned by
+/// [`get_consumption_with_options`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ConsumptionOptions {
+    /// If set, only records starting at or after this time will be returned.
+    pub period_from: Option<DateTime<Utc>>,
+    /// If set, only records ending at or before this time will be returned.
+    pub period_to: Option<DateTime<Utc>>,
+    /// The level of aggregation, or `None` for raw half-hourly records.
+    pub grouping: Option<Grouping>,
+    /// The order in which to return records.
+    pub order: Order,
+}
+
 /// Fetch electricity or gas consumption records from the meter with the given `mpxn` (MPAN or MPRN)
 /// and serial.
 ///
@@ -174,6 +385,29 @@ pub async fn get_consumption(
     page: u32,
     page_size: usize,
     grouping: Option<Grouping>,
+) -> Result<Readings, ApiError> {
+    let options = ConsumptionOptions {
+        grouping,
+        ..Default::default()
+    };
+    get_consumption_with_options(
+        auth_token, meter_type, mpxn, serial, page, page_size, &options,
+    )
+    .await
+}
+
+/// Fetch electricity or gas consumption records from the meter with the given `mpxn` (MPAN or MPRN)
+/// and serial, restricted to a time period and ordered according to the given `options`.
+///
+/// As with [`get_consumption`], records are fetched in pages of `page_size` starting from page 0.
+pub async fn get_consumption_with_options(
+    auth_token: &AuthToken,
+    meter_type: MeterType,
+    mpxn: &str,
+    serial: &str,
+    page: u32,
+    page_size: usize,
+    options: &ConsumptionOptions,
 ) -> Result<Readings, ApiError> {
     let client = Client::new();
     let mut url = Url::parse(&format!(
@@ -184,13 +418,24 @@ pub async fn get_consumption(
         page + 1,
         page_size,
     ))?;
-    if let Some(grouping) = grouping {
-        url.query_pairs_mut()
-            .append_pair("group_by", grouping.as_str());
+    {
+        let mut query = url.query_pairs_mut();
+        if let Some(period_from) = options.period_from {
+            query.append_pair("period_from", &period_from.to_rfc3339());
+        }
+        if let Some(period_to) = options.period_to {
+            query.append_pair("period_to", &period_to.to_rfc3339());
+        }
+        if let Some(grouping) = options.grouping {
+            query.append_pair("group_by", grouping.as_str());
+        }
+        if options.order != Order::default() {
+            query.append_pair("order_by", options.order.as_str());
+        }
     }
     let response = client
         .get(url)
-        .header("Authorization", &auth_token.0)
+        .header("Authorization", &auth_token.token)
         .send()
         .await?;
 
@@ -202,3 +447,108 @@ pub async fn get_consumption(
         Err(ApiError::RestError { status, body })
     }
 }
+
+/// Work out which of the given meters of the meter point with the given `mpxn` was recording
+/// consumption at each time.
+///
+/// This fetches the earliest and latest consumption record of each meter which might be active,
+/// and ignores any which don't return any records or aren't found. See [`active::active_meters`]
+/// for how the periods are then worked out.
+pub async fn get_active_meters(
+    auth_token: &AuthToken,
+    meter_type: MeterType,
+    mpxn: &str,
+    meters: &[Meter],
+    agreements: &[Agreement],
+) -> Result<Vec<ActiveMeter>, ApiError> {
+    let mut ranges = Vec::new();
+    for meter in candidate_meters(meters) {
+        let mut options = ConsumptionOptions {
+            order: Order::OldestFirst,
+            ..Default::default()
+        };
+        let oldest = match get_consumption_with_options(
+            auth_token,
+            meter_type,
+            mpxn,
+            &meter.serial_number,
+            0,
+            1,
+            &options,
+        )
+        .await
+        {
+            Ok(oldest) => oldest,
+            // Old meters may not be found at all.
+            Err(ApiError::RestError { status, .. }) if status == StatusCode::NOT_FOUND => continue,
+            Err(e) => return Err(e),
+        };
+        let Some(first) = oldest.results.first() else {
+            continue;
+        };
+        options.order = Order::NewestFirst;
+        let newest = get_consumption_with_options(
+            auth_token,
+            meter_type,
+            mpxn,
+            &meter.serial_number,
+            0,
+            1,
+            &options,
+        )
+        .await?;
+        let last = newest.results.first().unwrap_or(first);
+        ranges.push(DataRange {
+            serial_number: meter.serial_number.clone(),
+            first: first.interval_start,
+            last: last.interval_end,
+        });
+    }
+    Ok(active_meters(&ranges, agreements))
+}
+
+/// Fetch all unit rates or standing charges of the given type for the given tariff which apply at
+/// some point between `period_from` and `period_to`.
+///
+/// Prices are public information, so no authentication is needed. All pages of results are fetched
+/// and combined, most recent first.
+pub async fn get_tariff_rates(
+    tariff_code: &TariffCode,
+    rate_type: RateType,
+    period_from: Option<DateTime<Utc>>,
+    period_to: Option<DateTime<Utc>>,
+) -> Result<Vec<Rate>, ApiError> {
+    let client = Client::new();
+    let mut url = Url::parse(&format!(
+        "https://api.octopus.energy/v1/products/{}/{}-tariffs/{}/{}/?page_size={}",
+        tariff_code.product_code,
+        tariff_code.meter_type,
+        tariff_code,
+        rate_type.path_component(),
+        RATES_PAGE_SIZE,
+    ))?;
+    {
+        let mut query = url.query_pairs_mut();
+        if let Some(period_from) = period_from {
+            query.append_pair("period_from", &period_from.to_rfc3339());
+        }
+        if let Some(period_to) = period_to {
+            query.append_pair("period_to", &period_to.to_rfc3339());
+        }
+    }
+
+    let mut rates = Vec::new();
+    let mut next = Some(url);
+    while let Some(url) = next {
+        let response = client.get(url).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await?;
+            return Err(ApiError::RestError { status, body });
+        }
+        let page: Rates = response.json().await?;
+        rates.extend(page.results);
+        next = page.next.as_deref().map(Url::parse).transpose()?;
+    }
+    Ok(rates)
+}
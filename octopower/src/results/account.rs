@@ -4,11 +4,11 @@
 
 //! Types used for account information, as returned by [`get_account`](crate::get_account).
 
-use chrono::{DateTime, FixedOffset};
-use serde::Deserialize;
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::{Deserialize, Serialize};
 
 /// Information about an Octopus account.
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Account {
     /// The account number. This is usually of the form `"A-1234ABCD"`.
     pub number: String,
@@ -17,7 +17,7 @@ pub struct Account {
 }
 
 /// Information about a particular property within an Octopus account.
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Property {
     pub id: u32,
     pub moved_in_at: DateTime<FixedOffset>,
@@ -34,7 +34,7 @@ pub struct Property {
 
 /// Information about a particular electricity meter point at a property. This may include several
 /// different meters.
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct ElectricityMeterPoint {
     pub mpan: String,
     pub profile_class: u32,
@@ -45,9 +45,16 @@ pub struct ElectricityMeterPoint {
     pub is_export: bool,
 }
 
+impl ElectricityMeterPoint {
+    /// Returns the agreement which applies to this meter point at the given time, if any.
+    pub fn agreement_at(&self, time: DateTime<Utc>) -> Option<&Agreement> {
+        agreement_at(&self.agreements, time)
+    }
+}
+
 /// Information about a particular gas meter point at a property. This may include several different
 /// meters.
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct GasMeterPoint {
     pub mprn: String,
     pub consumption_standard: u32,
@@ -56,24 +63,81 @@ pub struct GasMeterPoint {
     pub agreements: Vec<Agreement>,
 }
 
+impl GasMeterPoint {
+    /// Returns the agreement which applies to this meter point at the given time, if any.
+    pub fn agreement_at(&self, time: DateTime<Utc>) -> Option<&Agreement> {
+        agreement_at(&self.agreements, time)
+    }
+}
+
 /// Information about a single electricity or gas meter at a property.
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Meter {
     pub serial_number: String,
     #[serde(default)]
     pub registers: Vec<Register>,
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Register {
     pub identifier: String,
     pub rate: String,
     pub is_settlement_register: bool,
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Agreement {
     pub tariff_code: String,
     pub valid_from: DateTime<FixedOffset>,
     pub valid_to: Option<DateTime<FixedOffset>>,
 }
+
+impl Agreement {
+    /// Returns whether this agreement applies at the given time.
+    pub fn is_valid_at(&self, time: DateTime<Utc>) -> bool {
+        self.valid_from <= time && self.valid_to.is_none_or(|valid_to| time < valid_to)
+    }
+}
+
+/// Returns the agreement from the given list which applies at the given time, if any.
+pub fn agreement_at(agreements: &[Agreement], time: DateTime<Utc>) -> Option<&Agreement> {
+    agreements
+        .iter()
+        .find(|agreement| agreement.is_valid_at(time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_agreement() {
+        let agreements = serde_json::from_str::<Vec<Agreement>>(
+            r#"[
+                {
+                    "tariff_code": "E-1R-VAR-21-09-29-C",
+                    "valid_from": "2022-01-01T00:00:00Z",
+                    "valid_to": "2023-01-01T00:00:00Z"
+                },
+                {
+                    "tariff_code": "E-1R-AGILE-FLEX-22-11-25-C",
+                    "valid_from": "2023-01-01T00:00:00Z",
+                    "valid_to": null
+                }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            agreement_at(&agreements, "2021-12-31T23:59:59Z".parse().unwrap()),
+            None
+        );
+        assert_eq!(
+            agreement_at(&agreements, "2022-06-01T00:00:00Z".parse().unwrap()),
+            Some(&agreements[0])
+        );
+        assert_eq!(
+            agreement_at(&agreements, "2023-01-01T00:00:00Z".parse().unwrap()),
+            Some(&agreements[1])
+        );
+    }
+}
@@ -6,10 +6,10 @@
 //! [`get_consumption`](crate::get_consumption).
 
 use chrono::{DateTime, Utc};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 /// A list of electricity or gas meter readings.
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Readings {
     /// The total number of readings available.
     pub count: usize,
@@ -21,7 +21,7 @@ pub struct Readings {
 
 /// A single consumption record from an electricity or gas meter. This may be either for a single
 /// half hour or a longer grouping.
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Consumption {
     /// The amount of energy consumed in this time period. For electrity meters and SMETS1 gas
     /// meters this in in kWh; for SMETS2 gas meters it is m^3.
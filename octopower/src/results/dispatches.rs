@@ -0,0 +1,66 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Types used for Intelligent Octopus smart charging dispatches, as returned by
+//! [`get_dispatches`](crate::get_dispatches).
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// The planned and completed smart charging dispatches of an account.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Dispatches {
+    /// Dispatches which are planned for the future, or currently in progress.
+    pub planned: Vec<Dispatch>,
+    /// Dispatches which have finished.
+    pub completed: Vec<Dispatch>,
+}
+
+/// A period during which a device such as an electric vehicle charger was told to charge, and
+/// consumption was charged at the off-peak rate.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Dispatch {
+    /// The start of the dispatch.
+    pub start: DateTime<Utc>,
+    /// The end of the dispatch.
+    pub end: DateTime<Utc>,
+    /// The change in energy consumption expected or achieved by the dispatch, in kWh, if known.
+    /// This is usually negative, as the device is drawing energy from the grid.
+    pub delta_kwh: Option<f64>,
+    /// What caused the dispatch, such as `smart-charge` or `bump-charge`.
+    pub source: Option<String>,
+    /// Where the dispatch happened, if known.
+    pub location: Option<String>,
+}
+
+impl Dispatch {
+    /// Returns whether the dispatch is in progress at the given time.
+    pub fn is_active_at(&self, time: DateTime<Utc>) -> bool {
+        self.start <= time && time < self.end
+    }
+}
+
+/// Parses a date and time in the format returned for dispatches, which is RFC 3339 but may use a
+/// space rather than `T` as the separator.
+pub(crate) fn parse_dispatch_time(time: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    Ok(time.parse::<DateTime<chrono::FixedOffset>>()?.to_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_time() {
+        assert_eq!(
+            parse_dispatch_time("2024-01-01 23:30:00+00:00").unwrap(),
+            "2024-01-01T23:30:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        assert_eq!(
+            parse_dispatch_time("2024-06-01T01:00:00+01:00").unwrap(),
+            "2024-06-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        assert!(parse_dispatch_time("tomorrow").is_err());
+    }
+}
@@ -0,0 +1,91 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Types used for tariff prices, as returned by [`get_tariff_rates`](crate::get_tariff_rates).
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A page of unit rates or standing charges.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct Rates {
+    pub next: Option<String>,
+    pub results: Vec<Rate>,
+}
+
+/// A unit rate or standing charge which applies for some period of time.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Rate {
+    /// The price in pence excluding VAT. This is per kWh for unit rates, or per day for standing
+    /// charges.
+    pub value_exc_vat: f64,
+    /// The price in pence including VAT. This is per kWh for unit rates, or per day for standing
+    /// charges.
+    pub value_inc_vat: f64,
+    /// The time from which this rate applies.
+    pub valid_from: DateTime<Utc>,
+    /// The time until which this rate applies, or `None` if it applies indefinitely.
+    pub valid_to: Option<DateTime<Utc>>,
+    /// The payment method to which this rate applies, if it is specific to one.
+    pub payment_method: Option<String>,
+}
+
+impl Rate {
+    /// Returns whether this rate applies at the given time.
+    pub fn applies_at(&self, time: DateTime<Utc>) -> bool {
+        self.valid_from <= time && self.valid_to.is_none_or(|valid_to| time < valid_to)
+    }
+}
+
+/// Returns the first of the given rates which applies at the given time, if any.
+pub fn rate_at(rates: &[Rate], time: DateTime<Utc>) -> Option<&Rate> {
+    rates.iter().find(|rate| rate.applies_at(time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_rates() {
+        let rates = serde_json::from_str::<Rates>(
+            r#"{
+                "count": 2,
+                "next": null,
+                "previous": null,
+                "results": [
+                    {
+                        "value_exc_vat": 23.3,
+                        "value_inc_vat": 24.465,
+                        "valid_from": "2023-03-26T01:30:00Z",
+                        "valid_to": null,
+                        "payment_method": null
+                    },
+                    {
+                        "value_exc_vat": 20.0,
+                        "value_inc_vat": 21.0,
+                        "valid_from": "2023-03-26T01:00:00Z",
+                        "valid_to": "2023-03-26T01:30:00Z",
+                        "payment_method": "DIRECT_DEBIT"
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(rates.next, None);
+        assert_eq!(rates.results.len(), 2);
+        assert_eq!(rates.results[1].value_inc_vat, 21.0);
+        assert_eq!(
+            rates.results[1].payment_method.as_deref(),
+            Some("DIRECT_DEBIT")
+        );
+
+        let before = "2023-03-26T00:59:59Z".parse().unwrap();
+        let first = "2023-03-26T01:00:00Z".parse().unwrap();
+        let second = "2023-03-26T01:30:00Z".parse().unwrap();
+        assert_eq!(rate_at(&rates.results, before), None);
+        assert_eq!(rate_at(&rates.results, first), Some(&rates.results[1]));
+        assert_eq!(rate_at(&rates.results, second), Some(&rates.results[0]));
+    }
+}
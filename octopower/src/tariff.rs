@@ -0,0 +1,145 @@
+// Copyright 2026 the octopower authors.
+// This project is dual-licensed under Apache 2.0 and MIT terms.
+// See LICENSE-APACHE and LICENSE-MIT for details.
+
+//! Types for identifying tariffs and their prices.
+
+use crate::MeterType;
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+use thiserror::Error;
+
+/// A tariff code, such as `"E-1R-AGILE-FLEX-22-11-25-C"`.
+///
+/// This identifies the fuel, the number of registers, the product and the region of a tariff, as
+/// found in [`Agreement::tariff_code`](crate::results::account::Agreement::tariff_code).
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct TariffCode {
+    /// Whether this is an electricity or gas tariff.
+    pub meter_type: MeterType,
+    /// The number of registers (i.e. separately priced time periods) of the tariff. This is 1 for
+    /// most tariffs, or 2 for Economy 7 style tariffs with day and night rates.
+    pub registers: u8,
+    /// The code of the product to which the tariff belongs, such as `"AGILE-FLEX-22-11-25"`.
+    pub product_code: String,
+    /// The letter identifying the Grid Supply Point region for which the tariff applies.
+    pub region: char,
+}
+
+/// An error parsing a [`TariffCode`].
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+#[error("Invalid tariff code \"{0}\"")]
+pub struct ParseTariffCodeError(String);
+
+impl FromStr for TariffCode {
+    type Err = ParseTariffCodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseTariffCodeError(s.to_owned());
+        let mut parts = s.splitn(3, '-');
+        let meter_type = match parts.next() {
+            Some("E") => MeterType::Electricity,
+            Some("G") => MeterType::Gas,
+            _ => return Err(error()),
+        };
+        let registers = parts
+            .next()
+            .and_then(|registers| registers.strip_suffix('R'))
+            .and_then(|registers| registers.parse().ok())
+            .ok_or_else(error)?;
+        let (product_code, region) = parts
+            .next()
+            .and_then(|rest| rest.rsplit_once('-'))
+            .ok_or_else(error)?;
+        let mut region_chars = region.chars();
+        let (Some(region), None) = (region_chars.next(), region_chars.next()) else {
+            return Err(error());
+        };
+        if product_code.is_empty() || !region.is_ascii_uppercase() {
+            return Err(error());
+        }
+        Ok(Self {
+            meter_type,
+            registers,
+            product_code: product_code.to_owned(),
+            region,
+        })
+    }
+}
+
+impl Display for TariffCode {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let fuel = match self.meter_type {
+            MeterType::Electricity => "E",
+            MeterType::Gas => "G",
+        };
+        write!(
+            f,
+            "{}-{}R-{}-{}",
+            fuel, self.registers, self.product_code, self.region
+        )
+    }
+}
+
+/// The type of price to fetch for a tariff with [`get_tariff_rates`](crate::get_tariff_rates).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum RateType {
+    /// The price per kWh for single register tariffs.
+    StandardUnit,
+    /// The price per kWh during the day for two register tariffs.
+    DayUnit,
+    /// The price per kWh during the night for two register tariffs.
+    NightUnit,
+    /// The fixed price per day.
+    StandingCharge,
+}
+
+impl RateType {
+    pub(crate) fn path_component(self) -> &'static str {
+        match self {
+            Self::StandardUnit => "standard-unit-rates",
+            Self::DayUnit => "day-unit-rates",
+            Self::NightUnit => "night-unit-rates",
+            Self::StandingCharge => "standing-charges",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_electricity() {
+        let tariff_code: TariffCode = "E-1R-AGILE-FLEX-22-11-25-C".parse().unwrap();
+        assert_eq!(
+            tariff_code,
+            TariffCode {
+                meter_type: MeterType::Electricity,
+                registers: 1,
+                product_code: "AGILE-FLEX-22-11-25".to_owned(),
+                region: 'C',
+            }
+        );
+        assert_eq!(tariff_code.to_string(), "E-1R-AGILE-FLEX-22-11-25-C");
+    }
+
+    #[test]
+    fn parse_gas() {
+        let tariff_code: TariffCode = "G-1R-VAR-22-11-01-A".parse().unwrap();
+        assert_eq!(tariff_code.meter_type, MeterType::Gas);
+        assert_eq!(tariff_code.product_code, "VAR-22-11-01");
+        assert_eq!(tariff_code.region, 'A');
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!("".parse::<TariffCode>().is_err());
+        assert!("X-1R-VAR-22-11-01-A".parse::<TariffCode>().is_err());
+        assert!("E-R-VAR-22-11-01-A".parse::<TariffCode>().is_err());
+        assert!("E-1R-A".parse::<TariffCode>().is_err());
+        assert!("E-1R-VAR-22-11-01-AB".parse::<TariffCode>().is_err());
+    }
+}